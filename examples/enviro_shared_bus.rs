@@ -0,0 +1,36 @@
+//! The LTR-559 sharing the I²C bus with the BME280 on the Pimoroni Enviro board.
+//!
+//! Run with `cargo run --example enviro_shared_bus --features eh1`.
+use core::cell::RefCell;
+use embedded_hal_1::i2c::I2c;
+use embedded_hal_bus::i2c::RefCellDevice;
+use linux_embedded_hal_1::I2cdev;
+use ltr_559::{AlsGain, AlsIntTime, AlsMeasRate, Eh1, Ltr559, SlaveAddr};
+
+const BME280_ADDRESS: u8 = 0x76;
+const BME280_CHIP_ID_REGISTER: u8 = 0xD0;
+
+fn main() {
+    let bus = RefCell::new(I2cdev::new("/dev/i2c-1").unwrap());
+    let mut bme280 = RefCellDevice::new(&bus);
+    let mut sensor = Ltr559::new_device(Eh1(RefCellDevice::new(&bus)), SlaveAddr::default());
+
+    let mut chip_id = [0];
+    bme280
+        .write_read(BME280_ADDRESS, &[BME280_CHIP_ID_REGISTER], &mut chip_id)
+        .unwrap();
+    println!("BME280 chip ID = 0x{:02x}", chip_id[0]);
+    let part_id = sensor.get_part_id().unwrap();
+    println!("LTR-559 part ID = 0x{:02x}", part_id);
+
+    sensor
+        .set_als_meas_rate(AlsIntTime::_50ms, AlsMeasRate::_50ms)
+        .unwrap();
+    sensor.set_als_contr(AlsGain::Gain4x, false, true).unwrap();
+    loop {
+        let status = sensor.get_status().unwrap();
+        if status.als_data_valid {
+            println!("Lux = {}", sensor.get_lux().unwrap());
+        }
+    }
+}
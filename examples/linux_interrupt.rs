@@ -0,0 +1,30 @@
+extern crate linux_embedded_hal as hal;
+extern crate ltr_559;
+use ltr_559::linux::InterruptLine;
+use ltr_559::{Event, InterruptMode, InterruptPinPolarity, Ltr559, PsMeasRate, SlaveAddr};
+
+// INT of the Pimoroni Enviro boards is wired to BCM 4
+const INT_LINE: u32 = 4;
+
+fn main() {
+    let dev = hal::I2cdev::new("/dev/i2c-1").unwrap();
+    let mut sensor = Ltr559::new_device(dev, SlaveAddr::default());
+    sensor.set_ps_meas_rate(PsMeasRate::_100ms).unwrap();
+    sensor.set_ps_contr(true, true).unwrap();
+    sensor.set_ps_thresholds(0, 400).unwrap();
+    sensor
+        .set_interrupt(InterruptPinPolarity::Low, InterruptMode::OnlyPS)
+        .unwrap();
+
+    let mut line =
+        InterruptLine::open("/dev/gpiochip0", INT_LINE, InterruptPinPolarity::Low).unwrap();
+    loop {
+        for event in sensor.wait_events(&mut line).unwrap() {
+            match event {
+                Event::PsNear(counts) => println!("Near ({} counts)", counts),
+                Event::PsFar(counts) => println!("Far ({} counts)", counts),
+                event => println!("{:?}", event),
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! ALS thresholds that adapt to the recent variability of the light
+//!
+//! Calling [`Ltr559::rearm_als_adaptive()`] after each ALS interrupt
+//! centers the threshold window on the current CH0 value, with a width
+//! following the smoothed deviation between successive readings. Under
+//! flickering or noisy light the window widens, so that the host is only
+//! woken up for changes that stand out from the usual fluctuation; under
+//! steady light it narrows again.
+//!
+//! ```
+//! use ltr_559::AdaptiveThresholds;
+//!
+//! // window between ±20 and ±5000 counts, 3 times the mean deviation
+//! let mut adaptive = AdaptiveThresholds::new(20, 5000, 3);
+//! assert_eq!(adaptive.update(1000), (980, 1020));
+//! assert_eq!(adaptive.update(1800), (1500, 2100));
+//! ```
+use crate::hal::blocking::i2c;
+use crate::{marker, Error, Ltr559};
+
+/// Deviation fraction bits
+const SHIFT: u32 = 4;
+/// Smoothing factor of the deviation, as a power of two (1/8)
+const SMOOTHING_SHIFT: u32 = 3;
+
+/// Computes ALS threshold windows from the recent CH0 deviation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveThresholds {
+    min_window: u16,
+    max_window: u16,
+    multiplier: u8,
+    deviation: u32,
+    last: Option<u16>,
+}
+
+impl AdaptiveThresholds {
+    /// Create a new controller.
+    ///
+    /// The half-width of the window is `multiplier` times the smoothed
+    /// absolute difference between successive CH0 readings, limited to
+    /// `min_window..=max_window` counts. `max_window` is raised to
+    /// `min_window` if it is lower.
+    pub fn new(min_window: u16, max_window: u16, multiplier: u8) -> Self {
+        AdaptiveThresholds {
+            min_window,
+            max_window: max_window.max(min_window),
+            multiplier,
+            deviation: 0,
+            last: None,
+        }
+    }
+
+    /// Feed a new CH0 reading and return the `(low, high)` thresholds to
+    /// program.
+    ///
+    /// The deviation is smoothed exponentially with a factor of 1/8. The
+    /// thresholds saturate at the register bounds.
+    pub fn update(&mut self, ch0: u16) -> (u16, u16) {
+        if let Some(last) = self.last {
+            let difference = u32::from(ch0.abs_diff(last)) << SHIFT;
+            if difference > self.deviation {
+                self.deviation += (difference - self.deviation) >> SMOOTHING_SHIFT;
+            } else {
+                self.deviation -= (self.deviation - difference) >> SMOOTHING_SHIFT;
+            }
+        }
+        self.last = Some(ch0);
+        let window = self.window();
+        (ch0.saturating_sub(window), ch0.saturating_add(window))
+    }
+
+    /// Current half-width of the window in counts
+    pub fn window(&self) -> u16 {
+        let window = (u32::from(self.multiplier) * self.deviation) >> SHIFT;
+        window
+            .max(u32::from(self.min_window))
+            .min(u32::from(self.max_window)) as u16
+    }
+
+    /// Forget the readings so far
+    pub fn reset(&mut self) {
+        self.deviation = 0;
+        self.last = None;
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E> + i2c::Write<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Read CH0, feed it to `adaptive` and program the resulting ALS
+    /// thresholds.
+    ///
+    /// Call this after each ALS interrupt. Returns the `(low, high)`
+    /// thresholds written.
+    pub fn rearm_als_adaptive(
+        &mut self,
+        adaptive: &mut AdaptiveThresholds,
+    ) -> Result<(u16, u16), Error<E>> {
+        let (ch0, _) = self.get_als_raw_data()?;
+        let (low, high) = adaptive.update(ch0);
+        self.set_als_thresholds(low, high)?;
+        Ok((low, high))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    #[test]
+    fn window_narrows_under_steady_light() {
+        let mut adaptive = AdaptiveThresholds::new(10, 1000, 4);
+        adaptive.update(500);
+        adaptive.update(900);
+        assert_eq!(adaptive.window(), 200);
+        for _ in 0..40 {
+            adaptive.update(900);
+        }
+        assert_eq!(adaptive.window(), 10);
+        adaptive.reset();
+        assert_eq!(adaptive.update(0), (0, 10));
+    }
+
+    #[test]
+    fn window_is_limited() {
+        let mut adaptive = AdaptiveThresholds::new(10, 100, 4);
+        adaptive.update(0);
+        assert_eq!(adaptive.update(0xFFFF), (0xFFFF - 100, 0xFFFF));
+    }
+
+    #[test]
+    fn rearm_programs_thresholds() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut adaptive = AdaptiveThresholds::new(50, 500, 2);
+        sensor.i2c.registers[0x8A..0x8C].copy_from_slice(&[0xE8, 0x03]);
+        assert_eq!(
+            sensor.rearm_als_adaptive(&mut adaptive).unwrap(),
+            (950, 1050)
+        );
+        let thresholds = sensor.get_thresholds().unwrap();
+        assert_eq!((thresholds.als_low, thresholds.als_high), (950, 1050));
+    }
+}
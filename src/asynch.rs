@@ -0,0 +1,463 @@
+//! Asynchronous driver based on [`embedded-hal-async`]
+//!
+//! Enabled with the `async` feature. The register methods are expanded from
+//! the same implementation as the blocking [`Ltr559`](crate::Ltr559) driver.
+//!
+//! The methods that wait for the device take a [`DelayNs`] provider. With
+//! the `embassy-time` feature, the driver can wait with
+//! [`embassy_time::Timer`] itself where the device needs time: after a
+//! software reset ([`sw_reset_timed()`](Ltr559Async::sw_reset_timed)), when
+//! waking the ALS up ([`wake_als()`](Ltr559Async::wake_als)) and while
+//! polling for new data
+//! ([`wait_for_als_data_timed()`](Ltr559Async::wait_for_als_data_timed),
+//! [`wait_for_ps_data_timed()`](Ltr559Async::wait_for_ps_data_timed) and
+//! `lux_stream_timed()`).
+//!
+//! [`embedded-hal-async`]: https://docs.rs/embedded-hal-async
+//! [`embassy_time::Timer`]: https://docs.rs/embassy-time
+use crate::calc::{LuxAlgorithm, LuxCoefficients};
+#[cfg(feature = "embassy-time")]
+use crate::device_impl::ALS_WAKEUP_TIME_MS;
+use crate::staging::RegisterImage;
+#[cfg(feature = "embassy-time")]
+use crate::PsData;
+use crate::{
+    AlsGain, AlsIntTime, AlsMeasRate, DiagnosticCounters, Error, InterruptCause,
+    InterruptPinPolarity, PsMeasRate, SlaveAddr, Status,
+};
+#[cfg(feature = "float")]
+use crate::{InterruptMode, Measurement, Thresholds};
+#[cfg(feature = "embassy-time")]
+use embassy_time::{Delay, Timer};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::I2c;
+#[cfg(feature = "float")]
+use futures_util::stream::{self, Stream};
+
+/// Asynchronous Ltr559 device driver
+#[derive(Debug)]
+pub struct Ltr559Async<I2C> {
+    i2c: I2C,
+    address: u8,
+    als_gain: AlsGain,
+    als_int: AlsIntTime,
+    als_meas_rate: AlsMeasRate,
+    ps_meas_rate: PsMeasRate,
+    interrupt_polarity: InterruptPinPolarity,
+    cache_dirty: bool,
+    staged: Option<RegisterImage>,
+    active_before_standby: Option<(bool, bool)>,
+    lux_coefficients: LuxCoefficients,
+    lux_algorithm: LuxAlgorithm,
+    held_als: (u16, u16),
+    dark_counts: (u16, u16),
+    #[cfg(feature = "float")]
+    window_factor: f32,
+    /// `window_factor` as a 16.16 fixed-point number for the integer variants
+    #[cfg(feature = "float")]
+    window_factor_q16: i32,
+    #[cfg(feature = "float")]
+    lux_offset: f32,
+    /// `lux_offset` as a 16.16 fixed-point number for the integer variants
+    #[cfg(feature = "float")]
+    lux_offset_q16: i32,
+    #[cfg(feature = "stats")]
+    lux_stats: crate::stats::RunningStats,
+    last_status: Option<Status>,
+    diagnostics: DiagnosticCounters,
+}
+
+impl<I2C> Ltr559Async<I2C> {
+    /// Create new instance of the device
+    pub fn new_device(i2c: I2C, address: SlaveAddr) -> Self {
+        Ltr559Async {
+            i2c,
+            address: address.addr(),
+            als_gain: AlsGain::default(),
+            als_int: AlsIntTime::default(),
+            als_meas_rate: AlsMeasRate::default(),
+            ps_meas_rate: PsMeasRate::default(),
+            interrupt_polarity: InterruptPinPolarity::default(),
+            cache_dirty: false,
+            staged: None,
+            active_before_standby: None,
+            lux_coefficients: LuxCoefficients::default(),
+            lux_algorithm: LuxAlgorithm::default(),
+            held_als: (0, 0),
+            dark_counts: (0, 0),
+            #[cfg(feature = "float")]
+            window_factor: 1.0,
+            #[cfg(feature = "float")]
+            window_factor_q16: 1 << 16,
+            #[cfg(feature = "float")]
+            lux_offset: 0.0,
+            #[cfg(feature = "float")]
+            lux_offset_q16: 0,
+            #[cfg(feature = "stats")]
+            lux_stats: crate::stats::RunningStats::new(),
+            last_status: None,
+            diagnostics: DiagnosticCounters::default(),
+        }
+    }
+
+    /// Destroy driver instance, return I²C bus instance.
+    pub fn destroy(self) -> I2C {
+        self.i2c
+    }
+
+    impl_staging_methods!();
+    impl_lux_settings!();
+    impl_cached_state_methods!();
+
+    /// Reset the internal state of this driver to the default values.
+    ///
+    /// *Note:* This does not alter the state or configuration of the device.
+    pub fn reset_internal_driver_state(&mut self) {
+        self.als_gain = AlsGain::default();
+        self.als_int = AlsIntTime::default();
+        self.als_meas_rate = AlsMeasRate::default();
+        self.ps_meas_rate = PsMeasRate::default();
+        self.interrupt_polarity = InterruptPinPolarity::default();
+        self.active_before_standby = None;
+        self.last_status = None;
+    }
+}
+
+impl<I2C, E> Ltr559Async<I2C>
+where
+    I2C: I2c<Error = E>,
+{
+    impl_read_methods!([async] [.await]);
+    impl_write_methods!([async] [.await]);
+    impl_data_methods!([async] [.await]);
+    impl_power_methods!([async] [.await] [DelayNs]);
+    impl_wait_methods!([async] [.await] [DelayNs]);
+    impl_threshold_methods!([async] [.await]);
+    impl_state_methods!([async] [.await]);
+    impl_calibration_methods!([async] [.await] [DelayNs]);
+
+    /// Return a stream of fresh lux measurements.
+    ///
+    /// The stream polls the status register ten times per measurement
+    /// period, waiting with `delay` in between, and yields a value each time
+    /// new valid ALS data is available, so every reading is yielded only
+    /// once. Bus errors are yielded as well and do not end the stream.
+    ///
+    /// Enable the ALS with the desired measurement rate before polling the
+    /// stream.
+    #[cfg(feature = "float")]
+    pub fn lux_stream<'a, D: DelayNs>(
+        &'a mut self,
+        delay: &'a mut D,
+    ) -> impl Stream<Item = Result<f32, Error<E>>> + 'a {
+        stream::unfold((self, delay), |(sensor, delay)| async move {
+            let result = sensor.next_fresh_lux(delay).await;
+            Some((result, (sensor, delay)))
+        })
+    }
+
+    /// Configure the interrupt and return a stream of measurements taken
+    /// each time the sensor asserts the interrupt pin.
+    ///
+    /// The ALS and PS thresholds are written first, then the interrupt is
+    /// enabled with `polarity` and `mode`. Each item holds the interrupt
+    /// cause and a measurement read right after the interrupt was cleared.
+    /// In between the stream only waits on `pin`, so with an
+    /// interrupt-capable pin the MCU can sleep until the sensor signals.
+    /// Bus and pin errors are yielded as well and do not end the stream.
+    #[cfg(feature = "float")]
+    pub async fn run_interrupt_driven<'a, P: Wait>(
+        &'a mut self,
+        pin: &'a mut P,
+        thresholds: Thresholds,
+        polarity: InterruptPinPolarity,
+        mode: InterruptMode,
+    ) -> Result<impl Stream<Item = Result<(InterruptCause, Measurement), Error<E>>> + 'a, Error<E>>
+    {
+        self.set_als_thresholds(thresholds.als_low, thresholds.als_high)
+            .await?;
+        self.set_ps_thresholds(thresholds.ps_low, thresholds.ps_high)
+            .await?;
+        self.set_interrupt(polarity, mode).await?;
+        Ok(stream::unfold((self, pin), |(sensor, pin)| async move {
+            let result = sensor.next_interrupt_measurement(pin).await;
+            Some((result, (sensor, pin)))
+        }))
+    }
+
+    #[cfg(feature = "float")]
+    async fn next_interrupt_measurement<P: Wait>(
+        &mut self,
+        pin: &mut P,
+    ) -> Result<(InterruptCause, Measurement), Error<E>> {
+        let cause = self.wait_for_interrupt_async(pin).await?;
+        Ok((cause, self.get_measurement().await?))
+    }
+
+    #[cfg(feature = "float")]
+    async fn next_fresh_lux<D: DelayNs>(&mut self, delay: &mut D) -> Result<f32, Error<E>> {
+        loop {
+            let status = self.get_status().await?;
+            if status.als_data_status && status.als_data_valid {
+                return self.get_lux().await;
+            }
+            delay.delay_ms(self.als_poll_interval_ms()).await;
+        }
+    }
+
+    /// Wait until the interrupt pin is asserted, then read and clear the
+    /// interrupt.
+    ///
+    /// The active level of `pin` is the polarity configured through
+    /// [`set_interrupt()`](#method.set_interrupt). Waiting for the level
+    /// rather than for an edge makes sure an interrupt that is already
+    /// pending is not missed. The interrupt is cleared by reading the status.
+    pub async fn wait_for_interrupt_async<P: Wait>(
+        &mut self,
+        pin: &mut P,
+    ) -> Result<InterruptCause, Error<E>> {
+        match self.interrupt_polarity {
+            InterruptPinPolarity::Low => pin.wait_for_low().await,
+            InterruptPinPolarity::High => pin.wait_for_high().await,
+        }
+        .map_err(|_| Error::Pin)?;
+        self.read_interrupt_cause().await
+    }
+
+    #[cfg(feature = "float")]
+    fn als_poll_interval_ms(&self) -> u32 {
+        (self.als_meas_rate.milliseconds() / 10).max(1)
+    }
+}
+
+#[cfg(feature = "embassy-time")]
+impl<I2C, E> Ltr559Async<I2C>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Perform a software reset as [`sw_reset()`](#method.sw_reset) does,
+    /// waiting for the device to start up again with a timer.
+    pub async fn sw_reset_timed(&mut self) -> Result<(), Error<E>> {
+        self.sw_reset(&mut Delay).await
+    }
+
+    /// Activate the ALS with `gain` and wait for its wakeup time.
+    pub async fn wake_als(&mut self, gain: AlsGain) -> Result<(), Error<E>> {
+        self.set_als_contr(gain, false, true).await?;
+        Timer::after_millis(u64::from(ALS_WAKEUP_TIME_MS)).await;
+        Ok(())
+    }
+
+    /// Wait for new valid ALS data as
+    /// [`wait_for_als_data()`](#method.wait_for_als_data) does, polling the
+    /// status with a timer.
+    pub async fn wait_for_als_data_timed(&mut self) -> Result<(), Error<E>> {
+        self.wait_for_als_data(&mut Delay).await
+    }
+
+    /// Wait for new PS data and return it as
+    /// [`wait_for_ps_data()`](#method.wait_for_ps_data) does, polling the
+    /// status with a timer.
+    pub async fn wait_for_ps_data_timed(&mut self, timeout_ms: u32) -> Result<PsData, Error<E>> {
+        self.wait_for_ps_data(&mut Delay, timeout_ms).await
+    }
+
+    /// Return a stream of fresh lux measurements as
+    /// [`lux_stream()`](#method.lux_stream) does, waiting between status
+    /// polls with a timer.
+    #[cfg(feature = "float")]
+    pub fn lux_stream_timed(&mut self) -> impl Stream<Item = Result<f32, Error<E>>> + '_ {
+        stream::unfold(self, |sensor| async move {
+            let result = sensor.next_fresh_lux(&mut Delay).await;
+            Some((result, sensor))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{block_on, DelayMock, I2cMock, PinMock};
+    use crate::InterruptMode;
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn async_lux_and_settings() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559Async::new_device(i2c, SlaveAddr::default());
+        block_on(sensor.set_als_contr(AlsGain::Gain2x, false, true)).unwrap();
+        block_on(sensor.set_ps_high_limit_raw(0x0456)).unwrap();
+        assert_eq!(block_on(sensor.get_lux()).unwrap(), 177.43 / 2.0);
+        assert!(block_on(sensor.set_ps_offset(1024)).is_err());
+        let regs = sensor.destroy().registers;
+        assert_eq!(regs[0x80], 0x05);
+        assert_eq!(regs[0x90], 0x56);
+        assert_eq!(regs[0x91], 0x04);
+    }
+
+    #[test]
+    fn async_wait_for_interrupt_reads_cause() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x02;
+        let mut sensor = Ltr559Async::new_device(i2c, SlaveAddr::default());
+        block_on(sensor.set_interrupt(InterruptPinPolarity::High, InterruptMode::Both)).unwrap();
+        let mut pin = PinMock::default();
+        let cause = block_on(sensor.wait_for_interrupt_async(&mut pin)).unwrap();
+        assert_eq!(
+            cause,
+            InterruptCause {
+                als: false,
+                ps: true
+            }
+        );
+        assert_eq!(pin.waited_for, Some(true));
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn lux_stream_yields_fresh_readings() {
+        use futures_util::StreamExt;
+
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x04;
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559Async::new_device(i2c, SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        let mut lux = core::pin::pin!(sensor.lux_stream(&mut delay));
+        assert_eq!(block_on(lux.next()).unwrap().unwrap(), 177.43);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn interrupt_driven_stream_measures_on_each_interrupt() {
+        use futures_util::StreamExt;
+
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x08;
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559Async::new_device(i2c, SlaveAddr::default());
+        let mut pin = PinMock::default();
+        let thresholds = Thresholds {
+            als_low: 10,
+            als_high: 1000,
+            ..Thresholds::default()
+        };
+        {
+            let measurements = block_on(sensor.run_interrupt_driven(
+                &mut pin,
+                thresholds,
+                InterruptPinPolarity::High,
+                InterruptMode::OnlyALS,
+            ))
+            .unwrap();
+            let mut measurements = core::pin::pin!(measurements);
+            let (cause, measurement) = block_on(measurements.next()).unwrap().unwrap();
+            assert!(cause.als && !cause.ps);
+            assert_eq!(measurement.lux, 177.43);
+        }
+        assert_eq!(pin.waited_for, Some(true));
+        let regs = sensor.destroy().registers;
+        assert_eq!(regs[0x97..0x9B], [0xE8, 0x03, 10, 0]);
+        assert_eq!(regs[0x8F], 0x06);
+    }
+
+    #[test]
+    fn sw_reset_resets_driver_state() {
+        let mut i2c = I2cMock::new();
+        i2c.reset_completes = true;
+        let mut sensor = Ltr559Async::new_device(i2c, SlaveAddr::default());
+        block_on(sensor.set_als_contr(AlsGain::Gain8x, false, true)).unwrap();
+        let mut delay = DelayMock::default();
+        block_on(sensor.sw_reset(&mut delay)).unwrap();
+        assert_eq!(sensor.als_gain, AlsGain::default());
+        assert_eq!(delay.elapsed_ms, 100);
+    }
+
+    #[test]
+    fn wait_for_als_data_times_out() {
+        let mut sensor = Ltr559Async::new_device(I2cMock::new(), SlaveAddr::default());
+        block_on(sensor.set_als_meas_rate(AlsIntTime::_50ms, AlsMeasRate::_50ms)).unwrap();
+        let mut delay = DelayMock::default();
+        assert!(matches!(
+            block_on(sensor.wait_for_als_data(&mut delay)),
+            Err(Error::Timeout)
+        ));
+        assert_eq!(delay.elapsed_ms, 150);
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn timed_waits_use_embassy_timer() {
+        use embassy_time::{Duration, Instant};
+
+        let mut i2c = I2cMock::new();
+        i2c.reset_completes = true;
+        let mut sensor = Ltr559Async::new_device(i2c, SlaveAddr::default());
+        block_on(sensor.set_als_contr(AlsGain::Gain8x, false, true)).unwrap();
+        let start = Instant::now();
+        block_on(sensor.sw_reset_timed()).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(sensor.als_gain, AlsGain::default());
+
+        let start = Instant::now();
+        block_on(sensor.wake_als(AlsGain::Gain4x)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(10));
+        assert_eq!(sensor.als_gain, AlsGain::Gain4x);
+        assert_eq!(sensor.destroy().registers[0x80], 0x09);
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn timed_data_waits_poll_with_embassy_timer() {
+        use embassy_time::{Duration, Instant};
+
+        let mut sensor = Ltr559Async::new_device(I2cMock::new(), SlaveAddr::default());
+        block_on(sensor.set_als_meas_rate(AlsIntTime::_50ms, AlsMeasRate::_50ms)).unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            block_on(sensor.wait_for_als_data_timed()),
+            Err(Error::Timeout)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        sensor.i2c.registers[0x8C] = 0x01;
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x23, 0x01]);
+        let data = block_on(sensor.wait_for_ps_data_timed(100)).unwrap();
+        assert_eq!(data.counts, 0x123);
+    }
+
+    #[cfg(all(feature = "embassy-time", feature = "float"))]
+    #[test]
+    fn timed_lux_stream_yields_fresh_readings() {
+        use futures_util::StreamExt;
+
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x04;
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559Async::new_device(i2c, SlaveAddr::default());
+        let mut lux = core::pin::pin!(sensor.lux_stream_timed());
+        assert_eq!(block_on(lux.next()).unwrap().unwrap(), 177.43);
+    }
+
+    #[test]
+    fn calibrates_ps_offset_and_rearms_window() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x05;
+        i2c.registers[0x8D] = 40;
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559Async::new_device(i2c, SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert_eq!(
+            block_on(sensor.calibrate_ps_offset(&mut delay, 4)).unwrap(),
+            40
+        );
+        block_on(sensor.rearm_als_window(10)).unwrap();
+        let state = block_on(sensor.save_state()).unwrap();
+        block_on(sensor.restore_state(&state)).unwrap();
+        let regs = sensor.destroy().registers;
+        assert_eq!(regs[0x94..0x96], [40, 0]);
+        assert_eq!(regs[0x97..0x9B], [110, 0, 90, 0]);
+    }
+}
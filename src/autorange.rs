@@ -0,0 +1,610 @@
+//! Automatic adjustment of the ALS range
+//!
+//! An [`AutoGain`] keeps the ALS counts in a band where they are well above
+//! the noise floor and away from full scale. After each measurement taken
+//! with [`Ltr559::get_measurement_auto_gain()`] the gain is stepped up in
+//! the dark and down in bright light. The lux of each measurement is
+//! computed with the gain its data was measured with, so the output stays
+//! continuous across gain changes.
+//!
+//! ```
+//! use ltr_559::{AlsGain, AutoGain};
+//!
+//! let agc = AutoGain::new(1000, 40000);
+//! assert_eq!(agc.next_gain(AlsGain::Gain1x, 600, 40), Some(AlsGain::Gain48x));
+//! assert_eq!(agc.next_gain(AlsGain::Gain8x, 60000, 9000), Some(AlsGain::Gain4x));
+//! assert_eq!(agc.next_gain(AlsGain::Gain4x, 20000, 3000), None);
+//! ```
+//!
+//! An [`AutoIntegration`] likewise lengthens the integration time in the
+//! dark and shortens it in bright light, up to a maximum integration time
+//! bounding the latency of each reading. See
+//! [`Ltr559::get_measurement_auto_int_time()`].
+//!
+//! Used together, both would fight over the same counts. A
+//! [`RangeManager`] instead moves along a single ladder of settings: the
+//! integration time is lengthened first, up to its maximum, then the gain
+//! is raised. It only steps after a number of consecutive readings out of
+//! the band, except at full scale, and discards the readings taken while
+//! the new range settles. See [`Ltr559::get_measurement_ranged()`].
+use crate::hal::blocking::{delay::DelayMs, i2c};
+use crate::{marker, AlsGain, AlsIntTime, Error, Ltr559, Measurement};
+
+/// ALS gains in ascending order
+const GAINS: [AlsGain; 6] = [
+    AlsGain::Gain1x,
+    AlsGain::Gain2x,
+    AlsGain::Gain4x,
+    AlsGain::Gain8x,
+    AlsGain::Gain48x,
+    AlsGain::Gain96x,
+];
+
+fn gain_index(gain: AlsGain) -> usize {
+    GAINS.iter().position(|g| *g == gain).unwrap_or(0)
+}
+
+/// Chooses the ALS gain keeping the counts within a band
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AutoGain {
+    low: u16,
+    high: u16,
+}
+
+impl Default for AutoGain {
+    /// Counts between 1000 and 40000
+    fn default() -> Self {
+        AutoGain::new(1000, 40000)
+    }
+}
+
+impl AutoGain {
+    /// Create a new policy keeping the larger ALS channel between `low`
+    /// and `high` counts. `high` is raised to `low` if it is lower.
+    pub fn new(low: u16, high: u16) -> Self {
+        AutoGain {
+            low,
+            high: high.max(low),
+        }
+    }
+
+    /// `(low, high)` counts of the band
+    pub fn band(&self) -> (u16, u16) {
+        (self.low, self.high)
+    }
+
+    /// Gain to switch to after reading `ch0` and `ch1` with `gain`, `None`
+    /// to keep it.
+    ///
+    /// Above the band or at full scale the gain is lowered by one step.
+    /// Below the band it is raised to the highest gain at which the
+    /// expected counts stay within the band, so that the next reading does
+    /// not step back down.
+    pub fn next_gain(&self, gain: AlsGain, ch0: u16, ch1: u16) -> Option<AlsGain> {
+        let counts = ch0.max(ch1);
+        let index = gain_index(gain);
+        if counts == u16::MAX || counts > self.high {
+            return index.checked_sub(1).map(|index| GAINS[index]);
+        }
+        if counts >= self.low {
+            return None;
+        }
+        let expected = |g: &AlsGain| u32::from(counts) * g.multiplier() / gain.multiplier();
+        GAINS[index + 1..]
+            .iter()
+            .rev()
+            .find(|g| expected(g) <= u32::from(self.high))
+            .copied()
+    }
+}
+
+/// ALS integration times in ascending order
+const INT_TIMES: [AlsIntTime; 8] = [
+    AlsIntTime::_50ms,
+    AlsIntTime::_100ms,
+    AlsIntTime::_150ms,
+    AlsIntTime::_200ms,
+    AlsIntTime::_250ms,
+    AlsIntTime::_300ms,
+    AlsIntTime::_350ms,
+    AlsIntTime::_400ms,
+];
+
+fn int_time_index(int_time: AlsIntTime) -> usize {
+    INT_TIMES.iter().position(|t| *t == int_time).unwrap_or(0)
+}
+
+/// Chooses the ALS integration time keeping the counts within a band
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AutoIntegration {
+    low: u16,
+    high: u16,
+    max_int_time: AlsIntTime,
+}
+
+impl Default for AutoIntegration {
+    /// Counts between 1000 and 40000, up to 400 ms
+    fn default() -> Self {
+        AutoIntegration::new(1000, 40000, AlsIntTime::_400ms)
+    }
+}
+
+impl AutoIntegration {
+    /// Create a new policy keeping the larger ALS channel between `low`
+    /// and `high` counts, with integration times up to `max_int_time`.
+    ///
+    /// `high` is raised to `low` if it is lower. The measurement rate is
+    /// lengthened as needed when the integration time grows and restored
+    /// when it shrinks again (see
+    /// [`Ltr559::change_int_time_safely()`](crate::Ltr559::change_int_time_safely)).
+    /// A long integration time therefore also lengthens the time between
+    /// two readings, e.g. to 500 ms for 400 ms.
+    pub fn new(low: u16, high: u16, max_int_time: AlsIntTime) -> Self {
+        AutoIntegration {
+            low,
+            high: high.max(low),
+            max_int_time,
+        }
+    }
+
+    /// `(low, high)` counts of the band
+    pub fn band(&self) -> (u16, u16) {
+        (self.low, self.high)
+    }
+
+    /// Longest integration time chosen
+    pub fn max_int_time(&self) -> AlsIntTime {
+        self.max_int_time
+    }
+
+    /// Integration time to switch to after reading `ch0` and `ch1` with
+    /// `int_time`, `None` to keep it.
+    ///
+    /// Above the band or at full scale the integration time is shortened
+    /// by one step. Below the band it is lengthened to the longest allowed
+    /// integration time at which the expected counts stay within the band.
+    /// An integration time above the maximum is shortened to it.
+    pub fn next_int_time(&self, int_time: AlsIntTime, ch0: u16, ch1: u16) -> Option<AlsIntTime> {
+        let counts = ch0.max(ch1);
+        let index = int_time_index(int_time);
+        let max_index = int_time_index(self.max_int_time);
+        if counts == u16::MAX || counts > self.high {
+            return index
+                .checked_sub(1)
+                .map(|index| INT_TIMES[index.min(max_index)]);
+        }
+        if index > max_index {
+            return Some(self.max_int_time);
+        }
+        if counts >= self.low {
+            return None;
+        }
+        let expected =
+            |t: &AlsIntTime| u32::from(counts) * t.milliseconds() / int_time.milliseconds();
+        INT_TIMES[index + 1..=max_index]
+            .iter()
+            .rev()
+            .find(|t| expected(t) <= u32::from(self.high))
+            .copied()
+    }
+}
+
+/// Decision of a [`RangeManager`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RangeStep {
+    /// The reading was taken while the range settled and was ignored
+    Settling,
+    /// Keep the current range
+    Keep,
+    /// Switch to this gain and integration time
+    Change(AlsGain, AlsIntTime),
+}
+
+/// Chooses the ALS gain and integration time together
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RangeManager {
+    low: u16,
+    high: u16,
+    max_int_time: AlsIntTime,
+    hold_samples: u8,
+    settle_samples: u8,
+    out_of_band: u8,
+    settling: u8,
+}
+
+impl Default for RangeManager {
+    /// Counts between 1000 and 40000, up to 400 ms, stepping after 2
+    /// readings out of the band and discarding 1 reading after a change
+    fn default() -> Self {
+        RangeManager::new(1000, 40000, AlsIntTime::_400ms, 2, 1)
+    }
+}
+
+impl RangeManager {
+    /// Create a new manager keeping the larger ALS channel between `low`
+    /// and `high` counts, with integration times up to `max_int_time`.
+    ///
+    /// The range is changed after `hold_samples` consecutive readings out
+    /// of the band (at least 1), or at once at full scale. After a change,
+    /// `settle_samples` readings are ignored. `high` is raised to `low` if
+    /// it is lower.
+    pub fn new(
+        low: u16,
+        high: u16,
+        max_int_time: AlsIntTime,
+        hold_samples: u8,
+        settle_samples: u8,
+    ) -> Self {
+        RangeManager {
+            low,
+            high: high.max(low),
+            max_int_time,
+            hold_samples: hold_samples.max(1),
+            settle_samples,
+            out_of_band: 0,
+            settling: 0,
+        }
+    }
+
+    /// Feed a reading of `ch0` and `ch1` taken with `gain` and `int_time`
+    /// and decide on the range.
+    ///
+    /// Lower sensitivity is reached by lowering the gain first, then the
+    /// integration time; higher sensitivity by lengthening the integration
+    /// time first, then raising the gain. When stepping up, the highest
+    /// setting at which the expected counts stay within the band is chosen.
+    pub fn update(&mut self, gain: AlsGain, int_time: AlsIntTime, ch0: u16, ch1: u16) -> RangeStep {
+        if self.settling > 0 {
+            self.settling -= 1;
+            return RangeStep::Settling;
+        }
+        let counts = ch0.max(ch1);
+        let saturated = counts == u16::MAX;
+        if !saturated && (self.low..=self.high).contains(&counts) {
+            self.out_of_band = 0;
+            return RangeStep::Keep;
+        }
+        self.out_of_band = self.out_of_band.saturating_add(1);
+        if !saturated && self.out_of_band < self.hold_samples {
+            return RangeStep::Keep;
+        }
+        let next = self.next_range(gain, int_time, counts);
+        self.out_of_band = 0;
+        match next {
+            Some((gain, int_time)) => {
+                self.settling = self.settle_samples;
+                RangeStep::Change(gain, int_time)
+            }
+            None => RangeStep::Keep,
+        }
+    }
+
+    /// Forget the pending out-of-band readings and the settling state
+    pub fn reset(&mut self) {
+        self.out_of_band = 0;
+        self.settling = 0;
+    }
+
+    /// Number of settings on the ladder
+    fn ladder_len(&self) -> usize {
+        int_time_index(self.max_int_time) + GAINS.len()
+    }
+
+    /// Setting `index` of the ladder: the integration times with gain 1x,
+    /// then the gains with the longest integration time
+    fn ladder(&self, index: usize) -> (AlsGain, AlsIntTime) {
+        let max_index = int_time_index(self.max_int_time);
+        if index <= max_index {
+            (AlsGain::Gain1x, INT_TIMES[index])
+        } else {
+            (GAINS[index - max_index], self.max_int_time)
+        }
+    }
+
+    fn next_range(
+        &self,
+        gain: AlsGain,
+        int_time: AlsIntTime,
+        counts: u16,
+    ) -> Option<(AlsGain, AlsIntTime)> {
+        let sensitivity =
+            |(gain, int_time): (AlsGain, AlsIntTime)| gain.multiplier() * int_time.milliseconds();
+        let current = sensitivity((gain, int_time));
+        let next = if counts > self.high {
+            // the most sensitive setting below the current one
+            (0..self.ladder_len())
+                .map(|index| self.ladder(index))
+                .take_while(|setting| sensitivity(*setting) < current)
+                .last()
+        } else {
+            let expected = |setting| u32::from(counts) * sensitivity(setting) / current;
+            (0..self.ladder_len())
+                .map(|index| self.ladder(index))
+                .filter(|setting| sensitivity(*setting) > current)
+                .take_while(|setting| expected(*setting) <= u32::from(self.high))
+                .last()
+        };
+        next.filter(|setting| *setting != (gain, int_time))
+    }
+}
+
+/// A measurement taken with automatic ranging
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AutoRangeMeasurement {
+    /// The measurement, with the lux computed for the gain it was taken
+    /// with
+    pub measurement: Measurement,
+    /// Whether the gain was changed after this measurement
+    pub gain_changed: bool,
+    /// Whether the integration time was changed after this measurement
+    pub int_time_changed: bool,
+    /// Whether the measurement was taken while the range settled after a
+    /// change and was not used to decide on the range
+    pub settling: bool,
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Take a measurement (see [`get_measurement()`](#method.get_measurement))
+    /// and adjust the ALS gain according to `agc`.
+    ///
+    /// The gain is changed with
+    /// [`change_gain_safely()`](#method.change_gain_safely), which waits
+    /// for the first conversion with the new gain and discards it, so the
+    /// next measurement is valid.
+    pub fn get_measurement_auto_gain<D: DelayMs<u32>>(
+        &mut self,
+        agc: &AutoGain,
+        delay: &mut D,
+    ) -> Result<AutoRangeMeasurement, Error<E>> {
+        let measurement = self.get_measurement()?;
+        let next = agc.next_gain(
+            measurement.als_gain_used,
+            measurement.als_ch0,
+            measurement.als_ch1,
+        );
+        if let Some(gain) = next {
+            self.change_gain_safely(gain, delay)?;
+        }
+        Ok(AutoRangeMeasurement {
+            measurement,
+            gain_changed: next.is_some(),
+            int_time_changed: false,
+            settling: false,
+        })
+    }
+
+    /// Take a measurement (see [`get_measurement()`](#method.get_measurement))
+    /// and adjust the ALS integration time according to `policy`.
+    ///
+    /// The integration time is changed with
+    /// [`change_int_time_safely()`](#method.change_int_time_safely), which
+    /// waits for the first conversion with the new integration time and
+    /// discards it, so the next measurement is valid.
+    pub fn get_measurement_auto_int_time<D: DelayMs<u32>>(
+        &mut self,
+        policy: &AutoIntegration,
+        delay: &mut D,
+    ) -> Result<AutoRangeMeasurement, Error<E>> {
+        let measurement = self.get_measurement()?;
+        let next = policy.next_int_time(
+            measurement.als_int_used,
+            measurement.als_ch0,
+            measurement.als_ch1,
+        );
+        if let Some(int_time) = next {
+            self.change_int_time_safely(int_time, delay)?;
+        }
+        Ok(AutoRangeMeasurement {
+            measurement,
+            gain_changed: false,
+            int_time_changed: next.is_some(),
+            settling: false,
+        })
+    }
+
+    /// Take a measurement (see [`get_measurement()`](#method.get_measurement))
+    /// and adjust the ALS gain and integration time according to `manager`.
+    ///
+    /// Both settings are changed at once with
+    /// [`change_range_safely()`](#method.change_range_safely), so a single
+    /// conversion is discarded. The largest lux measurable in the current
+    /// range is returned by [`full_scale_lux()`](#method.full_scale_lux).
+    pub fn get_measurement_ranged<D: DelayMs<u32>>(
+        &mut self,
+        manager: &mut RangeManager,
+        delay: &mut D,
+    ) -> Result<AutoRangeMeasurement, Error<E>> {
+        let measurement = self.get_measurement()?;
+        let step = manager.update(
+            measurement.als_gain_used,
+            measurement.als_int_used,
+            measurement.als_ch0,
+            measurement.als_ch1,
+        );
+        let mut result = AutoRangeMeasurement {
+            measurement,
+            settling: step == RangeStep::Settling,
+            ..AutoRangeMeasurement::default()
+        };
+        if let RangeStep::Change(gain, int_time) = step {
+            result.gain_changed = gain != self.als_gain;
+            result.int_time_changed = int_time != self.als_int;
+            self.change_range_safely(gain, int_time, delay)?;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{DelayMock, I2cMock};
+    use crate::SlaveAddr;
+
+    #[test]
+    fn steps_within_limits() {
+        let agc = AutoGain::default();
+        assert_eq!(agc.next_gain(AlsGain::Gain1x, u16::MAX, 0), None);
+        assert_eq!(agc.next_gain(AlsGain::Gain96x, 10, 10), None);
+        assert_eq!(
+            agc.next_gain(AlsGain::Gain96x, 0, 45000),
+            Some(AlsGain::Gain48x)
+        );
+        assert_eq!(agc.next_gain(AlsGain::Gain1x, 0, 0), Some(AlsGain::Gain96x));
+        // 8x would give 48000 counts, above the band
+        assert_eq!(agc.next_gain(AlsGain::Gain1x, 6000, 0), None);
+        assert_eq!(
+            agc.next_gain(AlsGain::Gain1x, 900, 0),
+            Some(AlsGain::Gain8x)
+        );
+    }
+
+    #[test]
+    fn integration_time_limited_by_latency() {
+        let policy = AutoIntegration::new(1000, 40000, AlsIntTime::_200ms);
+        assert_eq!(
+            policy.next_int_time(AlsIntTime::_100ms, 100, 0),
+            Some(AlsIntTime::_200ms)
+        );
+        // 200 ms would give 1900 counts, above the band
+        let narrow = AutoIntegration::new(1000, 1800, AlsIntTime::_200ms);
+        assert_eq!(
+            narrow.next_int_time(AlsIntTime::_100ms, 950, 0),
+            Some(AlsIntTime::_150ms)
+        );
+        assert_eq!(
+            policy.next_int_time(AlsIntTime::_100ms, 0, 50000),
+            Some(AlsIntTime::_50ms)
+        );
+        assert_eq!(policy.next_int_time(AlsIntTime::_50ms, u16::MAX, 0), None);
+        assert_eq!(
+            policy.next_int_time(AlsIntTime::_400ms, 5000, 0),
+            Some(AlsIntTime::_200ms)
+        );
+        assert_eq!(
+            policy.next_int_time(AlsIntTime::_400ms, u16::MAX, 0),
+            Some(AlsIntTime::_200ms)
+        );
+    }
+
+    #[test]
+    fn measurement_switches_int_time() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        sensor.i2c.registers[0x8A..0x8D].copy_from_slice(&[0x50, 0xC3, 0x04]);
+        let result = sensor
+            .get_measurement_auto_int_time(&AutoIntegration::default(), &mut delay)
+            .unwrap();
+        assert!(result.int_time_changed && !result.gain_changed);
+        assert_eq!(result.measurement.als_int_used, AlsIntTime::_100ms);
+        assert_eq!(
+            sensor.i2c.registers[0x85],
+            (AlsIntTime::_50ms.value() << 3) | 0x03
+        );
+    }
+
+    #[test]
+    fn range_follows_single_ladder() {
+        let mut manager = RangeManager::new(1000, 40000, AlsIntTime::_200ms, 1, 0);
+        // integration time first, then the gain: 100 ms 1x -> 200 ms 8x
+        assert_eq!(
+            manager.update(AlsGain::Gain1x, AlsIntTime::_100ms, 500, 0),
+            RangeStep::Change(AlsGain::Gain8x, AlsIntTime::_200ms)
+        );
+        // gain first when going down
+        assert_eq!(
+            manager.update(AlsGain::Gain8x, AlsIntTime::_200ms, 50000, 0),
+            RangeStep::Change(AlsGain::Gain4x, AlsIntTime::_200ms)
+        );
+        assert_eq!(
+            manager.update(AlsGain::Gain1x, AlsIntTime::_150ms, 50000, 0),
+            RangeStep::Change(AlsGain::Gain1x, AlsIntTime::_100ms)
+        );
+        assert_eq!(
+            manager.update(AlsGain::Gain1x, AlsIntTime::_50ms, u16::MAX, 0),
+            RangeStep::Keep
+        );
+        assert_eq!(
+            manager.update(AlsGain::Gain96x, AlsIntTime::_200ms, 10, 0),
+            RangeStep::Keep
+        );
+    }
+
+    #[test]
+    fn range_hysteresis_and_settling() {
+        let mut manager = RangeManager::default();
+        let (gain, int_time) = (AlsGain::Gain4x, AlsIntTime::_400ms);
+        assert_eq!(manager.update(gain, int_time, 50000, 0), RangeStep::Keep);
+        assert_eq!(manager.update(gain, int_time, 20000, 0), RangeStep::Keep);
+        assert_eq!(manager.update(gain, int_time, 50000, 0), RangeStep::Keep);
+        assert_eq!(
+            manager.update(gain, int_time, 50000, 0),
+            RangeStep::Change(AlsGain::Gain2x, int_time)
+        );
+        assert_eq!(
+            manager.update(AlsGain::Gain2x, int_time, 50000, 0),
+            RangeStep::Settling
+        );
+        // full scale does not wait
+        assert_eq!(
+            manager.update(AlsGain::Gain2x, int_time, u16::MAX, 0),
+            RangeStep::Change(AlsGain::Gain1x, int_time)
+        );
+        manager.reset();
+        assert_eq!(
+            manager.update(AlsGain::Gain1x, int_time, 30000, 0),
+            RangeStep::Keep
+        );
+    }
+
+    #[test]
+    fn ranged_measurement_changes_both_settings() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        let mut manager = RangeManager::new(1000, 40000, AlsIntTime::_200ms, 1, 1);
+        let full_scale = sensor.full_scale_lux();
+        sensor.i2c.registers[0x8A..0x8D].copy_from_slice(&[100, 0, 0x04]);
+        let result = sensor
+            .get_measurement_ranged(&mut manager, &mut delay)
+            .unwrap();
+        assert!(result.gain_changed && result.int_time_changed && !result.settling);
+        assert_eq!(sensor.als_gain, AlsGain::Gain96x);
+        assert_eq!(sensor.als_int, AlsIntTime::_200ms);
+        assert!(sensor.full_scale_lux() < full_scale / 100.0);
+        let result = sensor
+            .get_measurement_ranged(&mut manager, &mut delay)
+            .unwrap();
+        assert!(result.settling && !result.gain_changed);
+    }
+
+    #[test]
+    fn measurement_switches_gain() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        // ALS in standby, so that no conversion is waited for
+        sensor.i2c.registers[0x8A..0x8D].copy_from_slice(&[100, 0, 0x04]);
+        let result = sensor
+            .get_measurement_auto_gain(&AutoGain::default(), &mut delay)
+            .unwrap();
+        assert!(result.gain_changed);
+        assert_eq!(result.measurement.als_gain_used, AlsGain::Gain1x);
+        assert_eq!(sensor.i2c.registers[0x80], AlsGain::Gain96x.value());
+
+        // the status now reports data measured with 96x
+        sensor.i2c.registers[0x8A..0x8D].copy_from_slice(&[0x60, 0x25, 0x74]);
+        let result = sensor
+            .get_measurement_auto_gain(&AutoGain::default(), &mut delay)
+            .unwrap();
+        assert!(!result.gain_changed);
+        assert_eq!(result.measurement.als_gain_used, AlsGain::Gain96x);
+    }
+}
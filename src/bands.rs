@@ -0,0 +1,124 @@
+//! Classification of a value into bands with hysteresis at each boundary,
+//! shared by the lux zones and the proximity zones
+use core::marker::PhantomData;
+
+/// Named bands, from the lowest to the highest values
+pub(crate) trait Band: Copy + 'static {
+    /// All bands in ascending order, one more than there are boundaries
+    const ALL: &'static [Self];
+}
+
+/// Value type of the boundaries between the bands
+pub(crate) trait Boundary: Copy + PartialOrd {
+    /// Value from which `self` is crossed upwards
+    fn raised(self, hysteresis: Self) -> Self;
+    /// Value below which `self` is crossed downwards
+    fn lowered(self, hysteresis: Self) -> Self;
+}
+
+/// Relative hysteresis
+#[cfg(feature = "float")]
+impl Boundary for f32 {
+    fn raised(self, hysteresis: Self) -> Self {
+        self * (1.0 + hysteresis)
+    }
+
+    fn lowered(self, hysteresis: Self) -> Self {
+        self * (1.0 - hysteresis)
+    }
+}
+
+/// Absolute hysteresis, saturating at the bounds
+impl Boundary for u16 {
+    fn raised(self, hysteresis: Self) -> Self {
+        self.saturating_add(hysteresis)
+    }
+
+    fn lowered(self, hysteresis: Self) -> Self {
+        self.saturating_sub(hysteresis)
+    }
+}
+
+/// A band change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transition<B> {
+    /// Previous band, `None` for the first value
+    pub previous: Option<B>,
+    /// New band
+    pub current: B,
+}
+
+/// Maps values into the bands `B` separated by `N` boundaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Classifier<T, B, const N: usize> {
+    boundaries: [T; N],
+    hysteresis: T,
+    index: Option<usize>,
+    bands: PhantomData<B>,
+}
+
+impl<T: Boundary, B: Band, const N: usize> Classifier<T, B, N> {
+    /// Create a new classifier, sorting the boundaries in ascending order
+    pub(crate) fn new(mut boundaries: [T; N], hysteresis: T) -> Self {
+        debug_assert_eq!(B::ALL.len(), N + 1);
+        boundaries.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+        Classifier {
+            boundaries,
+            hysteresis,
+            index: None,
+            bands: PhantomData,
+        }
+    }
+
+    /// Feed a new value and return the transition if the band changed.
+    ///
+    /// The first value is classified without hysteresis and always
+    /// reported.
+    pub(crate) fn update(&mut self, value: T) -> Option<Transition<B>> {
+        let previous = self.index;
+        let hysteresis = previous.map(|_| self.hysteresis);
+        let raised = |boundary: T| hysteresis.map_or(boundary, |h| boundary.raised(h));
+        let lowered = |boundary: T| hysteresis.map_or(boundary, |h| boundary.lowered(h));
+        let mut index = previous.unwrap_or(0);
+        while index < N && value >= raised(self.boundaries[index]) {
+            index += 1;
+        }
+        while index > 0 && value < lowered(self.boundaries[index - 1]) {
+            index -= 1;
+        }
+        if previous == Some(index) {
+            return None;
+        }
+        self.index = Some(index);
+        Some(Transition {
+            previous: previous.map(|index| B::ALL[index]),
+            current: B::ALL[index],
+        })
+    }
+
+    /// Index of the current band, `None` before the first value
+    pub(crate) fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    /// Current band, `None` before the first value
+    pub(crate) fn band(&self) -> Option<B> {
+        self.index.map(|index| B::ALL[index])
+    }
+
+    /// Boundaries in ascending order
+    pub(crate) fn boundaries(&self) -> [T; N] {
+        self.boundaries
+    }
+
+    /// Hysteresis at each boundary
+    pub(crate) fn hysteresis(&self) -> T {
+        self.hysteresis
+    }
+
+    /// Forget the current band, so that the next value is reported
+    pub(crate) fn reset(&mut self) {
+        self.index = None;
+    }
+}
@@ -0,0 +1,131 @@
+//! Slow tracking of the PS level without target
+//!
+//! Temperature changes and dust on the cover glass shift the PS counts
+//! measured without any target over days and weeks. A [`PsBaseline`]
+//! follows this level slowly while no target is detected, like the
+//! baseline of a capacitive touch sensor, and returns the counts above it.
+//!
+//! ```
+//! use ltr_559::PsBaseline;
+//!
+//! // a target adds at least 50 counts, follow the drift by 1/16 per sample
+//! let mut baseline = PsBaseline::new(50, 4);
+//! assert_eq!(baseline.update(120), 0);
+//! assert_eq!(baseline.update(400), 280);
+//! assert_eq!(baseline.baseline(), Some(120));
+//! ```
+use crate::hal::blocking::i2c;
+use crate::{marker, Error, Ltr559};
+
+/// Baseline fraction bits
+const SHIFT: u32 = 4;
+/// Largest rate shift
+const MAX_RATE_SHIFT: u8 = 16;
+
+/// Estimates the no-target PS level and compensates it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PsBaseline {
+    target_threshold: u16,
+    rate_shift: u8,
+    baseline: Option<u32>,
+}
+
+impl PsBaseline {
+    /// Create a new tracker.
+    ///
+    /// Counts at least `target_threshold` above the baseline are taken as
+    /// a target and do not move the baseline. Other counts move it by
+    /// 1/2^`rate_shift` of the difference (`rate_shift` is limited to 16).
+    pub fn new(target_threshold: u16, rate_shift: u8) -> Self {
+        PsBaseline {
+            target_threshold,
+            rate_shift: rate_shift.min(MAX_RATE_SHIFT),
+            baseline: None,
+        }
+    }
+
+    /// Feed new PS counts and return them minus the baseline, saturating
+    /// at 0.
+    ///
+    /// The first counts initialize the baseline, so no target should be
+    /// present at that time.
+    pub fn update(&mut self, counts: u16) -> u16 {
+        let value = u32::from(counts) << SHIFT;
+        let baseline = match self.baseline {
+            Some(baseline) => baseline,
+            None => value,
+        };
+        let baseline = if self.is_target(counts, baseline) {
+            baseline
+        } else if value > baseline {
+            baseline + ((value - baseline) >> self.rate_shift)
+        } else {
+            baseline - ((baseline - value) >> self.rate_shift)
+        };
+        self.baseline = Some(baseline);
+        counts.saturating_sub((baseline >> SHIFT) as u16)
+    }
+
+    fn is_target(&self, counts: u16, baseline: u32) -> bool {
+        u32::from(counts) >= (baseline >> SHIFT) + u32::from(self.target_threshold)
+    }
+
+    /// Current baseline in counts, `None` before the first value
+    pub fn baseline(&self) -> Option<u16> {
+        self.baseline.map(|baseline| (baseline >> SHIFT) as u16)
+    }
+
+    /// Forget the baseline, so that the next counts initialize it again
+    pub fn reset(&mut self) {
+        self.baseline = None;
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Read the PS counts, update `baseline` with them and return the
+    /// counts above the baseline.
+    pub fn get_ps_data_compensated(&mut self, baseline: &mut PsBaseline) -> Result<u16, Error<E>> {
+        let (counts, _) = self.get_ps_data()?;
+        Ok(baseline.update(counts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    #[test]
+    fn follows_drift_but_not_target() {
+        let mut baseline = PsBaseline::new(50, 2);
+        baseline.update(100);
+        for _ in 0..40 {
+            baseline.update(130);
+        }
+        assert_eq!(baseline.baseline(), Some(129));
+        for _ in 0..40 {
+            assert_eq!(baseline.update(500), 371);
+        }
+        for _ in 0..40 {
+            baseline.update(90);
+        }
+        assert_eq!(baseline.baseline(), Some(90));
+        baseline.reset();
+        assert_eq!(baseline.baseline(), None);
+    }
+
+    #[test]
+    fn compensates_ps_data() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut baseline = PsBaseline::new(50, 4);
+        sensor.i2c.registers[0x8D] = 80;
+        assert_eq!(sensor.get_ps_data_compensated(&mut baseline).unwrap(), 0);
+        sensor.i2c.registers[0x8D] = 200;
+        assert_eq!(sensor.get_ps_data_compensated(&mut baseline).unwrap(), 120);
+    }
+}
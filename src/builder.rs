@@ -0,0 +1,154 @@
+//! Builder for a configured driver instance
+use crate::hal::blocking::i2c;
+use crate::{
+    ic, AlsGain, AlsIntTime, AlsMeasRate, Config, Error, InterruptMode, InterruptPinPolarity,
+    LedCurrent, LedDutyCycle, LedPulse, Ltr559, ModeChangeError, PsMeasRate, SlaveAddr,
+};
+
+/// Builder for a configured [`Ltr559`] instance
+///
+/// Created with [`Ltr559::builder()`](struct.Ltr559.html#method.builder).
+/// Settings that are not set keep their power-up value, except that the
+/// ALS is enabled by default.
+#[derive(Debug)]
+pub struct Ltr559Builder<I2C> {
+    i2c: I2C,
+    address: SlaveAddr,
+    config: Config,
+}
+
+impl<I2C> Ltr559<I2C, ic::Ltr559> {
+    /// Start building a configured instance of the device
+    pub fn builder(i2c: I2C, address: SlaveAddr) -> Ltr559Builder<I2C> {
+        Ltr559Builder {
+            i2c,
+            address,
+            config: Config {
+                als_active: true,
+                ..Config::default()
+            },
+        }
+    }
+}
+
+impl<I2C> Ltr559Builder<I2C> {
+    /// Start from a complete configuration
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the ALS gain
+    pub fn als_gain(mut self, gain: AlsGain) -> Self {
+        self.config.als_gain = gain;
+        self
+    }
+
+    /// Set the ALS integration time and measurement repeat rate
+    ///
+    /// The repeat rate may not be shorter than the integration time.
+    pub fn als_rate(mut self, int_time: AlsIntTime, meas_rate: AlsMeasRate) -> Self {
+        self.config.als_int_time = int_time;
+        self.config.als_meas_rate = meas_rate;
+        self
+    }
+
+    /// Enable or disable the ALS
+    pub fn als_enabled(mut self, enabled: bool) -> Self {
+        self.config.als_active = enabled;
+        self
+    }
+
+    /// Enable or disable the PS
+    pub fn ps_enabled(mut self, enabled: bool) -> Self {
+        self.config.ps_active = enabled;
+        self
+    }
+
+    /// Set the PS measurement repeat rate
+    pub fn ps_rate(mut self, meas_rate: PsMeasRate) -> Self {
+        self.config.ps_meas_rate = meas_rate;
+        self
+    }
+
+    /// Set the PS LED pulse frequency, duty cycle and peak current
+    pub fn ps_led(
+        mut self,
+        pulse: LedPulse,
+        duty_cycle: LedDutyCycle,
+        current: LedCurrent,
+    ) -> Self {
+        self.config.led_pulse = pulse;
+        self.config.led_duty_cycle = duty_cycle;
+        self.config.led_current = current;
+        self
+    }
+
+    /// Set the number of LED pulses per PS measurement (1 to 15)
+    pub fn ps_n_pulses(mut self, n_pulses: u8) -> Self {
+        self.config.ps_n_pulses = n_pulses;
+        self
+    }
+
+    /// Set the interrupt pin polarity and mode
+    pub fn interrupt(mut self, polarity: InterruptPinPolarity, mode: InterruptMode) -> Self {
+        self.config.interrupt_polarity = polarity;
+        self.config.interrupt_mode = mode;
+        self
+    }
+
+    /// Validate the settings, initialize the device and return the driver.
+    ///
+    /// The device is configured with the ALS and PS in standby, which are
+    /// activated last (see [`apply_config()`](struct.Ltr559.html#method.apply_config)).
+    /// The first measurement is available after the wakeup time plus one
+    /// measurement period.
+    ///
+    /// In case of error the I²C bus is returned inside the error. Invalid
+    /// settings are reported before anything is written.
+    pub fn build<E>(self) -> Result<Ltr559<I2C, ic::Ltr559>, ModeChangeError<E, I2C>>
+    where
+        I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+    {
+        let config = self.config;
+        if config.als_meas_rate.milliseconds() < config.als_int_time.milliseconds() {
+            return Err(ModeChangeError::InvalidInputData(self.i2c));
+        }
+        let mut sensor = Ltr559::new_device(self.i2c, self.address);
+        match sensor.apply_config(&config) {
+            Ok(()) => Ok(sensor),
+            Err(Error::I2C(e)) => Err(ModeChangeError::I2C(e, sensor.destroy())),
+            Err(_) => Err(ModeChangeError::InvalidInputData(sensor.destroy())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+
+    #[test]
+    fn builds_configured_driver() {
+        let mut sensor = Ltr559::builder(I2cMock::new(), SlaveAddr::default())
+            .als_gain(AlsGain::Gain4x)
+            .als_rate(AlsIntTime::_50ms, AlsMeasRate::_50ms)
+            .ps_enabled(true)
+            .build()
+            .ok()
+            .unwrap();
+        let config = sensor.read_config().unwrap();
+        assert_eq!(config.als_gain, AlsGain::Gain4x);
+        assert!(config.als_active);
+        assert!(config.ps_active);
+        assert_eq!(sensor.destroy().registers[0x85], 0x08);
+    }
+
+    #[test]
+    fn rejects_rate_shorter_than_integration_time() {
+        let result = Ltr559::builder(I2cMock::new(), SlaveAddr::default())
+            .als_rate(AlsIntTime::_200ms, AlsMeasRate::_100ms)
+            .build();
+        assert!(matches!(result, Err(ModeChangeError::InvalidInputData(_))));
+    }
+}
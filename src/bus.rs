@@ -0,0 +1,93 @@
+//! I²C bus adapters
+//!
+//! Register reads are performed through [`i2c::WriteRead`], which sends the
+//! register address and reads the value back with a repeated start and no
+//! stop condition in between, as required by the device.
+//!
+//! Some I²C controllers (or their HAL implementations) cannot do that and
+//! insert a stop condition, in which case the device may occasionally
+//! return stale bytes. [`SeparateWriteRead`] can be used with such
+//! controllers to explicitly perform the address write and the read as two
+//! separate transactions instead.
+//!
+//! The driver owns its bus handle, which does not prevent sharing the bus
+//! with other devices: with the `eh1` feature any [`embedded-hal-bus`]
+//! shared device (`RefCellDevice`, `CriticalSectionDevice`, `AtomicDevice`,
+//! ...) wrapped in `Eh1` can be passed to the constructor and retrieved
+//! again with `destroy()`. See the `enviro_shared_bus` example.
+//!
+//! [`embedded-hal-bus`]: https://docs.rs/embedded-hal-bus
+use crate::hal::blocking::i2c;
+
+/// Bus adapter performing register reads as a write followed by a separate read
+#[derive(Debug)]
+pub struct SeparateWriteRead<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C> SeparateWriteRead<I2C> {
+    /// Wrap an I²C bus
+    pub fn new(i2c: I2C) -> Self {
+        SeparateWriteRead { i2c }
+    }
+
+    /// Return the wrapped I²C bus
+    pub fn into_inner(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C, E> i2c::Write for SeparateWriteRead<I2C>
+where
+    I2C: i2c::Write<Error = E>,
+{
+    type Error = E;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        self.i2c.write(address, bytes)
+    }
+}
+
+impl<I2C, E> i2c::WriteRead for SeparateWriteRead<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+{
+    type Error = E;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), E> {
+        self.i2c.write(address, bytes)?;
+        self.i2c.read(address, buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::{Ltr559, SlaveAddr};
+
+    #[test]
+    fn reads_through_separate_transactions() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x87] = 0x05;
+        let mut sensor = Ltr559::new_device(SeparateWriteRead::new(i2c), SlaveAddr::default());
+        assert_eq!(sensor.get_manufacturer_id().unwrap(), 0x05);
+    }
+
+    #[cfg(feature = "eh1")]
+    #[test]
+    fn shares_bus_through_refcell_device() {
+        use crate::Eh1;
+        use core::cell::RefCell;
+        use embedded_hal_bus::i2c::RefCellDevice;
+
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x86] = 0x92;
+        let bus = RefCell::new(i2c);
+        let mut first = Ltr559::new_device(Eh1(RefCellDevice::new(&bus)), SlaveAddr::default());
+        let mut second = Ltr559::new_device(Eh1(RefCellDevice::new(&bus)), SlaveAddr::default());
+        first.set_ps_offset(0x123).unwrap();
+        assert_eq!(second.get_part_id().unwrap(), 0x92);
+        assert_eq!(bus.borrow().registers[0x94], 0x23);
+    }
+}
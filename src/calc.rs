@@ -0,0 +1,259 @@
+//! Lux computation from raw ALS channel data
+//!
+//! These are the functions used by the driver, exposed so that the
+//! conversion can run on the host, e.g. on raw data logged earlier or on a
+//! gateway instead of the MCU.
+//!
+//! ```
+//! # #[cfg(feature = "float")] {
+//! use ltr_559::{calc, AlsGain, AlsIntTime};
+//!
+//! let lux = calc::compute_lux(100, 0, AlsGain::Gain1x, AlsIntTime::_100ms);
+//! assert_eq!(lux, 177.43);
+//! # }
+//! ```
+use crate::{AlsGain, AlsIntTime};
+
+/// Lux formula coefficients
+///
+/// The IR ratio CH1 / (CH0 + CH1) in per mille selects one of four
+/// coefficient pairs: the first pair applies below the first breakpoint,
+/// the last one from the last breakpoint on. Lux is then computed as
+/// `(CH0 * ch0[i] - CH1 * ch1[i]) / 10000`, divided by the integration
+/// time in units of 100 ms and by the gain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LuxCoefficients {
+    /// Ascending IR ratio breakpoints in per mille
+    pub ratio_breakpoints: [u16; 3],
+    /// CH0 coefficients scaled by 10000
+    pub ch0: [i32; 4],
+    /// CH1 coefficients scaled by 10000
+    pub ch1: [i32; 4],
+}
+
+impl Default for LuxCoefficients {
+    /// The coefficients of the datasheet
+    fn default() -> Self {
+        LuxCoefficients::DATASHEET
+    }
+}
+
+impl LuxCoefficients {
+    /// The coefficients of the datasheet
+    pub const DATASHEET: LuxCoefficients = LuxCoefficients {
+        ratio_breakpoints: [450, 640, 850],
+        ch0: [17743, 42785, 5926, 0],
+        ch1: [-11059, 19548, -1185, 0],
+    };
+
+    /// Index of the coefficients for the IR ratio CH1 / (CH0 + CH1)
+    fn index(&self, als_data_ch0: u32, als_data_ch1: u32) -> usize {
+        // widen before adding: the sum of two full-scale channels does not fit in u32
+        let total = u64::from(als_data_ch0) + u64::from(als_data_ch1);
+        // comparing the truncated per-mille ratio to integer bounds is exact
+        let ratio = (u64::from(als_data_ch1) * 1000)
+            .checked_div(total)
+            .unwrap_or(1000);
+        self.ratio_breakpoints
+            .iter()
+            .take_while(|&&breakpoint| ratio >= u64::from(breakpoint))
+            .count()
+    }
+
+    /// Weighted channel sum, i.e. lux at 1x gain and 100 ms scaled by 10000
+    fn weighted(&self, als_data_ch0: u16, als_data_ch1: u16) -> i64 {
+        let index = self.index(u32::from(als_data_ch0), u32::from(als_data_ch1));
+        i64::from(als_data_ch0) * i64::from(self.ch0[index])
+            - i64::from(als_data_ch1) * i64::from(self.ch1[index])
+    }
+
+    /// Compute lux with these coefficients. See [`compute_lux()`].
+    #[cfg(feature = "float")]
+    pub fn lux(
+        &self,
+        als_data_ch0: u16,
+        als_data_ch1: u16,
+        als_gain: AlsGain,
+        als_int: AlsIntTime,
+    ) -> f32 {
+        let (ch0, ch1) = (u32::from(als_data_ch0), u32::from(als_data_ch1));
+        self.lux_scaled(ch0, ch1, 0, als_gain, als_int)
+    }
+
+    /// Compute lux from channel counts scaled up by `2^shift`, e.g. sums of
+    /// oversampled conversions
+    #[cfg(feature = "float")]
+    pub(crate) fn lux_scaled(
+        &self,
+        als_data_ch0: u32,
+        als_data_ch1: u32,
+        shift: u8,
+        als_gain: AlsGain,
+        als_int: AlsIntTime,
+    ) -> f32 {
+        let index = self.index(als_data_ch0, als_data_ch1);
+        let mut ret = ((als_data_ch0 as f32) * self.ch0[index] as f32
+            - (als_data_ch1 as f32) * self.ch1[index] as f32)
+            / 10000.0;
+
+        ret /= (1_u32 << shift) as f32;
+        ret /= als_int.lux_compute_value();
+        ret /= als_gain.lux_compute_value();
+        ret
+    }
+
+    /// Compute millilux with these coefficients. See [`compute_millilux()`].
+    pub fn millilux(
+        &self,
+        als_data_ch0: u16,
+        als_data_ch1: u16,
+        als_gain: AlsGain,
+        als_int: AlsIntTime,
+    ) -> u32 {
+        let weighted = self.weighted(als_data_ch0, als_data_ch1);
+        // lux = weighted / 10000 / (integration time / 100 ms) / gain
+        let divisor = i64::from(als_int.milliseconds()) * i64::from(als_gain.multiplier());
+        (weighted * 10 / divisor).max(0).min(i64::from(u32::MAX)) as u32
+    }
+
+    /// Compute fixed-point lux with these coefficients. See
+    /// [`compute_lux_fixed()`].
+    #[cfg(feature = "fixed")]
+    pub fn lux_fixed(
+        &self,
+        als_data_ch0: u16,
+        als_data_ch1: u16,
+        als_gain: AlsGain,
+        als_int: AlsIntTime,
+    ) -> fixed::types::I16F16 {
+        let weighted = self.weighted(als_data_ch0, als_data_ch1);
+        let divisor = i64::from(als_int.milliseconds()) * i64::from(als_gain.multiplier()) * 100;
+        let bits = (weighted << 16) / divisor;
+        fixed::types::I16F16::from_bits(
+            bits.max(i64::from(i32::MIN)).min(i64::from(i32::MAX)) as i32
+        )
+    }
+}
+
+/// Lux calculation mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LuxAlgorithm {
+    /// Compute lux from the latest ALS data with the configured
+    /// [`LuxCoefficients`] and, for a measurement snapshot, the gain reported
+    /// in the status register. This is the default.
+    #[default]
+    Datasheet,
+    /// Match the Pimoroni Python `ltr559` library: the ALS data is only
+    /// taken over when the status reports new data or an ALS interrupt,
+    /// otherwise the previous data is used again, and lux is always computed
+    /// with the datasheet coefficients and the configured gain. Saturated
+    /// channels are not treated specially.
+    Pimoroni,
+}
+
+/// Compute lux from the raw channel data measured with `als_gain` and
+/// `als_int`, with the coefficients of the datasheet.
+#[cfg(feature = "float")]
+pub fn compute_lux(
+    als_data_ch0: u16,
+    als_data_ch1: u16,
+    als_gain: AlsGain,
+    als_int: AlsIntTime,
+) -> f32 {
+    LuxCoefficients::DATASHEET.lux(als_data_ch0, als_data_ch1, als_gain, als_int)
+}
+
+/// Same as [`compute_lux()`] but in millilux, with integer arithmetic only
+pub fn compute_millilux(
+    als_data_ch0: u16,
+    als_data_ch1: u16,
+    als_gain: AlsGain,
+    als_int: AlsIntTime,
+) -> u32 {
+    LuxCoefficients::DATASHEET.millilux(als_data_ch0, als_data_ch1, als_gain, als_int)
+}
+
+/// Same as [`compute_lux()`] but as a fixed-point number, with integer
+/// arithmetic only. Values above the I16F16 range saturate at `I16F16::MAX`.
+#[cfg(feature = "fixed")]
+pub fn compute_lux_fixed(
+    als_data_ch0: u16,
+    als_data_ch1: u16,
+    als_gain: AlsGain,
+    als_int: AlsIntTime,
+) -> fixed::types::I16F16 {
+    LuxCoefficients::DATASHEET.lux_fixed(als_data_ch0, als_data_ch1, als_gain, als_int)
+}
+
+/// 16.16 fixed-point copy of a window factor for the integer lux variants,
+/// `None` unless it is at least 1/65536 and below 32768
+#[cfg(feature = "float")]
+pub(crate) fn window_factor_q16(factor: f32) -> Option<i32> {
+    let factor_q16 = factor * 65536.0;
+    (1.0..2_147_483_648.0)
+        .contains(&factor_q16)
+        .then_some(factor_q16 as i32)
+}
+
+/// 16.16 fixed-point copy of a lux offset for the integer lux variants,
+/// `None` unless it is above -32768 and below 32768
+#[cfg(feature = "float")]
+pub(crate) fn lux_offset_q16(offset: f32) -> Option<i32> {
+    (offset.abs() < 32768.0).then_some((offset * 65536.0) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn millilux_of_known_reading() {
+        assert_eq!(
+            compute_millilux(100, 0, AlsGain::Gain1x, AlsIntTime::_100ms),
+            177_430
+        );
+        assert_eq!(
+            compute_millilux(0, 0, AlsGain::Gain1x, AlsIntTime::_100ms),
+            0
+        );
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn integer_millilux_matches_float_lux() {
+        for &(ch0, ch1) in &[(100, 0), (1000, 700), (5000, 4000), (200, 900), (0xFFFF, 0)] {
+            for &gain in &[AlsGain::Gain1x, AlsGain::Gain4x, AlsGain::Gain96x] {
+                for &int in &[AlsIntTime::_50ms, AlsIntTime::_150ms, AlsIntTime::_400ms] {
+                    let lux = compute_lux(ch0, ch1, gain, int);
+                    let millilux = compute_millilux(ch0, ch1, gain, int);
+                    assert!((lux * 1000.0 - millilux as f32).abs() <= 1.0 + lux * 1e-3);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "fixed")]
+    #[test]
+    fn fixed_lux_matches_float_lux() {
+        use fixed::types::I16F16;
+        let lux = compute_lux_fixed(100, 0, AlsGain::Gain1x, AlsIntTime::_100ms);
+        assert!((lux - I16F16::from_num(177.43)).abs() < I16F16::from_num(0.001));
+        let lux = compute_lux_fixed(0xFFFF, 0, AlsGain::Gain1x, AlsIntTime::_50ms);
+        assert_eq!(lux, I16F16::MAX);
+    }
+
+    #[test]
+    fn custom_coefficients() {
+        let coefficients = LuxCoefficients {
+            ratio_breakpoints: [500, 1000, 1000],
+            ch0: [20000, 10000, 0, 0],
+            ch1: [0, 5000, 0, 0],
+        };
+        let (gain, int) = (AlsGain::Gain1x, AlsIntTime::_100ms);
+        assert_eq!(coefficients.millilux(100, 0, gain, int), 200_000);
+        assert_eq!(coefficients.millilux(100, 100, gain, int), 50_000);
+        assert_eq!(coefficients.millilux(0, 0, gain, int), 0);
+    }
+}
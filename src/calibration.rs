@@ -0,0 +1,249 @@
+//! Calibration and bring-up procedures
+//!
+//! The results of the calibration of a unit can be kept in a
+//! [`CalibrationData`], stored by the firmware, e.g. serialized with
+//! `postcard` (`serde` feature) into EEPROM or flash, and applied again at
+//! boot with [`Ltr559::apply_calibration()`].
+use crate::hal::blocking::{delay::DelayMs, i2c};
+#[cfg(feature = "float")]
+use crate::DistanceModel;
+use crate::{marker, Ltr559};
+
+/// Largest value of PS_OFFSET
+pub(crate) const PS_OFFSET_MAX: u16 = 1023;
+
+/// Options for [`Ltr559::tune_ps_thresholds()`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PsTuningOptions {
+    /// Number of PS samples averaged for each condition (default: 8)
+    pub samples: u8,
+    /// Margin kept from each measured level, in percent of the distance
+    /// between them (default: 25)
+    pub margin_percent: u8,
+}
+
+impl Default for PsTuningOptions {
+    fn default() -> Self {
+        PsTuningOptions {
+            samples: 8,
+            margin_percent: 25,
+        }
+    }
+}
+
+/// Result of [`Ltr559::tune_ps_thresholds()`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PsTuning {
+    /// Mean PS counts without target
+    pub baseline: u16,
+    /// Mean PS counts with the target present
+    pub target: u16,
+    /// Programmed PS low threshold (far)
+    pub low: u16,
+    /// Programmed PS high threshold (near)
+    pub high: u16,
+}
+
+/// Version of the [`CalibrationData`] layout
+///
+/// Version 2 added `als_dark_counts`.
+#[cfg(feature = "float")]
+pub const CALIBRATION_VERSION: u8 = 2;
+
+/// Calibration results of a unit, to store and apply again at boot
+///
+/// With the `serde` feature it can be serialized, e.g. with `postcard`.
+/// A non-self-describing format like `postcard` cannot detect data stored
+/// with another layout: it may fail to deserialize or give wrong values.
+/// `version` is therefore the first field, serialized ahead of the others
+/// (with `postcard`, as the first byte), so that it can be read and
+/// compared to [`CALIBRATION_VERSION`] before deserializing the rest.
+#[cfg(feature = "float")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalibrationData {
+    /// Layout version, [`CALIBRATION_VERSION`] when created by this crate
+    pub version: u8,
+    /// PS_OFFSET value (0..=1023)
+    pub ps_offset: u16,
+    /// Measured PS crosstalk in counts, for reference. See
+    /// [`Ltr559::calibrate_ps_offset()`].
+    pub ps_crosstalk: u16,
+    /// Lux scale, applied as the window factor
+    ///
+    /// [`Ltr559::apply_calibration()`] replaces the factor set by an earlier
+    /// [`Ltr559::set_window_factor()`], so it must already include the
+    /// attenuation of the cover glass or diffuser the unit was calibrated
+    /// with.
+    pub lux_scale: f32,
+    /// Lux offset, replacing the one set by [`Ltr559::set_lux_offset()`]
+    pub lux_offset: f32,
+    /// ALS dark counts `(ch0, ch1)`. See
+    /// [`Ltr559::measure_dark_counts()`].
+    pub als_dark_counts: (u16, u16),
+    /// Model converting the PS counts to a distance, if calibrated
+    pub distance: Option<DistanceModel>,
+}
+
+#[cfg(feature = "float")]
+impl Default for CalibrationData {
+    fn default() -> Self {
+        CalibrationData {
+            version: CALIBRATION_VERSION,
+            ps_offset: 0,
+            ps_crosstalk: 0,
+            lux_scale: 1.0,
+            lux_offset: 0.0,
+            als_dark_counts: (0, 0),
+            distance: None,
+        }
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    impl_calibration_methods!([] [] [DelayMs<u32>]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{DelayMock, I2cMock, SharedI2cMock};
+    use crate::{Error, SlaveAddr};
+    use core::cell::RefCell;
+
+    #[test]
+    fn tunes_thresholds_between_levels() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x01;
+        i2c.registers[0x8D] = 100;
+        let i2c = RefCell::new(i2c);
+        let mut sensor = Ltr559::new_device(SharedI2cMock(&i2c), SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        let tuning = sensor
+            .tune_ps_thresholds(&mut delay, PsTuningOptions::default(), || {
+                i2c.borrow_mut().registers[0x8D] = 0x2C;
+                i2c.borrow_mut().registers[0x8E] = 0x01;
+            })
+            .unwrap();
+        assert_eq!(
+            tuning,
+            PsTuning {
+                baseline: 100,
+                target: 300,
+                low: 150,
+                high: 250,
+            }
+        );
+        let regs = i2c.borrow().registers;
+        assert_eq!(regs[0x92], 150);
+        assert_eq!(regs[0x90], 250);
+    }
+
+    #[test]
+    fn programs_crosstalk_as_offset() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x01;
+        i2c.registers[0x8D..0x8F].copy_from_slice(&[0x2C, 0x01]);
+        i2c.registers[0x94] = 50;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert!(matches!(
+            sensor.calibrate_ps_offset(&mut delay, 0),
+            Err(Error::InvalidInputData)
+        ));
+        assert_eq!(sensor.calibrate_ps_offset(&mut delay, 4).unwrap(), 300);
+        assert_eq!(sensor.get_ps_offset().unwrap(), 300);
+
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0xD0, 0x07]);
+        assert_eq!(sensor.calibrate_ps_offset(&mut delay, 16).unwrap(), 2000);
+        assert_eq!(sensor.get_ps_offset().unwrap(), 1023);
+    }
+
+    #[test]
+    fn restores_offset_on_timeout() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x94] = 50;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert!(matches!(
+            sensor.calibrate_ps_offset(&mut delay, 16),
+            Err(Error::Timeout)
+        ));
+        assert_eq!(sensor.get_ps_offset().unwrap(), 50);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn applies_calibration() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let data = CalibrationData {
+            ps_offset: 300,
+            ps_crosstalk: 300,
+            lux_scale: 2.5,
+            lux_offset: -1.0,
+            als_dark_counts: (3, 1),
+            distance: DistanceModel::piecewise(&[(100, 80), (900, 10)]),
+            ..CalibrationData::default()
+        };
+        sensor.apply_calibration(&data).unwrap();
+        assert_eq!(sensor.get_ps_offset().unwrap(), 300);
+        assert_eq!(sensor.window_factor(), 2.5);
+        assert_eq!(sensor.lux_offset(), -1.0);
+        assert_eq!(sensor.dark_counts(), (3, 1));
+
+        let old = CalibrationData {
+            version: 1,
+            ..CalibrationData::default()
+        };
+        assert!(matches!(
+            sensor.apply_calibration(&old),
+            Err(Error::InvalidInputData)
+        ));
+
+        let bad_scale = CalibrationData {
+            ps_offset: 100,
+            lux_scale: 0.0,
+            ..CalibrationData::default()
+        };
+        assert!(matches!(
+            sensor.apply_calibration(&bad_scale),
+            Err(Error::InvalidInputData)
+        ));
+        assert_eq!(sensor.get_ps_offset().unwrap(), 300);
+        assert_eq!(sensor.window_factor(), 2.5);
+    }
+
+    #[test]
+    fn measures_dark_counts() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x88..0x8D].copy_from_slice(&[2, 0, 5, 0, 0x04]);
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert!(sensor.measure_dark_counts(&mut delay, 0).is_err());
+        assert_eq!(sensor.measure_dark_counts(&mut delay, 4).unwrap(), (5, 2));
+        assert_eq!(sensor.dark_counts(), (5, 2));
+        sensor.i2c.registers[0x8C] = 0;
+        assert!(matches!(
+            sensor.measure_dark_counts(&mut delay, 4),
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[test]
+    fn rejects_target_without_signal() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x01;
+        i2c.registers[0x8D] = 100;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert!(sensor
+            .tune_ps_thresholds(&mut delay, PsTuningOptions::default(), || {})
+            .is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Detection of meaningful ambient light changes
+//!
+//! ```
+//! use ltr_559::ChangeDetector;
+//!
+//! // ignore changes below 5 lux or 10%, whichever is larger
+//! let mut detector = ChangeDetector::new(5.0, 0.1);
+//! assert!(detector.update(100.0).is_some());
+//! assert!(detector.update(108.0).is_none());
+//! assert_eq!(detector.update(111.0).unwrap().previous, Some(100.0));
+//! ```
+
+/// Reports a lux change once it exceeds the hysteresis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChangeDetector {
+    absolute: f32,
+    relative: f32,
+    reference: Option<f32>,
+}
+
+impl ChangeDetector {
+    /// Create a new detector.
+    ///
+    /// A change is reported when the new value differs from the last
+    /// reported one by more than `absolute` lux and by more than `relative`
+    /// times the last reported value (e.g. `0.1` for 10%). Negative values
+    /// are treated as 0.
+    pub fn new(absolute: f32, relative: f32) -> Self {
+        ChangeDetector {
+            absolute: absolute.max(0.0),
+            relative: relative.max(0.0),
+            reference: None,
+        }
+    }
+
+    /// Feed a new lux value.
+    ///
+    /// The first value is always reported. Returns `None` while the value
+    /// stays within the hysteresis around the last reported one.
+    pub fn update(&mut self, lux: f32) -> Option<LuxChange> {
+        if let Some(reference) = self.reference {
+            let threshold = self.absolute.max(self.relative * reference.abs());
+            if (lux - reference).abs() <= threshold {
+                return None;
+            }
+        }
+        let change = LuxChange {
+            previous: self.reference,
+            current: lux,
+        };
+        self.reference = Some(lux);
+        Some(change)
+    }
+
+    /// Last reported lux value
+    pub fn reference(&self) -> Option<f32> {
+        self.reference
+    }
+
+    /// Forget the last reported value, so that the next one is reported
+    pub fn reset(&mut self) {
+        self.reference = None;
+    }
+}
+
+/// A lux change reported by [`ChangeDetector`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LuxChange {
+    /// Previously reported lux value, `None` for the first value
+    pub previous: Option<f32>,
+    /// New lux value
+    pub current: f32,
+}
+
+impl LuxChange {
+    /// Signed difference to the previous value, 0 for the first value
+    pub fn delta(&self) -> f32 {
+        self.previous
+            .map_or(0.0, |previous| self.current - previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_hysteresis_dominates_in_low_light() {
+        let mut detector = ChangeDetector::new(2.0, 0.1);
+        assert_eq!(detector.update(1.0).unwrap().delta(), 0.0);
+        assert_eq!(detector.update(2.5), None);
+        assert_eq!(detector.update(3.5).unwrap().delta(), 2.5);
+        assert_eq!(detector.reference(), Some(3.5));
+    }
+
+    #[test]
+    fn relative_hysteresis_dominates_in_bright_light() {
+        let mut detector = ChangeDetector::new(2.0, 0.1);
+        detector.update(1000.0);
+        assert_eq!(detector.update(950.0), None);
+        let change = detector.update(890.0).unwrap();
+        assert_eq!(change.previous, Some(1000.0));
+        assert_eq!(change.current, 890.0);
+        detector.reset();
+        assert!(detector.update(890.0).is_some());
+    }
+}
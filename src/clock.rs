@@ -0,0 +1,79 @@
+//! Monotonic timestamps for measurements and events
+//!
+//! A [`Clock`] provides millisecond timestamps to the driver methods
+//! ending in `_timestamped`, so that readings and events carry the time
+//! they were taken at. Any `FnMut() -> u32` closure is a clock.
+//!
+//! ```
+//! use ltr_559::Clock;
+//!
+//! let mut ticks = 0;
+//! let mut clock = || {
+//!     ticks += 10;
+//!     ticks
+//! };
+//! assert_eq!(clock.now_ms(), 10);
+//! assert_eq!(clock.now_ms(), 20);
+//! ```
+#[cfg(feature = "float")]
+use crate::hal::blocking::i2c;
+#[cfg(feature = "float")]
+use crate::{marker, Error, Ltr559, Measurement};
+
+/// Monotonic millisecond time source
+pub trait Clock {
+    /// Current time in milliseconds. The value may wrap around.
+    fn now_ms(&mut self) -> u32;
+}
+
+impl<F: FnMut() -> u32> Clock for F {
+    fn now_ms(&mut self) -> u32 {
+        self()
+    }
+}
+
+/// A value with the time it was read at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamped<T> {
+    /// Time in milliseconds from the [`Clock`] when the value was read
+    pub timestamp_ms: u32,
+    /// The value
+    pub value: T,
+}
+
+#[cfg(feature = "float")]
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Take a measurement (see [`get_measurement()`](#method.get_measurement))
+    /// stamped with the time of `clock` at the read.
+    pub fn get_measurement_timestamped<C: Clock>(
+        &mut self,
+        clock: &mut C,
+    ) -> Result<Timestamped<Measurement>, Error<E>> {
+        let value = self.get_measurement()?;
+        Ok(Timestamped {
+            timestamp_ms: clock.now_ms(),
+            value,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "float"))]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    #[test]
+    fn measurement_is_stamped() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8D] = 42;
+        let measurement = sensor.get_measurement_timestamped(&mut || 1234).unwrap();
+        assert_eq!(measurement.timestamp_ms, 1234);
+        assert_eq!(measurement.value.ps, 42);
+    }
+}
@@ -0,0 +1,871 @@
+//! Whole-device configuration
+use crate::device_impl::{Register, ALS_WAKEUP_TIME_MS};
+use crate::hal::blocking::{delay::DelayMs, i2c};
+use crate::{
+    ic, AlsContr, AlsGain, AlsIntTime, AlsMeasRate, AlsPersist, Error, InterruptMode,
+    InterruptPinPolarity, LedCurrent, LedDutyCycle, LedPulse, Ltr559, ModeChangeError, PsContr,
+    PsMeasRate, PsPersist, SlaveAddr,
+};
+
+/// Configuration registers in the order they are restored, the ALS and PS
+/// control registers first.
+pub(crate) const STATE_REGISTERS: [u8; 18] = [
+    Register::ALS_CONTR,
+    Register::PS_CONTR,
+    Register::PS_LED,
+    Register::PS_N_PULSES,
+    Register::PS_MEAS_RATE,
+    Register::ALS_MEAS_RATE,
+    Register::INTERRUPT,
+    Register::PS_THRES_UP_0,
+    Register::PS_THRES_UP_1,
+    Register::PS_THRES_LOW_0,
+    Register::PS_THRES_LOW_1,
+    Register::PS_OFFSET_0,
+    Register::PS_OFFSET_1,
+    Register::ALS_THRES_UP_0,
+    Register::ALS_THRES_UP_1,
+    Register::ALS_THRES_LOW_0,
+    Register::ALS_THRES_LOW_1,
+    Register::INTERRUPT_PERSIST,
+];
+
+/// Snapshot of the raw values of every configuration register
+///
+/// Unlike [`Config`] this does not interpret the register values, so it
+/// also preserves reserved bits. The bytes can be stored or transferred to
+/// another unit with [`as_bytes()`](DeviceState::as_bytes) and
+/// [`from_bytes()`](DeviceState::from_bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceState {
+    pub(crate) registers: [u8; DeviceState::SIZE],
+}
+
+impl DeviceState {
+    /// Size of the serialized state in bytes
+    pub const SIZE: usize = STATE_REGISTERS.len();
+
+    /// Raw register values, in the order ALS_CONTR, PS_CONTR, PS_LED,
+    /// PS_N_PULSES, PS_MEAS_RATE, ALS_MEAS_RATE, INTERRUPT, PS thresholds
+    /// (up, low), PS_OFFSET, ALS thresholds (up, low) and INTERRUPT_PERSIST.
+    /// 16-bit values are stored low byte first.
+    pub fn as_bytes(&self) -> &[u8; DeviceState::SIZE] {
+        &self.registers
+    }
+
+    /// Create a state from raw register values as returned by
+    /// [`as_bytes()`](DeviceState::as_bytes).
+    pub fn from_bytes(registers: [u8; DeviceState::SIZE]) -> Self {
+        DeviceState { registers }
+    }
+}
+
+/// Part number in the upper nibble of the PART_ID register
+const PART_NUMBER: u8 = 0x9;
+
+/// Complete device configuration
+///
+/// The default value corresponds to the power-up state of the device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// ALS gain
+    pub als_gain: AlsGain,
+    /// ALS active (otherwise in standby)
+    pub als_active: bool,
+    /// ALS integration time
+    pub als_int_time: AlsIntTime,
+    /// ALS measurement repeat rate
+    pub als_meas_rate: AlsMeasRate,
+    /// PS active (otherwise in standby)
+    pub ps_active: bool,
+    /// PS saturation indicator enabled
+    pub ps_saturation_indicator: bool,
+    /// PS measurement repeat rate
+    pub ps_meas_rate: PsMeasRate,
+    /// LED pulse modulation frequency
+    pub led_pulse: LedPulse,
+    /// LED duty cycle
+    pub led_duty_cycle: LedDutyCycle,
+    /// LED peak current
+    pub led_current: LedCurrent,
+    /// Number of LED pulses per PS measurement (1 to 15)
+    pub ps_n_pulses: u8,
+    /// PS offset (0..=1023)
+    pub ps_offset: u16,
+    /// ALS low threshold in raw format
+    pub als_low_limit: u16,
+    /// ALS high threshold in raw format
+    pub als_high_limit: u16,
+    /// PS low threshold in raw format
+    pub ps_low_limit: u16,
+    /// PS high threshold in raw format
+    pub ps_high_limit: u16,
+    /// ALS interrupt persist
+    pub als_persist: AlsPersist,
+    /// PS interrupt persist
+    pub ps_persist: PsPersist,
+    /// Interrupt pin polarity
+    pub interrupt_polarity: InterruptPinPolarity,
+    /// Interrupt mode
+    pub interrupt_mode: InterruptMode,
+}
+
+/// Rule of [`Config::is_valid()`] broken by a configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Violation {
+    MeasRate,
+    PulseCount,
+    Offset,
+    PsHighLimit,
+    Limits,
+}
+
+/// Set of [`Config`] fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConfigFields(u32);
+
+impl ConfigFields {
+    /// `als_gain` field
+    pub const ALS_GAIN: ConfigFields = ConfigFields(1 << 0);
+    /// `als_active` field
+    pub const ALS_ACTIVE: ConfigFields = ConfigFields(1 << 1);
+    /// `als_int_time` field
+    pub const ALS_INT_TIME: ConfigFields = ConfigFields(1 << 2);
+    /// `als_meas_rate` field
+    pub const ALS_MEAS_RATE: ConfigFields = ConfigFields(1 << 3);
+    /// `ps_active` field
+    pub const PS_ACTIVE: ConfigFields = ConfigFields(1 << 4);
+    /// `ps_saturation_indicator` field
+    pub const PS_SATURATION_INDICATOR: ConfigFields = ConfigFields(1 << 5);
+    /// `ps_meas_rate` field
+    pub const PS_MEAS_RATE: ConfigFields = ConfigFields(1 << 6);
+    /// `led_pulse` field
+    pub const LED_PULSE: ConfigFields = ConfigFields(1 << 7);
+    /// `led_duty_cycle` field
+    pub const LED_DUTY_CYCLE: ConfigFields = ConfigFields(1 << 8);
+    /// `led_current` field
+    pub const LED_CURRENT: ConfigFields = ConfigFields(1 << 9);
+    /// `ps_n_pulses` field
+    pub const PS_N_PULSES: ConfigFields = ConfigFields(1 << 10);
+    /// `ps_offset` field
+    pub const PS_OFFSET: ConfigFields = ConfigFields(1 << 11);
+    /// `als_low_limit` field
+    pub const ALS_LOW_LIMIT: ConfigFields = ConfigFields(1 << 12);
+    /// `als_high_limit` field
+    pub const ALS_HIGH_LIMIT: ConfigFields = ConfigFields(1 << 13);
+    /// `ps_low_limit` field
+    pub const PS_LOW_LIMIT: ConfigFields = ConfigFields(1 << 14);
+    /// `ps_high_limit` field
+    pub const PS_HIGH_LIMIT: ConfigFields = ConfigFields(1 << 15);
+    /// `als_persist` field
+    pub const ALS_PERSIST: ConfigFields = ConfigFields(1 << 16);
+    /// `ps_persist` field
+    pub const PS_PERSIST: ConfigFields = ConfigFields(1 << 17);
+    /// `interrupt_polarity` field
+    pub const INTERRUPT_POLARITY: ConfigFields = ConfigFields(1 << 18);
+    /// `interrupt_mode` field
+    pub const INTERRUPT_MODE: ConfigFields = ConfigFields(1 << 19);
+
+    /// Whether the set contains all fields of `other`
+    pub fn contains(self, other: ConfigFields) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether the set is empty
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Raw bit mask, with bit N set for the N-th field of [`Config`]
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// Result of a failed [`Ltr559::verify_config()`] call
+#[derive(Debug)]
+pub enum ConfigMismatch<E> {
+    /// Reading the configuration failed
+    Error(Error<E>),
+    /// The device configuration differs from the expected one.
+    Mismatch {
+        /// Fields that differ
+        fields: ConfigFields,
+        /// Configuration read from the device
+        actual: Config,
+    },
+}
+
+impl<E> From<Error<E>> for ConfigMismatch<E> {
+    fn from(error: Error<E>) -> Self {
+        ConfigMismatch::Error(error)
+    }
+}
+
+/// Build a [`Config`] at compile time.
+///
+/// Fields that are not given keep their power-up values. The configuration
+/// is checked with [`Config::validated()`] in a constant, so invalid
+/// combinations are compile errors and the result can be used in `const`
+/// and `static` initializers.
+///
+/// ```
+/// use ltr_559::{ltr559_config, AlsGain, AlsIntTime, AlsMeasRate, Config};
+///
+/// const CONFIG: Config = ltr559_config! {
+///     als_gain: AlsGain::Gain4x,
+///     als_active: true,
+///     als_int_time: AlsIntTime::_200ms,
+///     als_meas_rate: AlsMeasRate::_500ms,
+/// };
+/// ```
+///
+/// ```compile_fail
+/// use ltr_559::{ltr559_config, AlsIntTime, AlsMeasRate, Config};
+///
+/// // the measurement rate is shorter than the integration time
+/// const CONFIG: Config = ltr559_config! {
+///     als_int_time: AlsIntTime::_400ms,
+///     als_meas_rate: AlsMeasRate::_100ms,
+/// };
+/// ```
+#[macro_export]
+macro_rules! ltr559_config {
+    ($($field:ident: $value:expr),* $(,)?) => {{
+        const CONFIG: $crate::Config = $crate::Config {
+            $($field: $value,)*
+            ..$crate::Config::power_up()
+        }
+        .validated();
+        CONFIG
+    }};
+}
+
+/// Power-up state of the device
+const POWER_UP: Config = Config {
+    als_gain: AlsGain::Gain1x,
+    als_active: false,
+    als_int_time: AlsIntTime::_100ms,
+    als_meas_rate: AlsMeasRate::_500ms,
+    ps_active: false,
+    ps_saturation_indicator: false,
+    ps_meas_rate: PsMeasRate::_100ms,
+    led_pulse: LedPulse::Pulse60,
+    led_duty_cycle: LedDutyCycle::_100,
+    led_current: LedCurrent::_100mA,
+    ps_n_pulses: 1,
+    ps_offset: 0,
+    als_low_limit: 0,
+    als_high_limit: 0xFFFF,
+    ps_low_limit: 0,
+    ps_high_limit: 0x07FF,
+    als_persist: AlsPersist::EveryTime,
+    ps_persist: PsPersist::EveryTime,
+    interrupt_polarity: InterruptPinPolarity::Low,
+    interrupt_mode: InterruptMode::Inactive,
+};
+
+impl Default for Config {
+    fn default() -> Self {
+        POWER_UP
+    }
+}
+
+impl Config {
+    /// Fields whose values differ between `self` and `other`
+    pub fn diff(&self, other: &Config) -> ConfigFields {
+        let mut fields = ConfigFields::default();
+        if self.als_gain != other.als_gain {
+            fields.0 |= ConfigFields::ALS_GAIN.0;
+        }
+        if self.als_active != other.als_active {
+            fields.0 |= ConfigFields::ALS_ACTIVE.0;
+        }
+        if self.als_int_time != other.als_int_time {
+            fields.0 |= ConfigFields::ALS_INT_TIME.0;
+        }
+        if self.als_meas_rate != other.als_meas_rate {
+            fields.0 |= ConfigFields::ALS_MEAS_RATE.0;
+        }
+        if self.ps_active != other.ps_active {
+            fields.0 |= ConfigFields::PS_ACTIVE.0;
+        }
+        if self.ps_saturation_indicator != other.ps_saturation_indicator {
+            fields.0 |= ConfigFields::PS_SATURATION_INDICATOR.0;
+        }
+        if self.ps_meas_rate != other.ps_meas_rate {
+            fields.0 |= ConfigFields::PS_MEAS_RATE.0;
+        }
+        if self.led_pulse != other.led_pulse {
+            fields.0 |= ConfigFields::LED_PULSE.0;
+        }
+        if self.led_duty_cycle != other.led_duty_cycle {
+            fields.0 |= ConfigFields::LED_DUTY_CYCLE.0;
+        }
+        if self.led_current != other.led_current {
+            fields.0 |= ConfigFields::LED_CURRENT.0;
+        }
+        if self.ps_n_pulses != other.ps_n_pulses {
+            fields.0 |= ConfigFields::PS_N_PULSES.0;
+        }
+        if self.ps_offset != other.ps_offset {
+            fields.0 |= ConfigFields::PS_OFFSET.0;
+        }
+        if self.als_low_limit != other.als_low_limit {
+            fields.0 |= ConfigFields::ALS_LOW_LIMIT.0;
+        }
+        if self.als_high_limit != other.als_high_limit {
+            fields.0 |= ConfigFields::ALS_HIGH_LIMIT.0;
+        }
+        if self.ps_low_limit != other.ps_low_limit {
+            fields.0 |= ConfigFields::PS_LOW_LIMIT.0;
+        }
+        if self.ps_high_limit != other.ps_high_limit {
+            fields.0 |= ConfigFields::PS_HIGH_LIMIT.0;
+        }
+        if self.als_persist != other.als_persist {
+            fields.0 |= ConfigFields::ALS_PERSIST.0;
+        }
+        if self.ps_persist != other.ps_persist {
+            fields.0 |= ConfigFields::PS_PERSIST.0;
+        }
+        if self.interrupt_polarity != other.interrupt_polarity {
+            fields.0 |= ConfigFields::INTERRUPT_POLARITY.0;
+        }
+        if self.interrupt_mode != other.interrupt_mode {
+            fields.0 |= ConfigFields::INTERRUPT_MODE.0;
+        }
+        fields
+    }
+
+    /// The power-up configuration, same as `Config::default()` but usable
+    /// in constants.
+    pub const fn power_up() -> Self {
+        POWER_UP
+    }
+
+    /// Whether the configuration can be written to the device: the ALS
+    /// measurement rate is not shorter than the integration time, the PS
+    /// pulse count, offset and high threshold are in range and no low
+    /// threshold is above its high threshold.
+    pub const fn is_valid(&self) -> bool {
+        self.violation().is_none()
+    }
+
+    /// Check the configuration at compile time when used in a constant.
+    ///
+    /// Panics if the configuration is not [valid](#method.is_valid). See
+    /// [`ltr559_config!`](crate::ltr559_config).
+    pub const fn validated(self) -> Self {
+        match self.violation() {
+            Some(Violation::MeasRate) => {
+                panic!("ALS measurement rate is shorter than the integration time")
+            }
+            Some(Violation::PulseCount) => panic!("PS pulse count out of range (1..=15)"),
+            Some(Violation::Offset) => panic!("PS offset out of range (0..=1023)"),
+            Some(Violation::PsHighLimit) => panic!("PS high threshold out of range (0..=2047)"),
+            Some(Violation::Limits) => panic!("low threshold above high threshold"),
+            None => self,
+        }
+    }
+
+    /// First rule broken by the configuration
+    const fn violation(&self) -> Option<Violation> {
+        if self.als_meas_rate.milliseconds() < self.als_int_time.milliseconds() {
+            Some(Violation::MeasRate)
+        } else if self.ps_n_pulses == 0 || self.ps_n_pulses > 15 {
+            Some(Violation::PulseCount)
+        } else if self.ps_offset > 1023 {
+            Some(Violation::Offset)
+        } else if self.ps_high_limit > 0x07FF {
+            Some(Violation::PsHighLimit)
+        } else if self.als_low_limit > self.als_high_limit || self.ps_low_limit > self.ps_high_limit
+        {
+            Some(Violation::Limits)
+        } else {
+            None
+        }
+    }
+
+    /// Low-power profile: ALS only, 1x gain and 50 ms integration every
+    /// 2000 ms. Suited for slowly changing ambient light.
+    pub const fn low_power() -> Self {
+        Config {
+            als_active: true,
+            als_int_time: AlsIntTime::_50ms,
+            als_meas_rate: AlsMeasRate::_2000ms,
+            ..POWER_UP
+        }
+    }
+
+    /// High-accuracy profile: ALS only, 4x gain (0.25 lux to 16k lux) and
+    /// 400 ms integration every 500 ms, averaging out flicker.
+    pub const fn high_accuracy() -> Self {
+        Config {
+            als_gain: AlsGain::Gain4x,
+            als_active: true,
+            als_int_time: AlsIntTime::_400ms,
+            als_meas_rate: AlsMeasRate::_500ms,
+            ..POWER_UP
+        }
+    }
+
+    /// The settings of the Pimoroni LTR-559 breakout and Enviro board
+    /// libraries: ALS with 4x gain and 50 ms integration every 50 ms, PS
+    /// every 100 ms with the saturation indicator and one 30 kHz pulse at
+    /// 50 mA and 100% duty cycle.
+    pub const fn pimoroni_defaults() -> Self {
+        Config {
+            als_gain: AlsGain::Gain4x,
+            als_active: true,
+            als_int_time: AlsIntTime::_50ms,
+            als_meas_rate: AlsMeasRate::_50ms,
+            ps_active: true,
+            ps_saturation_indicator: true,
+            ps_meas_rate: PsMeasRate::_100ms,
+            led_pulse: LedPulse::Pulse30,
+            led_duty_cycle: LedDutyCycle::_100,
+            led_current: LedCurrent::_50mA,
+            ps_n_pulses: 1,
+            ..POWER_UP
+        }
+    }
+}
+
+impl<I2C, E> Ltr559<I2C, ic::Ltr559>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    /// Create a new instance of the device and apply `config` to it.
+    ///
+    /// The configuration is read back and compared to `config`. If the ALS
+    /// or the PS is activated, this waits for the wakeup time so that the
+    /// device is measuring when it returns.
+    ///
+    /// In case of error the I²C bus is returned inside the error.
+    pub fn with_config<D: DelayMs<u32>>(
+        i2c: I2C,
+        address: SlaveAddr,
+        config: &Config,
+        delay: &mut D,
+    ) -> Result<Self, ModeChangeError<E, I2C>> {
+        Self::new_device(i2c, address).configure(config, delay)
+    }
+
+    /// Create a new instance of the device, reset it and apply `config` to it.
+    ///
+    /// The part ID is checked first and nothing is written if it does not
+    /// belong to an LTR-559. The device is then reset through software,
+    /// which takes the startup time to complete, and configured as in
+    /// [`with_config()`](#method.with_config).
+    ///
+    /// In case of error the I²C bus is returned inside the error.
+    pub fn new_with_config<D: DelayMs<u32>>(
+        i2c: I2C,
+        address: SlaveAddr,
+        config: &Config,
+        delay: &mut D,
+    ) -> Result<Self, ModeChangeError<E, I2C>> {
+        let mut sensor = Self::new_device(i2c, address);
+        let reset = sensor.get_part_id().and_then(|part_id| {
+            if part_id >> 4 != PART_NUMBER {
+                return Ok(Some(part_id));
+            }
+            sensor.sw_reset(delay)?;
+            Ok(None)
+        });
+        match reset {
+            Ok(None) => sensor.configure(config, delay),
+            Ok(Some(part_id)) => Err(ModeChangeError::WrongPartId(part_id, sensor.destroy())),
+            Err(e) => Err(sensor.into_mode_change_error(e)),
+        }
+    }
+
+    fn configure<D: DelayMs<u32>>(
+        mut self,
+        config: &Config,
+        delay: &mut D,
+    ) -> Result<Self, ModeChangeError<E, I2C>> {
+        let result = self.apply_config(config).and_then(|_| {
+            if config.als_active || config.ps_active {
+                delay.delay_ms(ALS_WAKEUP_TIME_MS);
+            }
+            self.read_config()
+        });
+        match result {
+            Ok(actual) if actual == *config => Ok(self),
+            Ok(_) => Err(ModeChangeError::ConfigMismatch(self.destroy())),
+            Err(e) => Err(self.into_mode_change_error(e)),
+        }
+    }
+
+    fn into_mode_change_error(self, error: Error<E>) -> ModeChangeError<E, I2C> {
+        match error {
+            Error::I2C(e) => ModeChangeError::I2C(e, self.destroy()),
+            _ => ModeChangeError::InvalidInputData(self.destroy()),
+        }
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    /// Write the whole configuration.
+    ///
+    /// The ALS and the PS are put in standby first, then every register is
+    /// written and finally the ALS and the PS are activated as requested.
+    /// Note that no wakeup time is waited for.
+    ///
+    /// Returns `Error::InvalidInputData` without writing anything if the
+    /// configuration is not [valid](Config::is_valid).
+    pub fn apply_config(&mut self, config: &Config) -> Result<(), Error<E>> {
+        if !config.is_valid() {
+            return Err(Error::InvalidInputData);
+        }
+        self.set_als_contr(config.als_gain, false, false)?;
+        self.set_ps_contr(config.ps_saturation_indicator, false)?;
+        self.set_ps_led(config.led_pulse, config.led_duty_cycle, config.led_current)?;
+        self.set_ps_n_pulses(config.ps_n_pulses)?;
+        self.set_ps_meas_rate(config.ps_meas_rate)?;
+        self.set_als_meas_rate(config.als_int_time, config.als_meas_rate)?;
+        self.set_ps_offset(config.ps_offset)?;
+        self.set_als_low_limit_raw(config.als_low_limit)?;
+        self.set_als_high_limit_raw(config.als_high_limit)?;
+        self.set_ps_low_limit_raw(config.ps_low_limit)?;
+        self.set_ps_high_limit_raw(config.ps_high_limit)?;
+        self.set_interrupt_persist(config.als_persist, config.ps_persist)?;
+        self.set_interrupt(config.interrupt_polarity, config.interrupt_mode)?;
+        self.set_als_contr(config.als_gain, false, config.als_active)?;
+        self.set_ps_contr(config.ps_saturation_indicator, config.ps_active)?;
+        self.applied_config = Some(*config);
+        Ok(())
+    }
+
+    /// Re-apply the last configuration written with
+    /// [`apply_config()`](#method.apply_config) if the device has been reset.
+    ///
+    /// A reset is detected by reading ALS_CONTR and PS_CONTR and comparing
+    /// them to the stored configuration, which catches the device reverting
+    /// to its power-up state while the ALS or the PS should be active, or
+    /// with a non-default gain. Changes made through the individual setters
+    /// after `apply_config()` are not tracked and are not restored.
+    ///
+    /// Returns whether the configuration was re-applied. Note that no wakeup
+    /// time is waited for.
+    pub fn ensure_configured(&mut self) -> Result<bool, Error<E>> {
+        let config = match self.applied_config {
+            Some(config) => config,
+            None => return Ok(false),
+        };
+        let als_contr = self.read_register(Register::ALS_CONTR)?;
+        let ps_contr = PsContr::from_register(self.read_register(Register::PS_CONTR)?);
+        let intact = match AlsContr::from_register(als_contr) {
+            Some(als_contr) => {
+                als_contr.gain == config.als_gain
+                    && als_contr.active == config.als_active
+                    && ps_contr.active == config.ps_active
+                    && ps_contr.saturation_indicator == config.ps_saturation_indicator
+            }
+            None => false,
+        };
+        if intact {
+            return Ok(false);
+        }
+        self.reset_internal_driver_state();
+        self.apply_config(&config)?;
+        Ok(true)
+    }
+
+    /// Last configuration written with [`apply_config()`](#method.apply_config)
+    pub fn applied_config(&self) -> Option<&Config> {
+        self.applied_config.as_ref()
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    impl_state_methods!([] []);
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+{
+    /// Read back the configuration and compare it to `config`.
+    ///
+    /// This catches brown-outs and unexpected resets that silently reverted
+    /// the device to its power-up configuration. All differing fields are
+    /// reported.
+    pub fn verify_config(&mut self, config: &Config) -> Result<(), ConfigMismatch<E>> {
+        let actual = self.read_config()?;
+        let fields = config.diff(&actual);
+        if fields.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigMismatch::Mismatch { fields, actual })
+        }
+    }
+
+    /// Reconstruct the configuration from the device registers.
+    ///
+    /// Returns `Error::InvalidInputData` if the ALS gain register holds a
+    /// reserved value.
+    pub fn read_config(&mut self) -> Result<Config, Error<E>> {
+        let als_contr = self.get_als_contr()?;
+        let ps_contr = self.get_ps_contr()?;
+        let (led_pulse, led_duty_cycle, led_current) = self.get_ps_led()?;
+        let (als_int_time, als_meas_rate) = self.get_als_meas_rate()?;
+        let (als_persist, ps_persist) = self.get_interrupt_persist()?;
+        let (interrupt_polarity, interrupt_mode) = self.get_interrupt()?;
+        let thresholds = self.get_thresholds()?;
+        Ok(Config {
+            als_gain: als_contr.gain,
+            als_active: als_contr.active,
+            als_int_time,
+            als_meas_rate,
+            ps_active: ps_contr.active,
+            ps_saturation_indicator: ps_contr.saturation_indicator,
+            ps_meas_rate: self.get_ps_meas_rate()?,
+            led_pulse,
+            led_duty_cycle,
+            led_current,
+            ps_n_pulses: self.get_ps_n_pulses()?,
+            ps_offset: self.get_ps_offset()?,
+            als_low_limit: thresholds.als_low,
+            als_high_limit: thresholds.als_high,
+            ps_low_limit: thresholds.ps_low,
+            ps_high_limit: thresholds.ps_high,
+            als_persist,
+            ps_persist,
+            interrupt_polarity,
+            interrupt_mode,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{DelayMock, I2cMock};
+
+    fn config() -> Config {
+        Config {
+            als_gain: AlsGain::Gain4x,
+            als_active: true,
+            als_int_time: AlsIntTime::_50ms,
+            als_meas_rate: AlsMeasRate::_100ms,
+            ps_active: true,
+            ps_n_pulses: 4,
+            ps_offset: 300,
+            als_high_limit: 20000,
+            ps_high_limit: 1500,
+            als_persist: AlsPersist::_3v,
+            interrupt_mode: InterruptMode::Both,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn with_config_applies_and_verifies() {
+        let mut delay = DelayMock::default();
+        let sensor =
+            Ltr559::with_config(I2cMock::new(), SlaveAddr::default(), &config(), &mut delay)
+                .ok()
+                .unwrap();
+        assert_eq!(sensor.als_gain, AlsGain::Gain4x);
+        assert_eq!(sensor.als_int, AlsIntTime::_50ms);
+        assert_eq!(delay.elapsed_ms, 10);
+    }
+
+    #[test]
+    fn with_config_reports_mismatch() {
+        let mut i2c = I2cMock::new();
+        // PS_OFFSET_0 keeps its power-up value
+        i2c.read_only = Some(0x95);
+        let mut delay = DelayMock::default();
+        let result = Ltr559::with_config(i2c, SlaveAddr::default(), &config(), &mut delay);
+        match result {
+            Err(ModeChangeError::ConfigMismatch(i2c)) => assert_eq!(i2c.registers[0x95], 0),
+            _ => panic!("expected a configuration mismatch"),
+        }
+    }
+
+    #[test]
+    fn with_config_rejects_invalid_input() {
+        for config in [
+            Config {
+                ps_offset: 1024,
+                ..config()
+            },
+            Config {
+                ps_high_limit: 4000,
+                ..config()
+            },
+        ] {
+            let mut delay = DelayMock::default();
+            let result =
+                Ltr559::with_config(I2cMock::new(), SlaveAddr::default(), &config, &mut delay);
+            match result {
+                Err(ModeChangeError::InvalidInputData(i2c)) => {
+                    assert!(i2c.registers.iter().all(|&r| r == 0))
+                }
+                _ => panic!("expected invalid input data"),
+            }
+        }
+    }
+
+    #[test]
+    fn apply_config_rejects_invalid_config_before_writing() {
+        let invalid = [
+            Config {
+                ps_high_limit: 0x0800,
+                ..config()
+            },
+            Config {
+                als_low_limit: 2,
+                als_high_limit: 1,
+                ..config()
+            },
+            Config {
+                ps_low_limit: 2,
+                ps_high_limit: 1,
+                ..config()
+            },
+            Config {
+                als_int_time: AlsIntTime::_400ms,
+                als_meas_rate: AlsMeasRate::_100ms,
+                ..config()
+            },
+        ];
+        for config in &invalid {
+            assert!(!config.is_valid());
+            let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+            assert!(matches!(
+                sensor.apply_config(config),
+                Err(Error::InvalidInputData)
+            ));
+            assert!(sensor.destroy().registers.iter().all(|&r| r == 0));
+        }
+        assert!(config().is_valid());
+    }
+
+    #[test]
+    fn read_config_returns_applied_config() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.apply_config(&config()).unwrap();
+        assert_eq!(sensor.read_config().unwrap(), config());
+    }
+
+    #[test]
+    fn new_with_config_resets_and_configures() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x86] = 0x92;
+        i2c.reset_completes = true;
+        let mut delay = DelayMock::default();
+        let sensor = Ltr559::new_with_config(i2c, SlaveAddr::default(), &config(), &mut delay)
+            .ok()
+            .unwrap();
+        assert_eq!(sensor.als_gain, AlsGain::Gain4x);
+        assert_eq!(delay.elapsed_ms, 110);
+    }
+
+    #[test]
+    fn new_with_config_checks_part_id() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x86] = 0x81;
+        let mut delay = DelayMock::default();
+        let result = Ltr559::new_with_config(i2c, SlaveAddr::default(), &config(), &mut delay);
+        match result {
+            Err(ModeChangeError::WrongPartId(0x81, i2c)) => assert_eq!(i2c.registers[0x80], 0),
+            _ => panic!("unexpected result"),
+        }
+        assert_eq!(delay.elapsed_ms, 0);
+    }
+
+    #[test]
+    fn default_matches_power_up_registers() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.apply_config(&Config::default()).unwrap();
+        let regs = sensor.destroy().registers;
+        assert_eq!(regs[0x82], 0x7F);
+        assert_eq!(regs[0x84], 0x02);
+        assert_eq!(regs[0x85], 0x03);
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for config in [
+            Config::low_power(),
+            Config::high_accuracy(),
+            Config::pimoroni_defaults(),
+        ] {
+            let mut delay = DelayMock::default();
+            let result =
+                Ltr559::with_config(I2cMock::new(), SlaveAddr::default(), &config, &mut delay);
+            assert!(result.is_ok());
+        }
+    }
+
+    #[test]
+    fn restored_state_matches_saved_state() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.apply_config(&config()).unwrap();
+        let state = sensor.save_state().unwrap();
+        let mut other = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        other
+            .restore_state(&DeviceState::from_bytes(*state.as_bytes()))
+            .unwrap();
+        assert_eq!(other.read_config().unwrap(), config());
+        assert_eq!(other.als_gain, AlsGain::Gain4x);
+        assert_eq!(other.als_int, AlsIntTime::_50ms);
+        assert_eq!(other.save_state().unwrap(), state);
+    }
+
+    #[test]
+    fn verify_config_reports_all_differing_fields() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.apply_config(&config()).unwrap();
+        assert!(sensor.verify_config(&config()).is_ok());
+        let mut i2c = sensor.destroy();
+        i2c.registers[0x80] = 0;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        match sensor.verify_config(&config()) {
+            Err(ConfigMismatch::Mismatch { fields, actual }) => {
+                assert!(fields.contains(ConfigFields::ALS_GAIN));
+                assert!(fields.contains(ConfigFields::ALS_ACTIVE));
+                assert!(!fields.contains(ConfigFields::PS_ACTIVE));
+                assert!(!actual.als_active);
+            }
+            _ => panic!("unexpected result"),
+        }
+    }
+
+    #[test]
+    fn ensure_configured_reapplies_after_reset() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        assert!(!sensor.ensure_configured().unwrap());
+        sensor.apply_config(&config()).unwrap();
+        assert!(!sensor.ensure_configured().unwrap());
+        sensor.i2c.registers = [0; 256];
+        assert!(sensor.ensure_configured().unwrap());
+        assert_eq!(sensor.read_config().unwrap(), config());
+        assert_eq!(sensor.applied_config(), Some(&config()));
+    }
+
+    #[test]
+    fn config_macro_builds_validated_constant() {
+        static CONFIG: Config = ltr559_config! {
+            als_active: true,
+            als_int_time: AlsIntTime::_50ms,
+            als_meas_rate: AlsMeasRate::_2000ms,
+        };
+        assert_eq!(CONFIG, Config::low_power());
+        assert_eq!(Config::power_up(), Config::default());
+    }
+}
@@ -0,0 +1,134 @@
+//! Day/night detection, e.g. for streetlight or camera IR-cut control
+//!
+//! ```
+//! use ltr_559::{DayNight, DayNightTransition};
+//!
+//! // switch around 50 lux ± 10 lux after one minute
+//! let mut day_night = DayNight::new(50.0, 10.0, 60_000);
+//! assert_eq!(day_night.update(200.0, 0), None);
+//! assert_eq!(day_night.update(200.0, 60_000), Some(DayNightTransition::Dawn));
+//! assert_eq!(day_night.update(45.0, 70_000), None);
+//! assert_eq!(day_night.update(30.0, 80_000), None);
+//! assert_eq!(day_night.update(30.0, 140_000), Some(DayNightTransition::Dusk));
+//! ```
+
+/// Daylight phase determined by [`DayNight`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DayPhase {
+    /// Bright
+    Day,
+    /// Dark
+    Night,
+}
+
+/// Phase change reported by [`DayNight`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DayNightTransition {
+    /// Change to [`DayPhase::Day`]
+    Dawn,
+    /// Change to [`DayPhase::Night`]
+    Dusk,
+}
+
+/// Tracks the daylight phase with hysteresis and a minimum dwell time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayNight {
+    threshold: f32,
+    hysteresis: f32,
+    dwell_ms: u32,
+    phase: Option<DayPhase>,
+    candidate: Option<(DayPhase, u32)>,
+}
+
+impl DayNight {
+    /// Create a new detector.
+    ///
+    /// It is day once the lux stays at or above `threshold + hysteresis`
+    /// and night once it stays at or below `threshold - hysteresis`, in
+    /// both cases for at least `dwell_ms` milliseconds. A negative
+    /// hysteresis is treated as 0.
+    pub fn new(threshold: f32, hysteresis: f32, dwell_ms: u32) -> Self {
+        DayNight {
+            threshold,
+            hysteresis: hysteresis.max(0.0),
+            dwell_ms,
+            phase: None,
+            candidate: None,
+        }
+    }
+
+    /// Feed a lux value measured at `now_ms`.
+    ///
+    /// `now_ms` is a monotonic millisecond timestamp from the application
+    /// (it may wrap around). Returns the transition completed by this
+    /// value, if any. The first phase settled on is reported as well.
+    pub fn update(&mut self, lux: f32, now_ms: u32) -> Option<DayNightTransition> {
+        let target = if lux >= self.threshold + self.hysteresis {
+            DayPhase::Day
+        } else if lux <= self.threshold - self.hysteresis {
+            DayPhase::Night
+        } else {
+            self.candidate = None;
+            return None;
+        };
+        if self.phase == Some(target) {
+            self.candidate = None;
+            return None;
+        }
+        let since = match self.candidate {
+            Some((phase, since)) if phase == target => since,
+            _ => {
+                self.candidate = Some((target, now_ms));
+                now_ms
+            }
+        };
+        if now_ms.wrapping_sub(since) < self.dwell_ms {
+            return None;
+        }
+        self.phase = Some(target);
+        self.candidate = None;
+        Some(match target {
+            DayPhase::Day => DayNightTransition::Dawn,
+            DayPhase::Night => DayNightTransition::Dusk,
+        })
+    }
+
+    /// Current phase, `None` until the first one is settled on
+    pub fn phase(&self) -> Option<DayPhase> {
+        self.phase
+    }
+
+    /// Forget the current phase and any pending transition
+    pub fn reset(&mut self) {
+        self.phase = None;
+        self.candidate = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hysteresis_band_restarts_dwell() {
+        let mut day_night = DayNight::new(50.0, 10.0, 1000);
+        assert_eq!(day_night.update(10.0, 0), None);
+        assert_eq!(day_night.update(55.0, 500), None);
+        assert_eq!(day_night.update(10.0, 1200), None);
+        assert_eq!(day_night.phase(), None);
+        assert_eq!(day_night.update(10.0, 2200), Some(DayNightTransition::Dusk));
+        assert_eq!(day_night.phase(), Some(DayPhase::Night));
+        assert_eq!(day_night.update(0.0, 5000), None);
+    }
+
+    #[test]
+    fn zero_dwell_switches_immediately() {
+        let mut day_night = DayNight::new(50.0, 10.0, 0);
+        assert_eq!(day_night.update(60.0, 0), Some(DayNightTransition::Dawn));
+        assert_eq!(day_night.update(40.0, 1), Some(DayNightTransition::Dusk));
+        day_night.reset();
+        assert_eq!(day_night.phase(), None);
+    }
+}
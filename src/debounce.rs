@@ -0,0 +1,93 @@
+//! Time-based debouncing of PS "near" interrupts
+//!
+//! The hardware persist setting only requires a number of consecutive
+//! values above the threshold. A [`PsDebouncer`] additionally locks out
+//! further "near" triggers for a time window after one was accepted, e.g.
+//! to ignore a hand lingering at the edge of the detection range.
+//!
+//! ```
+//! use ltr_559::PsDebouncer;
+//!
+//! let mut debouncer = PsDebouncer::new(1000);
+//! assert!(debouncer.accept(0));
+//! assert!(!debouncer.accept(400));
+//! assert!(debouncer.accept(1000));
+//! ```
+#[cfg(feature = "heapless")]
+use crate::{Event, Events};
+
+/// Accepts at most one PS "near" trigger per time window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PsDebouncer {
+    window_ms: u32,
+    last_accepted: Option<u32>,
+}
+
+impl PsDebouncer {
+    /// Create a new debouncer with a lockout of `window_ms` milliseconds
+    pub fn new(window_ms: u32) -> Self {
+        PsDebouncer {
+            window_ms,
+            last_accepted: None,
+        }
+    }
+
+    /// Report a "near" trigger at `now_ms` and return whether to act on it.
+    ///
+    /// `now_ms` is a monotonic millisecond timestamp from the application
+    /// (it may wrap around). A trigger is accepted if none was accepted in
+    /// the last `window_ms` milliseconds. Suppressed triggers do not extend
+    /// the lockout.
+    pub fn accept(&mut self, now_ms: u32) -> bool {
+        if let Some(last) = self.last_accepted {
+            if now_ms.wrapping_sub(last) < self.window_ms {
+                return false;
+            }
+        }
+        self.last_accepted = Some(now_ms);
+        true
+    }
+
+    /// Remove the [`Event::PsNear`] events that are locked out from
+    /// `events` (`heapless` feature).
+    #[cfg(feature = "heapless")]
+    pub fn filter(&mut self, events: &mut Events, now_ms: u32) {
+        events.retain(|event| match event {
+            Event::PsNear(_) => self.accept(now_ms),
+            _ => true,
+        });
+    }
+
+    /// End the current lockout
+    pub fn reset(&mut self) {
+        self.last_accepted = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suppressed_triggers_do_not_extend_lockout() {
+        let mut debouncer = PsDebouncer::new(100);
+        assert!(debouncer.accept(u32::MAX - 10));
+        assert!(!debouncer.accept(50));
+        assert!(debouncer.accept(89));
+        debouncer.reset();
+        assert!(debouncer.accept(90));
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn filter_keeps_other_events() {
+        let mut debouncer = PsDebouncer::new(100);
+        let mut events = Events::new();
+        events.push(Event::PsNear(900)).unwrap();
+        events.push(Event::AlsSaturated).unwrap();
+        debouncer.filter(&mut events, 0);
+        assert_eq!(events.len(), 2);
+        debouncer.filter(&mut events, 10);
+        assert_eq!(&events[..], &[Event::AlsSaturated]);
+    }
+}
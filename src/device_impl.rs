@@ -1,43 +1,54 @@
-use crate::hal::blocking::i2c;
+use crate::calc::{LuxAlgorithm, LuxCoefficients};
+use crate::hal::blocking::{delay::DelayMs, i2c};
+use crate::hal::digital::v2::InputPin;
 use crate::{
-    ic, marker, AlsGain, AlsIntTime, AlsMeasRate, AlsPersist, Error, InterruptMode,
-    InterruptPinPolarity, LedCurrent, LedDutyCycle, LedPulse, Ltr559, PhantomData, PsMeasRate,
-    PsPersist, SlaveAddr, Status,
+    ic, marker, AlsGain, AlsIntTime, AlsMeasRate, AlsPersist, DiagnosticCounters, Error,
+    InterruptCause, InterruptMode, InterruptPinPolarity, Ltr559, PhantomData, PsMeasRate,
+    PsPersist, SlaveAddr, Status, WindowMode,
 };
+#[cfg(feature = "float")]
+use crate::{LuxAverage, Measurement};
 
-struct Register;
+pub(crate) struct Register;
+// the high bytes of the ALS data are read through `read_u16`
 impl Register {
-    const ALS_CONTR: u8 = 0x80;
-    const PS_CONTR: u8 = 0x81;
-    const PS_LED: u8 = 0x82;
-    const PS_N_PULSES: u8 = 0x83;
-    const PS_MEAS_RATE: u8 = 0x84;
-    const ALS_MEAS_RATE: u8 = 0x85;
-    const PART_ID: u8 = 0x86;
-    const MANUFAC_ID: u8 = 0x87;
-    const ALS_DATA_CH1_0: u8 = 0x88;
-    const ALS_DATA_CH1_1: u8 = 0x89;
-    const ALS_DATA_CH0_0: u8 = 0x8A;
-    const ALS_DATA_CH0_1: u8 = 0x8B;
-    const ALS_PS_STATUS: u8 = 0x8C;
-    const PS_DATA_0: u8 = 0x8D;
-    const PS_DATA_1: u8 = 0x8E;
-    const INTERRUPT: u8 = 0x8F;
-    const PS_THRES_UP_0: u8 = 0x90;
-    const PS_THRES_UP_1: u8 = 0x91;
-    const PS_THRES_LOW_0: u8 = 0x92;
-    const PS_THRES_LOW_1: u8 = 0x93;
-    const PS_OFFSET_0: u8 = 0x94;
-    const PS_OFFSET_1: u8 = 0x95;
-    const ALS_THRES_UP_0: u8 = 0x97;
-    const ALS_THRES_UP_1: u8 = 0x98;
-    const ALS_THRES_LOW_0: u8 = 0x99;
-    const ALS_THRES_LOW_1: u8 = 0x9A;
-    const INTERRUPT_PERSIST: u8 = 0x9E;
+    pub(crate) const ALS_CONTR: u8 = 0x80;
+    pub(crate) const PS_CONTR: u8 = 0x81;
+    pub(crate) const PS_LED: u8 = 0x82;
+    pub(crate) const PS_N_PULSES: u8 = 0x83;
+    pub(crate) const PS_MEAS_RATE: u8 = 0x84;
+    pub(crate) const ALS_MEAS_RATE: u8 = 0x85;
+    pub(crate) const PART_ID: u8 = 0x86;
+    pub(crate) const MANUFAC_ID: u8 = 0x87;
+    pub(crate) const ALS_DATA_CH1_0: u8 = 0x88;
+    pub(crate) const ALS_DATA_CH0_0: u8 = 0x8A;
+    pub(crate) const ALS_PS_STATUS: u8 = 0x8C;
+    pub(crate) const PS_DATA_0: u8 = 0x8D;
+    pub(crate) const PS_DATA_1: u8 = 0x8E;
+    pub(crate) const INTERRUPT: u8 = 0x8F;
+    pub(crate) const PS_THRES_UP_0: u8 = 0x90;
+    pub(crate) const PS_THRES_UP_1: u8 = 0x91;
+    pub(crate) const PS_THRES_LOW_0: u8 = 0x92;
+    pub(crate) const PS_THRES_LOW_1: u8 = 0x93;
+    pub(crate) const PS_OFFSET_0: u8 = 0x94;
+    pub(crate) const PS_OFFSET_1: u8 = 0x95;
+    pub(crate) const ALS_THRES_UP_0: u8 = 0x97;
+    pub(crate) const ALS_THRES_UP_1: u8 = 0x98;
+    pub(crate) const ALS_THRES_LOW_0: u8 = 0x99;
+    pub(crate) const ALS_THRES_LOW_1: u8 = 0x9A;
+    pub(crate) const INTERRUPT_PERSIST: u8 = 0x9E;
 }
 
-struct BitFlags;
+/// ALS wakeup time from standby in milliseconds
+pub(crate) const ALS_WAKEUP_TIME_MS: u32 = 10;
+
+/// Initial startup time after power-up or software reset in milliseconds
+pub(crate) const STARTUP_TIME_MS: u32 = 100;
+
+pub(crate) struct BitFlags;
 impl BitFlags {
+    const R80_ALS_ACTIVE: u8 = 1 << 0;
+    pub(crate) const R80_SW_RESET: u8 = 1 << 1;
     const R8C_PS_DATA_STATUS: u8 = 1 << 0;
     const R8C_PS_INTERRUPT_STATUS: u8 = 1 << 1;
     const R8C_ALS_DATA_STATUS: u8 = 1 << 2;
@@ -45,6 +56,10 @@ impl BitFlags {
     const R8C_ALS_DATA_VALID: u8 = 1 << 7;
     const R8C_ALS_GAIN: u8 = 7 << 4;
     const R8E_PS_SATURATION: u8 = 1 << 7;
+    #[cfg(feature = "float")]
+    pub(crate) const R8F_INTERRUPT_ALS: u8 = 1 << 1;
+    #[cfg(feature = "float")]
+    pub(crate) const R9E_ALS_PERSIST: u8 = 0x0F;
 }
 
 impl marker::WithDeviceId for ic::Ltr559 {}
@@ -59,6 +74,32 @@ macro_rules! create {
                     address: address.addr(),
                     als_gain: AlsGain::default(),
                     als_int: AlsIntTime::default(),
+                    als_meas_rate: AlsMeasRate::default(),
+                    lengthened_meas_rate: None,
+                    ps_meas_rate: PsMeasRate::default(),
+                    last_als_ready_ms: None,
+                    last_ps_ready_ms: None,
+                    interrupt_polarity: InterruptPinPolarity::default(),
+                    cache_dirty: false,
+                    staged: None,
+                    applied_config: None,
+                    active_before_standby: None,
+                    lux_coefficients: LuxCoefficients::default(),
+                    lux_algorithm: LuxAlgorithm::default(),
+                    held_als: (0, 0),
+                    dark_counts: (0, 0),
+                    #[cfg(feature = "float")]
+                    window_factor: 1.0,
+                    #[cfg(feature = "float")]
+                    window_factor_q16: 1 << 16,
+                    #[cfg(feature = "float")]
+                    lux_offset: 0.0,
+                    #[cfg(feature = "float")]
+                    lux_offset_q16: 0,
+                    #[cfg(feature = "stats")]
+                    lux_stats: crate::stats::RunningStats::new(),
+                    last_status: None,
+                    diagnostics: DiagnosticCounters::default(),
                     _ic: PhantomData,
                 }
             }
@@ -72,260 +113,566 @@ impl<I2C, IC> Ltr559<I2C, IC> {
     pub fn destroy(self) -> I2C {
         self.i2c
     }
+
+    /// Run `f` with direct access to the I²C bus.
+    ///
+    /// This is meant for ad-hoc transactions to other devices on the bus or
+    /// to undocumented registers. Since `f` may change the device
+    /// configuration behind the driver's back, the cached configuration is
+    /// marked dirty afterwards and re-read from the device on the next
+    /// [`get_lux()`](#method.get_lux) call (or explicitly through
+    /// [`resync_internal_driver_state()`](#method.resync_internal_driver_state)).
+    pub fn with_bus<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut I2C) -> R,
+    {
+        self.cache_dirty = true;
+        f(&mut self.i2c)
+    }
+
+    impl_staging_methods!();
+    impl_lux_settings!();
+    impl_cached_state_methods!();
+
+    /// Whether the cached configuration may differ from the device.
+    ///
+    /// See [`with_bus()`](#method.with_bus).
+    pub fn is_cache_dirty(&self) -> bool {
+        self.cache_dirty
+    }
 }
 
 impl<I2C, E, IC> Ltr559<I2C, IC>
 where
     I2C: i2c::WriteRead<Error = E>,
 {
-    /// Read the status of the conversion.
+    impl_read_methods!([] []);
+
+    /// Estimate the time in milliseconds until the next ALS or PS sample is due.
     ///
-    /// Note that the conversion ready flag is cleared automatically
-    /// after calling this method.
-    pub fn get_status(&mut self) -> Result<Status, Error<E>> {
-        let config = self.read_register(Register::ALS_PS_STATUS)?;
-        Ok(Status {
-            ps_data_status: (config & BitFlags::R8C_PS_DATA_STATUS) != 0,
-            ps_interrupt_status: (config & BitFlags::R8C_PS_INTERRUPT_STATUS) != 0,
-            als_data_status: (config & BitFlags::R8C_ALS_DATA_STATUS) != 0,
-            als_interrupt_status: (config & BitFlags::R8C_ALS_INTERRUPT_STATUS) != 0,
-            als_gain: (config & BitFlags::R8C_ALS_GAIN) >> 4,
-            als_data_valid: (config & BitFlags::R8C_ALS_DATA_VALID) != BitFlags::R8C_ALS_DATA_VALID,
+    /// `now_ms` is a monotonic millisecond timestamp from the application
+    /// (it may wrap around). This reads the status once: if new data is
+    /// available its arrival time is recorded and `Some(0)` is returned.
+    /// Otherwise the remaining time is computed from the configured
+    /// measurement rates and the last observed data-ready times.
+    ///
+    /// Returns `None` until data-ready has been observed at least once.
+    pub fn time_until_next_sample(&mut self, now_ms: u32) -> Result<Option<u32>, Error<E>> {
+        let status = self.get_status()?;
+        if status.als_data_status {
+            self.last_als_ready_ms = Some(now_ms);
+        }
+        if status.ps_data_status {
+            self.last_ps_ready_ms = Some(now_ms);
+        }
+        if status.als_data_status || status.ps_data_status {
+            return Ok(Some(0));
+        }
+        let remaining = |last: Option<u32>, period: u32| {
+            last.map(|last| (period - now_ms.wrapping_sub(last) % period) % period)
+        };
+        let als = remaining(self.last_als_ready_ms, self.als_meas_rate.milliseconds());
+        let ps = remaining(self.last_ps_ready_ms, self.ps_meas_rate.milliseconds());
+        Ok(match (als, ps) {
+            (Some(als), Some(ps)) => Some(als.min(ps)),
+            (als, ps) => als.or(ps),
         })
     }
+
+    /// Wait for new valid ALS data and return it in lux.
+    ///
+    /// The status is polled with `delay` ten times per ALS measurement
+    /// period. Returns `Error::Timeout` if no new data arrives within
+    /// `timeout_ms` milliseconds.
+    #[cfg(feature = "float")]
+    pub fn read_lux_blocking<D: DelayMs<u32>>(
+        &mut self,
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> Result<f32, Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        let period = self.als_meas_rate.milliseconds();
+        self.poll_status(delay, period, timeout_ms, |status| {
+            status.als_data_status && status.als_data_valid
+        })?;
+        self.get_lux()
+    }
+
+    /// Return the lux of a new conversion, if available.
+    ///
+    /// Returns `nb::Error::WouldBlock` if no new valid ALS data is available
+    /// since the status was last read. Note that reading the status clears
+    /// the data-ready flags.
+    #[cfg(feature = "float")]
+    pub fn try_get_lux(&mut self) -> nb::Result<f32, Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        let status = self.get_status().map_err(nb::Error::Other)?;
+        if !status.als_data_status || !status.als_data_valid {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.get_lux().map_err(nb::Error::Other)
+    }
+
+    /// Wait for new PS data and return it in format (value, saturated).
+    ///
+    /// The status is polled with `delay` ten times per PS measurement
+    /// period. Returns `Error::Timeout` if no new data arrives within
+    /// `timeout_ms` milliseconds.
+    pub fn read_ps_blocking<D: DelayMs<u32>>(
+        &mut self,
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> Result<(u16, bool), Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        let period = self.ps_meas_rate.milliseconds();
+        self.poll_status(delay, period, timeout_ms, |status| status.ps_data_status)?;
+        self.get_ps_data()
+    }
+
+    /// Wait until the interrupt pin is asserted, then read and clear the
+    /// interrupt.
+    ///
+    /// The active level of `pin` is the polarity configured through
+    /// [`set_interrupt()`](#method.set_interrupt). The pin is polled every
+    /// millisecond and `Error::Timeout` is returned if it is still inactive
+    /// after `timeout_ms`. The interrupt is cleared by reading the status.
+    pub fn wait_for_interrupt<P, D>(
+        &mut self,
+        pin: &mut P,
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> Result<InterruptCause, Error<E>>
+    where
+        P: InputPin,
+        D: DelayMs<u32>,
+    {
+        let mut waited = 0;
+        loop {
+            if self.is_interrupt_pin_active(pin)? {
+                return self.read_interrupt_cause();
+            }
+            if waited >= timeout_ms {
+                return Err(Error::Timeout);
+            }
+            delay.delay_ms(1);
+            waited += 1;
+        }
+    }
+
+    fn is_interrupt_pin_active<P: InputPin>(&self, pin: &mut P) -> Result<bool, Error<E>> {
+        match self.interrupt_polarity {
+            InterruptPinPolarity::Low => pin.is_low(),
+            InterruptPinPolarity::High => pin.is_high(),
+        }
+        .map_err(|_| Error::Pin)
+    }
 }
 
 impl<I2C, E, IC> Ltr559<I2C, IC>
 where
     I2C: i2c::Write<Error = E>,
 {
-    /// Set ALS_CONTR Register
+    impl_write_methods!([] []);
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    impl_data_methods!([] []);
+    impl_wait_methods!([] [] [DelayMs<u32>]);
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    impl_power_methods!([] [] [DelayMs<u32>]);
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    impl_threshold_methods!([] []);
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    /// Check that the INT pin is wired and follows the configured polarity.
+    ///
+    /// The pin must be inactive once the interrupt is cleared. The ALS is
+    /// then activated (if it was not) with impossible thresholds, so that
+    /// the next conversion raises an ALS interrupt, and the pin must become
+    /// active within three measurement periods and inactive again once the
+    /// interrupt is cleared. Afterwards the thresholds, interrupt and
+    /// persist settings and the ALS mode are restored and the interrupt
+    /// raised by the test is cleared.
     ///
-    pub fn set_als_contr(
+    /// Returns whether the test passed. Bus and pin errors are returned
+    /// after the configuration has been restored.
+    pub fn interrupt_self_test<P, D>(
         &mut self,
-        als_gain: AlsGain,
-        sw_reset: bool,
-        als_active: bool,
-    ) -> Result<(), Error<E>> {
-        let mut value: u8 = als_gain.value();
-        if sw_reset {
-            value += 2;
-        }
-        if als_active {
-            value += 1;
-        }
+        pin: &mut P,
+        delay: &mut D,
+    ) -> Result<bool, Error<E>>
+    where
+        P: InputPin,
+        D: DelayMs<u32>,
+    {
+        let thresholds = self.get_thresholds()?;
+        let (polarity, mode) = self.get_interrupt()?;
+        let (als_persist, ps_persist) = self.get_interrupt_persist()?;
+        let als_contr = self.get_als_contr()?;
 
-        self.write_register(Register::ALS_CONTR, value)?;
-        self.als_gain = als_gain;
-        Ok(())
+        let result = self.force_interrupt(pin, delay, polarity, ps_persist, als_contr.active);
+
+        self.set_interrupt(polarity, InterruptMode::Inactive)?;
+        self.set_als_contr(als_contr.gain, false, als_contr.active)?;
+        self.set_als_low_limit_raw(thresholds.als_low)?;
+        self.set_als_high_limit_raw(thresholds.als_high)?;
+        self.set_interrupt_persist(als_persist, ps_persist)?;
+        self.get_status()?;
+        self.set_interrupt(polarity, mode)?;
+        result
     }
 
-    /// Set PS_CONTR Register
-    ///
-    pub fn set_ps_contr(
+    fn force_interrupt<P, D>(
         &mut self,
-        ps_saturation_indicator_enable: bool,
-        ps_active: bool,
-    ) -> Result<(), Error<E>> {
-        let mut value: u8 = 0;
-        if ps_saturation_indicator_enable {
-            value += 1 << 5;
+        pin: &mut P,
+        delay: &mut D,
+        polarity: InterruptPinPolarity,
+        ps_persist: PsPersist,
+        als_active: bool,
+    ) -> Result<bool, Error<E>>
+    where
+        P: InputPin,
+        D: DelayMs<u32>,
+    {
+        self.set_interrupt(polarity, InterruptMode::Inactive)?;
+        self.get_status()?;
+        if self.is_interrupt_pin_active(pin)? {
+            return Ok(false);
         }
-        if ps_active {
-            value += 3;
+        self.set_interrupt_persist(AlsPersist::EveryTime, ps_persist)?;
+        self.set_als_low_limit_raw(0xFFFF)?;
+        self.set_als_high_limit_raw(0)?;
+        self.set_interrupt(polarity, InterruptMode::OnlyALS)?;
+        if !als_active {
+            self.set_als_contr(self.als_gain, false, true)?;
+        }
+        let timeout_ms = 3 * self.als_meas_rate.milliseconds() + ALS_WAKEUP_TIME_MS;
+        match self.wait_for_interrupt(pin, delay, timeout_ms) {
+            Ok(cause) if cause.als => Ok(!self.is_interrupt_pin_active(pin)?),
+            Ok(_) | Err(Error::Timeout) => Ok(false),
+            Err(e) => Err(e),
         }
-
-        self.write_register(Register::PS_CONTR, value)
     }
 
-    /// Set PS LED controls
+    /// Change the ALS gain, taking care of the data-valid semantics.
     ///
-    pub fn set_ps_led(
-        &mut self,
-        led_pulse_freq: LedPulse,
-        led_duty_cycle: LedDutyCycle,
-        led_peak_current: LedCurrent,
-    ) -> Result<(), Error<E>> {
-        let mut value: u8;
-        value = led_pulse_freq.value();
-        value |= led_duty_cycle.value();
-        value |= led_peak_current.value();
-        self.write_register(Register::PS_LED, value)
-    }
-
-    /// Set the fault count for both ALS and PS
+    /// Data converted right after a gain change is unreliable, so this
+    /// switches the gain (keeping the ALS mode unchanged), rescales the ALS
+    /// thresholds so that they keep their meaning in lux, then waits for
+    /// the first conversion made with the new gain and discards it.
     ///
-    pub fn set_interrupt_persist(
+    /// If the ALS is in standby no conversion is waited for. Returns
+    /// `Error::Timeout` if no valid conversion arrives within three
+    /// measurement periods.
+    pub fn change_gain_safely<D: DelayMs<u32>>(
         &mut self,
-        als_count: AlsPersist,
-        ps_count: PsPersist,
-    ) -> Result<(), Error<E>> {
-        let value = ps_count.value() | als_count.value();
-        self.write_register(Register::INTERRUPT_PERSIST, value)
+        new_gain: AlsGain,
+        delay: &mut D,
+    ) -> Result<(), Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        self.change_range_safely(new_gain, self.als_int, delay)
     }
 
-    /// Set the integration (conversion) time and measurement repeat timer
-    pub fn set_als_meas_rate(
+    /// Change the ALS integration time, taking care of the data-valid
+    /// semantics.
+    ///
+    /// The measurement rate is lengthened if it is shorter than the new
+    /// integration time. It is restored by a later change to an integration
+    /// time that fits in the original rate, unless the rate was changed in
+    /// between. As with
+    /// [`change_gain_safely()`](#method.change_gain_safely), the ALS
+    /// thresholds are rescaled and the first conversion with the new
+    /// integration time is discarded.
+    ///
+    /// If the ALS is in standby no conversion is waited for. Returns
+    /// `Error::Timeout` if no valid conversion arrives within three
+    /// measurement periods.
+    pub fn change_int_time_safely<D: DelayMs<u32>>(
         &mut self,
-        als_int: AlsIntTime,
-        als_meas_rate: AlsMeasRate,
-    ) -> Result<(), Error<E>> {
-        let value = (als_int.value() << 3) | als_meas_rate.value();
-        self.write_register(Register::ALS_MEAS_RATE, value)?;
-        self.als_int = als_int;
-        Ok(())
+        new_int: AlsIntTime,
+        delay: &mut D,
+    ) -> Result<(), Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        self.change_range_safely(self.als_gain, new_int, delay)
     }
 
-    /// Set the lux low limit in raw format
-    pub fn set_als_low_limit_raw(&mut self, value: u16) -> Result<(), Error<E>> {
-        let low = (value & 0xff) as u8;
-        let high = ((value >> 8) & 0xff) as u8;
-        self.write_register(Register::ALS_THRES_LOW_0, low)?;
-        self.write_register(Register::ALS_THRES_LOW_1, high)?;
-        Ok(())
-    }
+    /// Change the ALS gain and integration time together, taking care of
+    /// the data-valid semantics.
+    ///
+    /// Works as [`change_gain_safely()`](#method.change_gain_safely) and
+    /// [`change_int_time_safely()`](#method.change_int_time_safely) but
+    /// discards a single conversion for both changes.
+    pub fn change_range_safely<D: DelayMs<u32>>(
+        &mut self,
+        new_gain: AlsGain,
+        new_int: AlsIntTime,
+        delay: &mut D,
+    ) -> Result<(), Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        let (old_gain, old_int) = (self.als_gain, self.als_int);
+        let active = self.read_register(Register::ALS_CONTR)? & BitFlags::R80_ALS_ACTIVE != 0;
+        let low = self.read_u16(Register::ALS_THRES_LOW_0)?;
+        let high = self.read_u16(Register::ALS_THRES_UP_0)?;
+        if new_gain != old_gain {
+            self.set_als_contr(new_gain, false, active)?;
+        }
+        if new_int != old_int {
+            let original = match self.lengthened_meas_rate {
+                Some((original, lengthened)) if lengthened == self.als_meas_rate => original,
+                _ => self.als_meas_rate,
+            };
+            let meas_rate = meas_rate_for(new_int, original);
+            self.set_als_meas_rate(new_int, meas_rate)?;
+            self.lengthened_meas_rate =
+                Some((original, meas_rate)).filter(|_| meas_rate != original);
+        }
 
-    /// Set the lux low limit in raw format
-    pub fn set_als_high_limit_raw(&mut self, value: u16) -> Result<(), Error<E>> {
-        let low = (value & 0xff) as u8;
-        let high = ((value >> 8) & 0xff) as u8;
-        self.write_register(Register::ALS_THRES_UP_0, low)?;
-        self.write_register(Register::ALS_THRES_UP_1, high)?;
-        Ok(())
-    }
+        let new = new_gain.multiplier() * new_int.milliseconds();
+        let old = old_gain.multiplier() * old_int.milliseconds();
+        self.set_als_low_limit_raw(scale_raw(low, new, old))?;
+        self.set_als_high_limit_raw(scale_raw(high, new, old))?;
 
-    /// Set the ps low limit in raw format
-    pub fn set_ps_low_limit_raw(&mut self, value: u16) -> Result<(), Error<E>> {
-        let low = (value & 0xff) as u8;
-        let high = ((value >> 8) & 0xff) as u8;
-        self.write_register(Register::PS_THRES_LOW_0, low)?;
-        self.write_register(Register::PS_THRES_LOW_1, high)?;
+        if active {
+            self.discard_als_conversion(delay)?;
+        }
         Ok(())
     }
 
-    /// Set the ps low limit in raw format
-    pub fn set_ps_high_limit_raw(&mut self, value: u16) -> Result<(), Error<E>> {
-        let low = (value & 0xff) as u8;
-        let high = ((value >> 8) & 0xff) as u8;
-        self.write_register(Register::PS_THRES_UP_0, low)?;
-        self.write_register(Register::PS_THRES_UP_1, high)?;
-        Ok(())
+    /// Wait for the first conversion after a range change and read all
+    /// four data bytes to discard it, then wait for the second, reliable
+    /// one.
+    fn discard_als_conversion<D: DelayMs<u32>>(&mut self, delay: &mut D) -> Result<(), Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        self.wait_for_als_data(delay)?;
+        self.get_als_raw_data()?;
+        self.wait_for_als_data(delay)
     }
 
-    /// Set PS Meas Rate
-    pub fn set_ps_meas_rate(&mut self, ps_meas_rate: PsMeasRate) -> Result<(), Error<E>> {
-        self.write_register(Register::PS_MEAS_RATE, ps_meas_rate.value())
+    /// Perform a one-shot lux measurement and leave the ALS in standby.
+    ///
+    /// Activates the ALS with the current gain, waits for the wakeup time
+    /// plus exactly one integration period (with a 10% margin), reads the
+    /// result and puts the ALS back in standby. This avoids polling the
+    /// status register in a tight loop.
+    #[cfg(feature = "float")]
+    pub fn measure_blocking<D: DelayMs<u32>>(&mut self, delay: &mut D) -> Result<f32, Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        let integration_ms = self.als_int.milliseconds();
+        self.set_als_contr(self.als_gain, false, true)?;
+        delay.delay_ms(ALS_WAKEUP_TIME_MS + integration_ms + integration_ms / 10);
+        let lux = self.wait_for_als_data(delay).and_then(|_| self.get_lux());
+        self.set_als_contr(self.als_gain, false, false)?;
+        lux
     }
 
-    /// Set PS OFFSET.
+    /// Emulate a single-shot measurement and leave the device in standby.
     ///
-    /// Values that exceed 1023 will cause an Err to be returned
-    pub fn set_ps_offset(&mut self, value: u16) -> Result<(), Error<E>> {
-        if value > 1023 {
-            return Err(Error::InvalidInputData);
+    /// Activates the ALS with the current gain, and the PS too if `with_ps`
+    /// is `true`, waits for the first valid conversion after the wakeup
+    /// time, reads it and puts the ALS and the PS back in standby, also on
+    /// error. Without `with_ps` the PS fields of the measurement hold
+    /// whatever the PS data registers contain.
+    ///
+    /// Returns `Error::Timeout` if no conversion arrives within three
+    /// measurement periods of the slower of the two.
+    #[cfg(feature = "float")]
+    pub fn measure_once<D: DelayMs<u32>>(
+        &mut self,
+        delay: &mut D,
+        with_ps: bool,
+    ) -> Result<Measurement, Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        self.set_als_contr(self.als_gain, false, true)?;
+        let result = if with_ps {
+            self.update_register(Register::PS_CONTR, 0x03, 0x03)
+        } else {
+            Ok(())
         }
-        let ps_offset_0 = (value & 0xff) as u8;
-        let ps_offset_1 = ((value >> 8) & 0xff) as u8;
-        self.write_register(Register::PS_OFFSET_0, ps_offset_0)?;
-        self.write_register(Register::PS_OFFSET_1, ps_offset_1)
+        .and_then(|_| {
+            delay.delay_ms(ALS_WAKEUP_TIME_MS);
+            self.wait_for_first_conversion(delay, with_ps)
+        })
+        .and_then(|_| self.get_measurement());
+        self.set_als_contr(self.als_gain, false, false)?;
+        if with_ps {
+            self.update_register(Register::PS_CONTR, 0x03, 0)?;
+        }
+        result
     }
 
-    /// Set PS N Pulses
+    /// Wait for a valid ALS conversion and, if `with_ps`, a PS conversion.
     ///
-    /// Accepted values are 1..16
-    pub fn set_ps_n_pulses(&mut self, value: u8) -> Result<(), Error<E>> {
-        if value > 0 && value < 16 {
-            self.write_register(Register::PS_N_PULSES, value)
-        } else {
-            Err(Error::InvalidInputData)
+    /// Both flags are polled in the same loop because reading the status
+    /// clears them.
+    #[cfg(feature = "float")]
+    fn wait_for_first_conversion<D: DelayMs<u32>>(
+        &mut self,
+        delay: &mut D,
+        with_ps: bool,
+    ) -> Result<(), Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        let gain = self.als_gain.value() >> 2;
+        let mut period = self.als_meas_rate.milliseconds();
+        let mut timeout_ms = 3 * period;
+        if with_ps {
+            let ps_period = self.ps_meas_rate.milliseconds();
+            period = period.min(ps_period);
+            timeout_ms = timeout_ms.max(3 * ps_period);
         }
+        let (mut als_ready, mut ps_ready) = (false, !with_ps);
+        self.poll_status(delay, period, timeout_ms, |status| {
+            als_ready |= status.als_data_status && status.als_data_valid && status.als_gain == gain;
+            ps_ready |= status.ps_data_status;
+            als_ready && ps_ready
+        })
     }
 
-    /// Set Interrupt Polarity and Enable
-    pub fn set_interrupt(
+    /// Collect `n` consecutive valid ALS conversions and return their mean
+    /// and variance.
+    ///
+    /// Each conversion is waited for with `delay` as in
+    /// [`measure_once()`](#method.measure_once), so the ALS has to be
+    /// active. Returns `Error::InvalidInputData` if `n` is 0.
+    #[cfg(feature = "float")]
+    pub fn get_lux_averaged<D: DelayMs<u32>>(
         &mut self,
-        polarity: InterruptPinPolarity,
-        mode: InterruptMode,
-    ) -> Result<(), Error<E>> {
-        let value = mode.value() | polarity.value();
-        self.write_register(Register::INTERRUPT, value)
+        n: u8,
+        delay: &mut D,
+    ) -> Result<LuxAverage, Error<E>>
+    where
+        IC: marker::WithDeviceId,
+    {
+        if n == 0 {
+            return Err(Error::InvalidInputData);
+        }
+        // Welford's online algorithm
+        let (mut mean, mut m2) = (0.0_f32, 0.0_f32);
+        for count in 1..=n {
+            self.wait_for_als_data(delay)?;
+            let lux = self.get_lux()?;
+            let delta = lux - mean;
+            mean += delta / f32::from(count);
+            m2 += delta * (lux - mean);
+        }
+        Ok(LuxAverage {
+            mean,
+            variance: m2 / f32::from(n),
+            samples: n,
+        })
     }
 }
 
-impl<I2C, E, IC> Ltr559<I2C, IC>
-where
-    I2C: i2c::WriteRead<Error = E>,
-    IC: marker::WithDeviceId,
-{
-    /// Read the manufacturer ID
-    pub fn get_manufacturer_id(&mut self) -> Result<u8, Error<E>> {
-        self.read_register(Register::MANUFAC_ID)
-    }
-
-    /// Read the device part number and revision id
-    pub fn get_part_id(&mut self) -> Result<u8, Error<E>> {
-        self.read_register(Register::PART_ID)
-    }
-
-    /// Get ALS Data in (als_ch0, als_ch1) format
-    pub fn get_als_raw_data(&mut self) -> Result<(u16, u16), Error<E>> {
-        let mut measurements = [0; 4];
-        let regs = [
-            Register::ALS_DATA_CH1_0,
-            Register::ALS_DATA_CH1_1,
-            Register::ALS_DATA_CH0_0,
-            Register::ALS_DATA_CH0_1,
-        ];
-        for i in 0..4 {
-            let value = self.read_register(regs[i])?;
-            measurements[i] = value;
-        }
+/// Decode the PS_DATA registers into (value, saturated)
+pub(crate) fn decode_ps_data(ps0: u8, ps1: u8) -> (u16, bool) {
+    let value = u16::from(ps1 & 7) << 8 | u16::from(ps0);
+    let saturated = ps1 & BitFlags::R8E_PS_SATURATION;
+    (value, saturated != 0)
+}
 
-        let ch1 = ((measurements[1] as u16) << 8) + (measurements[0] as u16);
-        let ch0 = ((measurements[3] as u16) << 8) + (measurements[2] as u16);
-        Ok((ch0, ch1))
-    }
+/// Half-width of an ALS window of ±`percent` % around `ch0`, at least 1
+pub(crate) fn als_window_delta(ch0: u16, percent: u8) -> u16 {
+    let delta = u32::from(ch0) * u32::from(percent) / 100;
+    delta.clamp(1, 0xFFFF) as u16
+}
 
-    /// Return calculated lux
-    pub fn get_lux(&mut self) -> Result<f32, Error<E>> {
-        let (als_data_ch0, als_data_ch1) = self.get_als_raw_data()?;
-        let mut ret;
-        let ratio;
-        if als_data_ch1 + als_data_ch0 == 0 {
-            ratio = 1000.0;
-        } else {
-            ratio = (als_data_ch1 as f32 * 1000.0) as f32 / (als_data_ch1 + als_data_ch0) as f32;
-        }
+/// Thresholds for an interrupt on `value` relative to the band `low..=high`
+pub(crate) fn window_limits(
+    value: u16,
+    low: u16,
+    high: u16,
+    max: u16,
+    mode: WindowMode,
+) -> (u16, u16) {
+    match mode {
+        WindowMode::Outside => (low, high),
+        WindowMode::Inside if value < low => (0, low - 1),
+        WindowMode::Inside if value > high => (high + 1, max),
+        WindowMode::Inside => (max, 0),
+    }
+}
 
-        let ch0_c: [f32; 4] = [17743.0, 42785.0, 5926.0, 0.0];
-        let ch1_c: [f32; 4] = [-11059.0, 19548.0, -1185.0, 0.0];
-        let index_co;
-        if ratio < 450.0 {
-            index_co = 0;
-        } else if ratio < 640.0 {
-            index_co = 1;
-        } else if ratio < 850.0 {
-            index_co = 2;
-        } else {
-            index_co = 3;
+impl Status {
+    /// Decode from the ALS_PS_STATUS and PS_DATA_1 register values
+    pub(crate) fn from_registers(config: u8, ps_data_1: u8) -> Self {
+        Status {
+            ps_saturated: (ps_data_1 & BitFlags::R8E_PS_SATURATION) != 0,
+            ps_data_status: (config & BitFlags::R8C_PS_DATA_STATUS) != 0,
+            ps_interrupt_status: (config & BitFlags::R8C_PS_INTERRUPT_STATUS) != 0,
+            als_data_status: (config & BitFlags::R8C_ALS_DATA_STATUS) != 0,
+            als_interrupt_status: (config & BitFlags::R8C_ALS_INTERRUPT_STATUS) != 0,
+            als_gain: (config & BitFlags::R8C_ALS_GAIN) >> 4,
+            als_data_valid: (config & BitFlags::R8C_ALS_DATA_VALID) != BitFlags::R8C_ALS_DATA_VALID,
         }
+    }
+}
 
-        ret = ((als_data_ch0 as f32) * ch0_c[index_co] - (als_data_ch1 as f32) * ch1_c[index_co])
-            / 10000.0;
+/// Scale a raw threshold by `new / old`, e.g. the ratio of the gain
+/// multipliers, saturating at the register bounds
+fn scale_raw(value: u16, new: u32, old: u32) -> u16 {
+    let scaled = u32::from(value) * new / old;
+    scaled.min(0xFFFF) as u16
+}
 
-        ret /= self.als_int.lux_compute_value();
-        ret /= self.als_gain.lux_compute_value();
-        Ok(ret)
-    }
+/// ALS measurement rates in ascending order
+const ALS_MEAS_RATES: [AlsMeasRate; 6] = [
+    AlsMeasRate::_50ms,
+    AlsMeasRate::_100ms,
+    AlsMeasRate::_200ms,
+    AlsMeasRate::_500ms,
+    AlsMeasRate::_1000ms,
+    AlsMeasRate::_2000ms,
+];
 
-    /// Return PS Data in format (value, saturated)
-    pub fn get_ps_data(&mut self) -> Result<(u16, bool), Error<E>> {
-        let ps0 = self.read_register(Register::PS_DATA_0)?;
-        let ps1 = self.read_register(Register::PS_DATA_1)?;
-        let value = (((ps1 & 7) as u16) << 8) + (ps0 as u16);
-        let saturated = ps1 & BitFlags::R8E_PS_SATURATION;
-        Ok((value, saturated != 0))
+/// `current` if it is not shorter than `int_time`, otherwise the shortest
+/// measurement rate that is
+fn meas_rate_for(int_time: AlsIntTime, current: AlsMeasRate) -> AlsMeasRate {
+    if current.milliseconds() >= int_time.milliseconds() {
+        return current;
     }
+    ALS_MEAS_RATES
+        .iter()
+        .copied()
+        .find(|rate| rate.milliseconds() >= int_time.milliseconds())
+        .unwrap_or(AlsMeasRate::_2000ms)
 }
 
 impl<I2C, IC> Ltr559<I2C, IC> {
@@ -343,47 +690,29 @@ impl<I2C, IC> Ltr559<I2C, IC> {
     pub fn reset_internal_driver_state(&mut self) {
         self.als_gain = AlsGain::default();
         self.als_int = AlsIntTime::default();
-    }
-}
-
-impl<I2C, E, IC> Ltr559<I2C, IC>
-where
-    I2C: i2c::WriteRead<Error = E>,
-{
-    fn read_register(&mut self, register: u8) -> Result<u8, Error<E>> {
-        let mut data = [0];
-        self.i2c
-            .write_read(self.address, &[register], &mut data)
-            .map_err(Error::I2C)
-            .and(Ok(data[0]))
-    }
-}
-
-impl<I2C, E, IC> Ltr559<I2C, IC>
-where
-    I2C: i2c::Write<Error = E>,
-{
-    fn write_register(&mut self, register: u8, value: u8) -> Result<(), Error<E>> {
-        let data = [register, value];
-        self.i2c.write(self.address, &data).map_err(Error::I2C)
+        self.als_meas_rate = AlsMeasRate::default();
+        self.lengthened_meas_rate = None;
+        self.ps_meas_rate = PsMeasRate::default();
+        self.interrupt_polarity = InterruptPinPolarity::default();
+        self.last_als_ready_ms = None;
+        self.last_ps_ready_ms = None;
+        self.active_before_standby = None;
+        self.last_status = None;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    struct I2cMock;
-    impl i2c::Write for I2cMock {
-        type Error = ();
-        fn write(&mut self, _addr: u8, _bytes: &[u8]) -> Result<(), Self::Error> {
-            Ok(())
-        }
-    }
+    use crate::mock::{DelayMock, I2cMock, InputPinMock};
+    use crate::{
+        AlsData, AlsPersist, InterruptMode, LedConfig, LedCurrent, LedDutyCycle, LedPulse,
+        LightSource, PsData, PsPersist, Tracking,
+    };
 
     #[test]
     fn can_reset_driver_state() {
-        let mut device = Ltr559::new_device(I2cMock {}, SlaveAddr::default());
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
         device
             .set_interrupt_persist(AlsPersist::_3v, PsPersist::_2v)
             .unwrap();
@@ -397,25 +726,1111 @@ mod tests {
 
     #[test]
     fn ps_offset_outside() {
-        let mut device = Ltr559::new_device(I2cMock {}, SlaveAddr::default());
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
         assert!(device.set_ps_offset(1024).is_err());
     }
 
     #[test]
     fn ps_offset_ok() {
-        let mut device = Ltr559::new_device(I2cMock {}, SlaveAddr::default());
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
         assert!(device.set_ps_offset(1023).is_ok());
     }
 
     #[test]
     fn ps_n_pulses_outside() {
-        let mut device = Ltr559::new_device(I2cMock {}, SlaveAddr::default());
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
         assert!(device.set_ps_n_pulses(0).is_err());
     }
 
     #[test]
     fn ps_n_pulses_ok() {
-        let mut device = Ltr559::new_device(I2cMock {}, SlaveAddr::default());
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
         assert!(device.set_ps_n_pulses(15).is_ok());
     }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn wake_on_darkness_programs_low_threshold() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.i2c.registers[Register::INTERRUPT_PERSIST as usize] = 0x50;
+        device.i2c.registers[Register::INTERRUPT as usize] = 0x05;
+        device.wake_on_darkness(17.743, AlsPersist::_4v).unwrap();
+        let regs = device.destroy().registers;
+        assert_eq!(regs[Register::ALS_THRES_LOW_0 as usize], 10);
+        assert_eq!(regs[Register::ALS_THRES_LOW_1 as usize], 0);
+        assert_eq!(regs[Register::ALS_THRES_UP_0 as usize], 0xFF);
+        assert_eq!(regs[Register::ALS_THRES_UP_1 as usize], 0xFF);
+        assert_eq!(regs[Register::INTERRUPT_PERSIST as usize], 0x53);
+        assert_eq!(regs[Register::INTERRUPT as usize], 0x07);
+        assert_eq!(regs[Register::ALS_MEAS_RATE as usize], 0x07);
+        assert_eq!(regs[Register::ALS_CONTR as usize], 0x01);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn wake_on_light_programs_high_threshold() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device
+            .wake_on_light(1_000_000.0, AlsPersist::EveryTime)
+            .unwrap();
+        let regs = device.destroy().registers;
+        assert_eq!(regs[Register::ALS_THRES_LOW_0 as usize], 0);
+        assert_eq!(regs[Register::ALS_THRES_UP_0 as usize], 0xFF);
+        assert_eq!(regs[Register::ALS_THRES_UP_1 as usize], 0xFF);
+        assert_eq!(regs[Register::INTERRUPT as usize], 0x02);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn lux_to_raw_follows_lux_coefficients() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.set_lux_coefficients(LuxCoefficients {
+            ch0: [35486, 42785, 5926, 0],
+            ..LuxCoefficients::DATASHEET
+        });
+        device.i2c.registers[Register::ALS_DATA_CH0_0 as usize] = 100;
+        let lux = device.get_lux().unwrap();
+        assert_eq!(lux, 354.86);
+        assert_eq!(device.lux_to_raw(lux), 100);
+        device.wake_on_darkness(lux, AlsPersist::_4v).unwrap();
+        let regs = device.destroy().registers;
+        assert_eq!(regs[Register::ALS_THRES_LOW_0 as usize], 100);
+        assert_eq!(regs[Register::ALS_THRES_LOW_1 as usize], 0);
+    }
+
+    #[test]
+    fn change_gain_safely_rescales_thresholds() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.set_als_low_limit_raw(100).unwrap();
+        device.set_als_high_limit_raw(40000).unwrap();
+        device.set_als_contr(AlsGain::Gain1x, false, true).unwrap();
+        // new valid data converted with gain 2x
+        device.i2c.registers[Register::ALS_PS_STATUS as usize] = 0x14;
+        let mut delay = DelayMock::default();
+        device
+            .change_gain_safely(AlsGain::Gain2x, &mut delay)
+            .unwrap();
+        assert_eq!(device.als_gain, AlsGain::Gain2x);
+        let regs = device.destroy().registers;
+        assert_eq!(regs[Register::ALS_CONTR as usize], 0x05);
+        assert_eq!(regs[Register::ALS_THRES_LOW_0 as usize], 200);
+        assert_eq!(regs[Register::ALS_THRES_UP_0 as usize], 0xFF);
+        assert_eq!(regs[Register::ALS_THRES_UP_1 as usize], 0xFF);
+    }
+
+    #[test]
+    fn change_int_time_safely_adapts_meas_rate() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.set_als_low_limit_raw(100).unwrap();
+        device.set_als_high_limit_raw(1000).unwrap();
+        device
+            .set_als_meas_rate(AlsIntTime::_100ms, AlsMeasRate::_100ms)
+            .unwrap();
+        device
+            .change_int_time_safely(AlsIntTime::_400ms, &mut DelayMock::default())
+            .unwrap();
+        assert_eq!(device.als_int, AlsIntTime::_400ms);
+        assert_eq!(device.als_meas_rate, AlsMeasRate::_500ms);
+        let thresholds = device.get_thresholds().unwrap();
+        assert_eq!((thresholds.als_low, thresholds.als_high), (400, 4000));
+        device
+            .change_int_time_safely(AlsIntTime::_50ms, &mut DelayMock::default())
+            .unwrap();
+        assert_eq!(device.als_meas_rate, AlsMeasRate::_100ms);
+        assert_eq!(device.get_thresholds().unwrap().als_low, 50);
+    }
+
+    #[test]
+    fn change_int_time_safely_keeps_meas_rate_changed_in_between() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device
+            .set_als_meas_rate(AlsIntTime::_100ms, AlsMeasRate::_100ms)
+            .unwrap();
+        let mut delay = DelayMock::default();
+        device
+            .change_int_time_safely(AlsIntTime::_300ms, &mut delay)
+            .unwrap();
+        assert_eq!(device.als_meas_rate, AlsMeasRate::_500ms);
+        device
+            .set_als_meas_rate(AlsIntTime::_300ms, AlsMeasRate::_1000ms)
+            .unwrap();
+        device
+            .change_int_time_safely(AlsIntTime::_100ms, &mut delay)
+            .unwrap();
+        assert_eq!(device.als_meas_rate, AlsMeasRate::_1000ms);
+    }
+
+    #[test]
+    fn change_range_safely_rescales_thresholds_once() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.set_als_thresholds(100, 1000).unwrap();
+        device
+            .change_range_safely(
+                AlsGain::Gain2x,
+                AlsIntTime::_200ms,
+                &mut DelayMock::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            (device.als_gain, device.als_int),
+            (AlsGain::Gain2x, AlsIntTime::_200ms)
+        );
+        let thresholds = device.get_thresholds().unwrap();
+        assert_eq!((thresholds.als_low, thresholds.als_high), (400, 4000));
+        assert_eq!(device.i2c.registers[Register::ALS_CONTR as usize], 0x04);
+    }
+
+    #[test]
+    fn change_gain_safely_times_out_without_data() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.set_als_contr(AlsGain::Gain1x, false, true).unwrap();
+        let mut delay = DelayMock::default();
+        assert!(matches!(
+            device.change_gain_safely(AlsGain::Gain8x, &mut delay),
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn lux_at_full_scale_does_not_overflow() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        for register in Register::ALS_DATA_CH1_0..=Register::ALS_DATA_CH0_0 + 1 {
+            device.i2c.registers[register as usize] = 0xFF;
+        }
+        assert_eq!(device.get_als_raw_data().unwrap(), (0xFFFF, 0xFFFF));
+        assert!(matches!(device.get_lux(), Err(Error::AlsSaturated)));
+        assert!(matches!(device.get_lux_millis(), Err(Error::AlsSaturated)));
+        assert!(device.get_measurement().unwrap().als_saturated);
+        // ratio is 500, second coefficient band
+        let expected = (65535.0 * 42785.0 - 65535.0 * 19548.0) / 10000.0;
+        let lux = crate::calc::compute_lux(0xFFFF, 0xFFFF, AlsGain::Gain1x, AlsIntTime::_100ms);
+        assert_eq!(lux, expected);
+    }
+
+    #[test]
+    fn ps_data_at_full_scale() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.i2c.registers[Register::PS_DATA_0 as usize] = 0xFF;
+        device.i2c.registers[Register::PS_DATA_1 as usize] = 0xFF;
+        assert_eq!(device.get_ps_data().unwrap(), (2047, true));
+    }
+
+    #[test]
+    fn ps_fast_mode_within_led_budget() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device
+            .set_ps_led(LedPulse::Pulse60, LedDutyCycle::_100, LedCurrent::_100mA)
+            .unwrap();
+        device.set_ps_fast_mode(6).unwrap();
+        let regs = device.destroy().registers;
+        assert_eq!(regs[Register::PS_N_PULSES as usize], 6);
+        assert_eq!(regs[Register::PS_MEAS_RATE as usize], 8);
+    }
+
+    #[test]
+    fn ps_fast_mode_exceeding_led_budget() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device
+            .set_ps_led(LedPulse::Pulse60, LedDutyCycle::_100, LedCurrent::_100mA)
+            .unwrap();
+        assert!(device.set_ps_fast_mode(7).is_err());
+        assert!(device.set_ps_fast_mode(0).is_err());
+        assert!(device.set_ps_fast_mode_with_limit(7, 1200).is_ok());
+        device
+            .set_ps_led(LedPulse::Pulse100, LedDutyCycle::_25, LedCurrent::_100mA)
+            .unwrap();
+        assert!(device.set_ps_fast_mode(15).is_ok());
+        assert!(device.set_ps_fast_mode(16).is_err());
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn with_bus_marks_cache_dirty_until_resync() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.with_bus(|i2c| {
+            i2c.registers[Register::ALS_CONTR as usize] = 0x0D;
+            i2c.registers[Register::ALS_MEAS_RATE as usize] = 0x12;
+        });
+        assert!(device.is_cache_dirty());
+        device.get_lux().unwrap();
+        assert!(!device.is_cache_dirty());
+        assert_eq!(device.als_gain, AlsGain::Gain8x);
+        assert_eq!(device.als_int, AlsIntTime::_200ms);
+        assert_eq!(device.als_meas_rate, AlsMeasRate::_200ms);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn measure_blocking_waits_one_integration_and_goes_to_standby() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        device.i2c.registers[Register::ALS_DATA_CH0_0 as usize] = 100;
+        device.i2c.registers[Register::ALS_PS_STATUS as usize] = 0x04;
+        let mut delay = DelayMock::default();
+        let lux = device.measure_blocking(&mut delay).unwrap();
+        assert_eq!(lux, 177.43);
+        assert_eq!(delay.elapsed_ms, 120);
+        assert_eq!(device.destroy().registers[Register::ALS_CONTR as usize], 0);
+    }
+
+    #[test]
+    fn next_sample_due_from_last_data_ready() {
+        let mut device = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        assert_eq!(device.time_until_next_sample(0).unwrap(), None);
+        device.i2c.registers[Register::ALS_PS_STATUS as usize] = 0x04;
+        assert_eq!(device.time_until_next_sample(1000).unwrap(), Some(0));
+        device.i2c.registers[Register::ALS_PS_STATUS as usize] = 0;
+        assert_eq!(device.time_until_next_sample(1200).unwrap(), Some(300));
+        assert_eq!(device.time_until_next_sample(1500).unwrap(), Some(0));
+        assert_eq!(device.time_until_next_sample(1700).unwrap(), Some(300));
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn read_lux_blocking_returns_fresh_data() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x04;
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert_eq!(sensor.read_lux_blocking(&mut delay, 100).unwrap(), 177.43);
+        assert_eq!(delay.elapsed_ms, 0);
+    }
+
+    #[test]
+    fn read_ps_blocking_times_out() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert!(matches!(
+            sensor.read_ps_blocking(&mut delay, 25),
+            Err(Error::Timeout)
+        ));
+        assert_eq!(delay.elapsed_ms, 25);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn try_get_lux_would_block_without_new_data() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8A] = 100;
+        assert!(matches!(sensor.try_get_lux(), Err(nb::Error::WouldBlock)));
+        sensor.i2c.registers[0x8C] = 0x04;
+        assert_eq!(sensor.try_get_lux().unwrap(), 177.43);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn measure_once_returns_to_standby() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8A] = 100;
+        sensor.i2c.registers[0x8C] = 0x05;
+        sensor.i2c.registers[0x8D] = 0x42;
+        let mut delay = DelayMock::default();
+        let measurement = sensor.measure_once(&mut delay, true).unwrap();
+        assert_eq!(measurement.lux, 177.43);
+        assert_eq!(measurement.ps, 0x42);
+        assert_eq!(sensor.i2c.registers[0x80] & 0x03, 0);
+        assert_eq!(sensor.i2c.registers[0x81] & 0x03, 0);
+        assert_eq!(delay.elapsed_ms, ALS_WAKEUP_TIME_MS);
+
+        sensor.i2c.registers[0x8C] = 0;
+        assert!(matches!(
+            sensor.measure_once(&mut delay, false),
+            Err(Error::Timeout)
+        ));
+        assert_eq!(sensor.i2c.registers[0x80] & 0x03, 0);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn measure_once_waits_for_both_flags_in_one_loop() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.status_clears_on_read = true;
+        sensor.i2c.registers[0x8A] = 100;
+        sensor.i2c.registers[0x8C] = 0x05;
+        sensor.i2c.registers[0x8D] = 0x42;
+        let measurement = sensor
+            .measure_once(&mut DelayMock::default(), true)
+            .unwrap();
+        assert_eq!(measurement.ps, 0x42);
+    }
+
+    /// Fails every write to one register
+    #[cfg(feature = "float")]
+    struct FailingRegister(I2cMock, u8);
+
+    #[cfg(feature = "float")]
+    impl i2c::Write for FailingRegister {
+        type Error = ();
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            if bytes[0] == self.1 {
+                return Err(());
+            }
+            self.0.write(address, bytes).map_err(|_| ())
+        }
+    }
+
+    #[cfg(feature = "float")]
+    impl i2c::WriteRead for FailingRegister {
+        type Error = ();
+        fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            self.0.write_read(address, bytes, buffer).map_err(|_| ())
+        }
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn measure_once_leaves_als_in_standby_if_ps_fails() {
+        let bus = FailingRegister(I2cMock::new(), Register::PS_CONTR);
+        let mut sensor = Ltr559::new_device(bus, SlaveAddr::default());
+        assert!(matches!(
+            sensor.measure_once(&mut DelayMock::default(), true),
+            Err(Error::I2C(()))
+        ));
+        assert_eq!(sensor.i2c.0.registers[0x80] & 0x03, 0);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn lux_averaged_over_constant_readings() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8A] = 100;
+        sensor.i2c.registers[0x8C] = 0x04;
+        let mut delay = DelayMock::default();
+        let average = sensor.get_lux_averaged(4, &mut delay).unwrap();
+        assert_eq!(average.samples, 4);
+        assert_eq!(average.mean, 177.43);
+        assert_eq!(average.std_dev(), 0.0);
+        assert!(matches!(
+            sensor.get_lux_averaged(0, &mut delay),
+            Err(Error::InvalidInputData)
+        ));
+        let spread = LuxAverage {
+            mean: 10.0,
+            variance: 4.0,
+            samples: 2,
+        };
+        assert!((spread.std_dev() - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ps_data_robust_trims_extremes() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8C] = 0x01;
+        sensor.i2c.registers[0x8D] = 100;
+        let mut delay = DelayMock::default();
+        assert_eq!(sensor.get_ps_data_robust(5, &mut delay).unwrap(), 100);
+        assert_eq!(sensor.get_ps_data_robust(1, &mut delay).unwrap(), 100);
+        assert!(matches!(
+            sensor.get_ps_data_robust(0, &mut delay),
+            Err(Error::InvalidInputData)
+        ));
+        sensor.i2c.registers[0x8C] = 0;
+        assert!(matches!(
+            sensor.get_ps_data_robust(3, &mut delay),
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[test]
+    fn wait_for_ps_data_returns_fresh_counts() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert!(matches!(
+            sensor.wait_for_ps_data(&mut delay, 30),
+            Err(Error::Timeout)
+        ));
+        assert_eq!(delay.elapsed_ms, 30);
+        sensor.i2c.registers[0x8C] = 0x03;
+        sensor.i2c.registers[0x8D] = 0x21;
+        sensor.i2c.registers[0x8E] = 0x03;
+        assert_eq!(
+            sensor.wait_for_ps_data(&mut delay, 30).unwrap(),
+            PsData {
+                counts: 0x321,
+                saturated: false,
+                new_data: true
+            }
+        );
+    }
+
+    #[test]
+    fn raw_measurement_combines_als_and_ps() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x88] = 0x34;
+        i2c.registers[0x89] = 0x12;
+        i2c.registers[0x8A] = 0x78;
+        i2c.registers[0x8B] = 0x56;
+        i2c.registers[0x8D] = 0xFF;
+        i2c.registers[0x8E] = 0x87;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        assert_eq!(
+            sensor.get_raw_measurement().unwrap(),
+            crate::RawMeasurement {
+                als_ch0: 0x5678,
+                als_ch1: 0x1234,
+                ps: 0x7FF,
+                ps_saturated: true,
+            }
+        );
+    }
+
+    #[test]
+    fn control_and_rate_readback() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.set_als_contr(AlsGain::Gain48x, false, true).unwrap();
+        sensor.set_ps_contr(true, true).unwrap();
+        sensor
+            .set_als_meas_rate(AlsIntTime::_200ms, AlsMeasRate::_500ms)
+            .unwrap();
+        sensor.set_ps_meas_rate(PsMeasRate::_70ms).unwrap();
+        let als_contr = sensor.get_als_contr().unwrap();
+        assert_eq!(als_contr.gain, AlsGain::Gain48x);
+        assert!(als_contr.active && !als_contr.sw_reset);
+        let ps_contr = sensor.get_ps_contr().unwrap();
+        assert!(ps_contr.active && ps_contr.saturation_indicator);
+        assert_eq!(
+            sensor.get_als_meas_rate().unwrap(),
+            (AlsIntTime::_200ms, AlsMeasRate::_500ms)
+        );
+        assert_eq!(sensor.get_ps_meas_rate().unwrap(), PsMeasRate::_70ms);
+    }
+
+    #[test]
+    fn reserved_gain_is_reported() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x80] = 4 << 2;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        assert!(matches!(
+            sensor.get_als_contr(),
+            Err(Error::InvalidInputData)
+        ));
+    }
+
+    #[test]
+    fn led_pulses_and_offset_readback() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor
+            .set_ps_led(LedPulse::Pulse40, LedDutyCycle::_75, LedCurrent::_50mA)
+            .unwrap();
+        sensor.set_ps_n_pulses(7).unwrap();
+        sensor.set_ps_offset(1000).unwrap();
+        assert_eq!(
+            sensor.get_ps_led().unwrap(),
+            (LedPulse::Pulse40, LedDutyCycle::_75, LedCurrent::_50mA)
+        );
+        assert_eq!(sensor.get_ps_n_pulses().unwrap(), 7);
+        assert_eq!(sensor.get_ps_offset().unwrap(), 1000);
+    }
+
+    #[test]
+    fn interrupt_settings_readback() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor
+            .set_interrupt(InterruptPinPolarity::High, InterruptMode::OnlyALS)
+            .unwrap();
+        sensor
+            .set_interrupt_persist(AlsPersist::_7v, PsPersist::_2v)
+            .unwrap();
+        assert_eq!(
+            sensor.get_interrupt().unwrap(),
+            (InterruptPinPolarity::High, InterruptMode::OnlyALS)
+        );
+        assert_eq!(
+            sensor.get_interrupt_persist().unwrap(),
+            (AlsPersist::_7v, PsPersist::_2v)
+        );
+    }
+
+    #[test]
+    fn thresholds_readback() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.set_als_low_limit_raw(100).unwrap();
+        sensor.set_als_high_limit_raw(40000).unwrap();
+        sensor.set_ps_low_limit_raw(0x0123).unwrap();
+        sensor.set_ps_high_limit_raw(0xF7FF).unwrap();
+        assert_eq!(
+            sensor.get_thresholds().unwrap(),
+            crate::Thresholds {
+                als_low: 100,
+                als_high: 40000,
+                ps_low: 0x0123,
+                ps_high: 0x07FF,
+            }
+        );
+    }
+
+    #[test]
+    fn als_thresholds_are_validated() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        assert!(matches!(
+            sensor.set_als_thresholds(2000, 1000),
+            Err(Error::InvalidInputData)
+        ));
+        sensor.set_als_thresholds(1000, 0x1234).unwrap();
+        let regs = sensor.destroy().registers;
+        assert_eq!(regs[0x97..=0x9A], [0x34, 0x12, 0xE8, 0x03]);
+    }
+
+    #[test]
+    fn ps_thresholds_are_validated() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        assert!(matches!(
+            sensor.set_ps_thresholds(100, 2048),
+            Err(Error::InvalidInputData)
+        ));
+        assert!(matches!(
+            sensor.set_ps_thresholds(300, 200),
+            Err(Error::InvalidInputData)
+        ));
+        sensor.set_ps_thresholds(0x100, 0x7FF).unwrap();
+        let regs = sensor.destroy().registers;
+        assert_eq!(regs[0x90..=0x93], [0xFF, 0x07, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn windows_saturate_at_bounds() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.set_als_window(100, 500).unwrap();
+        sensor.set_ps_window(2000, 100).unwrap();
+        let thresholds = sensor.get_thresholds().unwrap();
+        assert_eq!((thresholds.als_low, thresholds.als_high), (0, 600));
+        assert_eq!((thresholds.ps_low, thresholds.ps_high), (1900, 2047));
+        sensor.set_als_window(65000, 1000).unwrap();
+        assert_eq!(sensor.get_thresholds().unwrap().als_high, 0xFFFF);
+        assert!(matches!(
+            sensor.set_ps_window(2048, 10),
+            Err(Error::InvalidInputData)
+        ));
+    }
+
+    #[test]
+    fn rearm_als_window_around_last_reading() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 0xE8;
+        i2c.registers[0x8B] = 0x03;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        sensor.rearm_als_window(10).unwrap();
+        let thresholds = sensor.get_thresholds().unwrap();
+        assert_eq!((thresholds.als_low, thresholds.als_high), (900, 1100));
+        sensor.i2c.registers[0x8A..0x8C].copy_from_slice(&[0, 0]);
+        sensor.rearm_als_window(10).unwrap();
+        let thresholds = sensor.get_thresholds().unwrap();
+        assert_eq!((thresholds.als_low, thresholds.als_high), (0, 1));
+    }
+
+    #[test]
+    fn staged_settings_are_committed_in_bursts() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.begin_staging();
+        sensor.set_als_contr(AlsGain::Gain4x, false, true).unwrap();
+        sensor
+            .set_als_meas_rate(AlsIntTime::_50ms, AlsMeasRate::_50ms)
+            .unwrap();
+        sensor.set_als_thresholds(100, 200).unwrap();
+        assert_eq!(sensor.get_als_contr().unwrap().gain, AlsGain::Gain4x);
+        assert_eq!(sensor.i2c.registers[0x80], 0);
+        sensor.commit().unwrap();
+        assert!(!sensor.is_staging());
+        assert_eq!(sensor.i2c.registers[0x80], 0x09);
+        assert_eq!(sensor.i2c.registers[0x85], 0x08);
+        assert_eq!(sensor.i2c.registers[0x97..=0x9A], [200, 0, 100, 0]);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn discarding_staged_settings_marks_cache_dirty() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.begin_staging();
+        sensor.set_als_contr(AlsGain::Gain8x, false, true).unwrap();
+        sensor.discard_staged();
+        assert!(sensor.is_cache_dirty());
+        sensor.get_lux().unwrap();
+        assert_eq!(sensor.als_gain, AlsGain::Gain1x);
+        assert_eq!(sensor.i2c.registers[0x80], 0);
+    }
+
+    #[test]
+    fn led_config_is_checked_against_average_current() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let config = LedConfig {
+            pulse: LedPulse::Pulse30,
+            n_pulses: 15,
+            ..LedConfig::default()
+        };
+        assert_eq!(config.average_current_ua(PsMeasRate::_100ms), 500);
+        sensor.set_led_config(&config, 1000).unwrap();
+        assert_eq!(sensor.i2c.registers[0x82], 0x1F);
+        assert_eq!(sensor.i2c.registers[0x83], 15);
+
+        sensor.set_ps_meas_rate(PsMeasRate::_10ms).unwrap();
+        assert!(matches!(
+            sensor.set_led_config(&config, 1000),
+            Err(Error::InvalidInputData)
+        ));
+        sensor.set_led_config_unchecked(&config).unwrap();
+        let config = LedConfig {
+            n_pulses: 0,
+            ..config
+        };
+        assert!(matches!(
+            sensor.set_led_config_unchecked(&config),
+            Err(Error::InvalidInputData)
+        ));
+    }
+
+    #[test]
+    fn standby_and_wake_keep_settings() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.set_als_contr(AlsGain::Gain8x, false, true).unwrap();
+        sensor.set_ps_contr(true, false).unwrap();
+        assert!(sensor.is_active().unwrap());
+        sensor.standby().unwrap();
+        assert!(!sensor.is_active().unwrap());
+        assert_eq!(sensor.i2c.registers[0x80], 0x0C);
+        assert_eq!(sensor.i2c.registers[0x81], 0x20);
+        sensor.standby().unwrap();
+        sensor.wake().unwrap();
+        assert_eq!(sensor.i2c.registers[0x80], 0x0D);
+        assert_eq!(sensor.i2c.registers[0x81], 0x20);
+        sensor.wake().unwrap();
+        assert_eq!(sensor.i2c.registers[0x81], 0x23);
+    }
+
+    #[test]
+    fn sw_reset_waits_and_resets_driver_state() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.reset_completes = true;
+        let mut delay = DelayMock::default();
+        sensor.set_als_contr(AlsGain::Gain8x, false, true).unwrap();
+        sensor.begin_staging();
+        sensor.sw_reset(&mut delay).unwrap();
+        assert!(!sensor.is_staging());
+        assert_eq!(sensor.als_gain, AlsGain::default());
+        assert_eq!(delay.elapsed_ms, 100);
+        assert_eq!(sensor.i2c.registers[0x80], 0x00);
+    }
+
+    #[test]
+    fn sw_reset_times_out_if_reset_bit_stays_set() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        assert!(matches!(sensor.sw_reset(&mut delay), Err(Error::Timeout)));
+        assert_eq!(sensor.i2c.registers[0x80], 0x02);
+    }
+
+    #[test]
+    fn destroy_and_shutdown_leaves_device_in_standby() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.set_als_contr(AlsGain::Gain4x, false, true).unwrap();
+        sensor.set_ps_contr(false, true).unwrap();
+        sensor.begin_staging();
+        let i2c = sensor.destroy_and_shutdown().ok().unwrap();
+        assert_eq!(i2c.registers[0x80], 0x08);
+        assert_eq!(i2c.registers[0x81], 0x00);
+    }
+
+    #[test]
+    fn read_all_decodes_burst() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x88..=0x8E].copy_from_slice(&[0x34, 0x12, 0x78, 0x56, 0x04, 0x21, 0x83]);
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let data = sensor.read_all().unwrap();
+        assert_eq!(data.als_ch0, 0x5678);
+        assert_eq!(data.als_ch1, 0x1234);
+        assert!(data.status.als_data_valid && data.status.als_data_status);
+        assert_eq!(data.ps, 0x321);
+        assert!(data.ps_saturated);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn measurement_uses_gain_from_status() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 100;
+        i2c.registers[0x8C] = 0x24;
+        i2c.registers[0x8D] = 50;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let measurement = sensor.get_measurement().unwrap();
+        assert_eq!(measurement.als_gain_used, AlsGain::Gain4x);
+        assert_eq!(measurement.als_int_used, AlsIntTime::_100ms);
+        assert_eq!(measurement.lux, 177.43 / 4.0);
+        assert_eq!(measurement.ps, 50);
+    }
+
+    #[test]
+    fn checked_als_data_requires_new_valid_data() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        assert!(matches!(
+            sensor.get_als_data_checked(),
+            Err(Error::StaleData)
+        ));
+        sensor.i2c.registers[0x8C] = 0x84;
+        assert!(matches!(
+            sensor.get_als_data_checked(),
+            Err(Error::StaleData)
+        ));
+        sensor.i2c.registers[0x8C] = 0x04;
+        assert_eq!(
+            sensor.get_als_data_checked().unwrap(),
+            AlsData { ch0: 100, ch1: 0 }
+        );
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn als_data_helpers() {
+        let data = AlsData { ch0: 300, ch1: 100 };
+        assert_eq!(data.ratio(), 0.25);
+        assert!(!data.is_saturated());
+        assert_eq!(AlsData::default().ratio(), 1.0);
+        assert!(AlsData {
+            ch0: 0xFFFF,
+            ch1: 0
+        }
+        .is_saturated());
+    }
+
+    #[test]
+    fn ps_data_with_freshness() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8C] = 0x01;
+        i2c.registers[0x8D] = 0x34;
+        i2c.registers[0x8E] = 0x82;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let data = sensor.get_ps().unwrap();
+        assert_eq!(
+            data,
+            PsData {
+                counts: 0x234,
+                saturated: true,
+                new_data: true
+            }
+        );
+    }
+
+    #[test]
+    fn lux_millis_from_device() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        assert_eq!(sensor.get_lux_millis().unwrap(), 177_430);
+    }
+
+    #[test]
+    fn pimoroni_lux_holds_data_until_new_data() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        sensor.set_lux_algorithm(LuxAlgorithm::Pimoroni);
+        assert_eq!(sensor.get_lux_millis().unwrap(), 0);
+        sensor.i2c.registers[0x8C] = 0x04;
+        assert_eq!(sensor.get_lux_millis().unwrap(), 177_430);
+        sensor.i2c.registers[0x8C] = 0;
+        sensor.i2c.registers[0x8A] = 200;
+        assert_eq!(sensor.get_lux_millis().unwrap(), 177_430);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn window_factor_scales_lux() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        sensor.set_window_factor(2.0).unwrap();
+        assert_eq!(sensor.get_lux().unwrap(), 177.43 * 2.0);
+        assert_eq!(sensor.get_lux_millis().unwrap(), 354_860);
+        #[cfg(feature = "fixed")]
+        assert_eq!(sensor.get_lux_fixed().unwrap().to_num::<f32>(), 354.86);
+        assert_eq!(sensor.lux_to_raw(177.43 * 2.0), 100);
+        sensor.set_window_factor(1.0 / 0.4).unwrap();
+        assert_eq!(sensor.get_lux_millis().unwrap(), 443_575);
+        assert_eq!(sensor.lux_to_raw(443.575), 100);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn rejects_out_of_range_lux_corrections() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.set_window_factor(2.0).unwrap();
+        for factor in [0.0, -1.0, 1.0 / 131_072.0, 32768.0, f32::NAN, f32::INFINITY] {
+            assert!(matches!(
+                sensor.set_window_factor(factor),
+                Err(Error::InvalidInputData)
+            ));
+        }
+        assert_eq!(sensor.window_factor(), 2.0);
+        sensor.set_window_factor(1.0 / 65536.0).unwrap();
+        sensor.set_window_factor(32767.0).unwrap();
+        assert_eq!(sensor.window_factor(), 32767.0);
+
+        sensor.set_lux_offset(-1.0).unwrap();
+        for offset in [-32768.0, 32768.0, f32::NAN, f32::NEG_INFINITY] {
+            assert!(matches!(
+                sensor.set_lux_offset(offset),
+                Err(Error::InvalidInputData)
+            ));
+        }
+        assert_eq!(sensor.lux_offset(), -1.0);
+    }
+
+    #[test]
+    fn dark_counts_are_subtracted_before_lux() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x88..0x8C].copy_from_slice(&[2, 0, 105, 0]);
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        sensor.set_dark_counts(5, 3);
+        assert_eq!(sensor.get_lux_millis().unwrap(), 177_430);
+        assert_eq!(sensor.get_als_raw_data().unwrap(), (105, 2));
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn lux_offset_is_added_after_window_factor() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        sensor.set_window_factor(2.0).unwrap();
+        sensor.set_lux_offset(-0.86).unwrap();
+        assert_eq!(sensor.get_lux().unwrap(), 177.43 * 2.0 - 0.86);
+        assert_eq!(sensor.get_lux_millis().unwrap(), 354_000);
+        #[cfg(feature = "fixed")]
+        assert_eq!(sensor.get_lux_fixed().unwrap().to_num::<f32>(), 354.0);
+        assert_eq!(sensor.lux_to_raw(177.43 * 2.0 - 0.86), 100);
+        sensor.set_lux_offset(-1000.0).unwrap();
+        assert_eq!(sensor.get_lux().unwrap(), 0.0);
+        assert_eq!(sensor.get_lux_millis().unwrap(), 0);
+        #[cfg(feature = "fixed")]
+        assert_eq!(sensor.get_lux_fixed().unwrap().to_num::<f32>(), 0.0);
+    }
+
+    #[test]
+    fn light_source_from_ir_ratio() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        assert_eq!(sensor.get_light_source().unwrap(), None);
+        // CH1 = 0x0110, CH0 = 0x0330: ratio 250 per mille
+        sensor.i2c.registers[0x88..0x8C].copy_from_slice(&[0x10, 0x01, 0x30, 0x03]);
+        assert_eq!(
+            sensor.get_als_data_coherent().unwrap(),
+            AlsData {
+                ch0: 0x0330,
+                ch1: 0x0110
+            }
+        );
+        assert_eq!(
+            sensor.get_light_source().unwrap(),
+            Some(LightSource::Sunlight)
+        );
+        sensor.i2c.registers[0x89] = 0;
+        assert_eq!(
+            sensor.get_light_source().unwrap(),
+            Some(LightSource::FluorescentOrLed)
+        );
+        sensor.i2c.registers[0x88..0x8C].copy_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(
+            sensor.get_light_source().unwrap(),
+            Some(LightSource::Incandescent)
+        );
+    }
+
+    #[test]
+    fn status_reports_ps_saturation() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8C] = 0x01;
+        sensor.i2c.registers[0x8E] = 0x87;
+        let status = sensor.get_status().unwrap();
+        assert!(status.ps_data_status && status.ps_saturated);
+        assert!(sensor.is_ps_saturated().unwrap());
+        sensor.i2c.registers[0x8E] = 0x07;
+        assert!(!sensor.get_status().unwrap().ps_saturated);
+        assert!(!sensor.is_ps_saturated().unwrap());
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn lux_stats_track_readings() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8A] = 100;
+        sensor.get_lux().unwrap();
+        sensor.i2c.registers[0x8A] = 0;
+        sensor.get_lux().unwrap();
+        assert_eq!(sensor.lux_stats().count(), 2);
+        assert_eq!(sensor.lux_stats().max(), Some(177.43));
+        assert_eq!(sensor.lux_stats().min(), Some(0.0));
+        sensor.reset_lux_stats();
+        assert_eq!(sensor.lux_stats().mean(), None);
+    }
+
+    #[test]
+    fn interrupt_cause_is_cached_for_peek() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        assert_eq!(sensor.peek_status(), None);
+        sensor.i2c.registers[0x8C] = 0x0A;
+        let cause = sensor.read_interrupt_cause().unwrap();
+        assert!(cause.als && cause.ps);
+        let status = sensor.peek_status().unwrap();
+        assert!(status.als_interrupt_status && status.ps_interrupt_status);
+        sensor.reset_internal_driver_state();
+        assert_eq!(sensor.peek_status(), None);
+    }
+
+    #[test]
+    fn wait_for_interrupt_polls_pin_at_configured_level() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor
+            .set_interrupt(InterruptPinPolarity::High, InterruptMode::Both)
+            .unwrap();
+        sensor.i2c.registers[0x8C] = 0x08;
+        let mut pin = InputPinMock {
+            active_high: true,
+            active_after: 3,
+            ..Default::default()
+        };
+        let mut delay = DelayMock::default();
+        let cause = sensor.wait_for_interrupt(&mut pin, &mut delay, 10).unwrap();
+        assert!(cause.als && !cause.ps);
+        assert_eq!(delay.elapsed_ms, 3);
+    }
+
+    #[test]
+    fn wait_for_interrupt_times_out() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut pin = InputPinMock {
+            active_after: u32::MAX,
+            ..Default::default()
+        };
+        let mut delay = DelayMock::default();
+        let result = sensor.wait_for_interrupt(&mut pin, &mut delay, 5);
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert_eq!(delay.elapsed_ms, 5);
+    }
+
+    #[test]
+    fn acknowledge_and_rearm_tracks_triggering_sensor() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8A..0x8C].copy_from_slice(&[0xE8, 0x03]);
+        sensor.i2c.registers[0x8C] = 0x08;
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x20, 0x03]);
+        let tracking = Tracking {
+            als_percent: 10,
+            ps_delta: 50,
+        };
+        let data = sensor.acknowledge_and_rearm(Some(tracking)).unwrap();
+        assert!(data.status.als_interrupt_status);
+        assert_eq!((data.als_ch0, data.ps), (1000, 0x320));
+        let thresholds = sensor.get_thresholds().unwrap();
+        assert_eq!((thresholds.als_low, thresholds.als_high), (900, 1100));
+        // the PS did not trigger, its thresholds are untouched
+        assert_eq!((thresholds.ps_low, thresholds.ps_high), (0, 0));
+
+        sensor.i2c.registers[0x8C] = 0x02;
+        sensor.acknowledge_and_rearm(None).unwrap();
+        assert_eq!(sensor.get_thresholds().unwrap().ps_high, 0);
+        sensor.acknowledge_and_rearm(Some(tracking)).unwrap();
+        let thresholds = sensor.get_thresholds().unwrap();
+        assert_eq!((thresholds.ps_low, thresholds.ps_high), (750, 850));
+    }
+
+    #[test]
+    fn interrupt_self_test_restores_configuration() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor
+            .set_interrupt(InterruptPinPolarity::High, InterruptMode::OnlyPS)
+            .unwrap();
+        sensor.set_als_thresholds(100, 200).unwrap();
+        sensor
+            .set_interrupt_persist(AlsPersist::_4v, PsPersist::_2v)
+            .unwrap();
+        sensor.i2c.registers[0x8C] = 0x08;
+        let mut pin = InputPinMock {
+            active_high: true,
+            active_after: 2,
+            active_reads: Some(1),
+            ..Default::default()
+        };
+        let mut delay = DelayMock::default();
+        assert!(sensor.interrupt_self_test(&mut pin, &mut delay).unwrap());
+        assert_eq!(delay.elapsed_ms, 1);
+        let registers = &sensor.i2c.registers;
+        assert_eq!(registers[0x80] & 1, 0);
+        assert_eq!(registers[0x8F], 0x05);
+        assert_eq!(registers[0x97..=0x9A], [200, 0, 100, 0]);
+        assert_eq!(registers[0x9E], 0x13);
+    }
+
+    #[test]
+    fn interrupt_self_test_detects_stuck_pin() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8C] = 0x08;
+        // active low pin that is always low
+        let mut pin = InputPinMock::default();
+        let mut delay = DelayMock::default();
+        assert!(!sensor.interrupt_self_test(&mut pin, &mut delay).unwrap());
+        assert_eq!(delay.elapsed_ms, 0);
+    }
+
+    #[test]
+    fn diagnostics_count_interrupts_and_saturations() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8C] = 0x0B;
+        sensor.i2c.registers[0x8E] = 0x80;
+        sensor.read_interrupt_cause().unwrap();
+        sensor.i2c.registers[0x8C] = 0x08;
+        sensor.get_status().unwrap();
+        let counters = sensor.diagnostics();
+        assert_eq!((counters.als_interrupts, counters.ps_interrupts), (2, 1));
+        assert_eq!(counters.ps_saturations, 1);
+        assert_eq!(counters.i2c_errors, 0);
+        sensor.reset_diagnostics();
+        assert_eq!(sensor.diagnostics(), DiagnosticCounters::default());
+    }
+
+    struct FailingBus;
+
+    impl i2c::WriteRead for FailingBus {
+        type Error = ();
+        fn write_read(&mut self, _: u8, _: &[u8], _: &mut [u8]) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn diagnostics_count_bus_errors() {
+        let mut sensor = Ltr559::new_device(FailingBus, SlaveAddr::default());
+        assert!(sensor.get_status().is_err());
+        assert!(sensor.get_thresholds().is_err());
+        assert_eq!(sensor.diagnostics().i2c_errors, 2);
+    }
+
+    #[test]
+    fn window_mode_inside_follows_current_value() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8A] = 50;
+        sensor
+            .set_als_window_mode(100, 200, WindowMode::Inside)
+            .unwrap();
+        assert_eq!(sensor.i2c.registers[0x97..=0x9A], [99, 0, 0, 0]);
+        sensor.i2c.registers[0x8A] = 150;
+        sensor
+            .set_als_window_mode(100, 200, WindowMode::Inside)
+            .unwrap();
+        assert_eq!(sensor.i2c.registers[0x97..=0x9A], [0, 0, 0xFF, 0xFF]);
+        sensor
+            .set_als_window_mode(100, 200, WindowMode::Outside)
+            .unwrap();
+        assert_eq!(sensor.i2c.registers[0x97..=0x9A], [200, 0, 100, 0]);
+
+        sensor.i2c.registers[0x8D] = 0xFF;
+        sensor
+            .set_ps_window_mode(100, 200, WindowMode::Inside)
+            .unwrap();
+        let thresholds = sensor.get_thresholds().unwrap();
+        assert_eq!((thresholds.ps_low, thresholds.ps_high), (201, 0x07FF));
+        assert!(matches!(
+            sensor.set_ps_window_mode(100, 2048, WindowMode::Outside),
+            Err(Error::InvalidInputData)
+        ));
+    }
 }
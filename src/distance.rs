@@ -0,0 +1,222 @@
+//! Estimation of the target distance from the PS counts
+//!
+//! The PS counts for a given distance depend on the target reflectance,
+//! the cover glass and the enclosure, so a [`DistanceModel`] is fitted from
+//! calibration points measured in the final product. It either
+//! interpolates linearly between the points or follows a power law
+//! `distance = scale * counts^-exponent` fitted to them.
+//!
+//! ```
+//! use ltr_559::DistanceModel;
+//!
+//! // (counts, millimetres)
+//! let points = [(1600, 25), (400, 50), (100, 100)];
+//! let piecewise = DistanceModel::piecewise(&points).unwrap();
+//! assert_eq!(piecewise.distance_mm(1000), 38);
+//! let power_law = DistanceModel::fit_power_law(&points).unwrap();
+//! assert_eq!(power_law.distance_mm(900), 33);
+//! ```
+use crate::hal::blocking::i2c;
+use crate::math::{exp, ln};
+use crate::{marker, Error, Ltr559};
+
+/// Largest number of calibration points of a piecewise model
+pub const MAX_CALIBRATION_POINTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Kind {
+    /// Points sorted by ascending counts
+    Piecewise {
+        points: [(u16, u16); MAX_CALIBRATION_POINTS],
+        len: u8,
+    },
+    PowerLaw {
+        scale: f32,
+        exponent: f32,
+    },
+}
+
+/// Maps PS counts to a distance in millimetres
+///
+/// With the `serde` feature it can be serialized, e.g. as part of a
+/// [`CalibrationData`](crate::CalibrationData).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DistanceModel {
+    kind: Kind,
+}
+
+impl DistanceModel {
+    /// Interpolate linearly between `(counts, millimetres)` calibration
+    /// points.
+    ///
+    /// Counts beyond the calibrated range give the distance of the nearest
+    /// point. Returns `None` with fewer than 2 or more than
+    /// [`MAX_CALIBRATION_POINTS`] points, or if two points have the same
+    /// counts.
+    pub fn piecewise(calibration: &[(u16, u16)]) -> Option<Self> {
+        let len = calibration.len();
+        if !(2..=MAX_CALIBRATION_POINTS).contains(&len) {
+            return None;
+        }
+        let mut points = [(0, 0); MAX_CALIBRATION_POINTS];
+        points[..len].copy_from_slice(calibration);
+        points[..len].sort_unstable_by_key(|point| point.0);
+        if points[..len].windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return None;
+        }
+        Some(DistanceModel {
+            kind: Kind::Piecewise {
+                points,
+                len: len as u8,
+            },
+        })
+    }
+
+    /// Create a power law model `distance = scale * counts^-exponent`
+    pub fn power_law(scale: f32, exponent: f32) -> Self {
+        DistanceModel {
+            kind: Kind::PowerLaw { scale, exponent },
+        }
+    }
+
+    /// Fit a power law model to `(counts, millimetres)` calibration points
+    /// by least squares on their logarithms.
+    ///
+    /// Returns `None` with fewer than 2 points, if a point is zero or if
+    /// all points have the same counts.
+    pub fn fit_power_law(calibration: &[(u16, u16)]) -> Option<Self> {
+        if calibration.len() < 2 || calibration.iter().any(|&(c, d)| c == 0 || d == 0) {
+            return None;
+        }
+        let n = calibration.len() as f32;
+        let (mut sum_x, mut sum_y, mut sum_xx, mut sum_xy) = (0.0, 0.0, 0.0, 0.0);
+        for &(counts, distance) in calibration {
+            let x = ln(f32::from(counts));
+            let y = ln(f32::from(distance));
+            sum_x += x;
+            sum_y += y;
+            sum_xx += x * x;
+            sum_xy += x * y;
+        }
+        let denominator = n * sum_xx - sum_x * sum_x;
+        if denominator <= f32::EPSILON * n * sum_xx {
+            return None;
+        }
+        let slope = (n * sum_xy - sum_x * sum_y) / denominator;
+        let scale = exp((sum_y - slope * sum_x) / n);
+        Some(DistanceModel::power_law(scale, -slope))
+    }
+
+    /// `(scale, exponent)` of a power law model, `None` for a piecewise one
+    pub fn power_law_parameters(&self) -> Option<(f32, f32)> {
+        match self.kind {
+            Kind::PowerLaw { scale, exponent } => Some((scale, exponent)),
+            Kind::Piecewise { .. } => None,
+        }
+    }
+
+    /// Calibration points of a piecewise model sorted by ascending counts,
+    /// empty for a power law one
+    pub fn points(&self) -> &[(u16, u16)] {
+        match &self.kind {
+            Kind::Piecewise { points, len } => {
+                &points[..usize::from(*len).min(MAX_CALIBRATION_POINTS)]
+            }
+            Kind::PowerLaw { .. } => &[],
+        }
+    }
+
+    /// Distance in millimetres for `counts`, saturating at `u16::MAX`
+    pub fn distance_mm(&self, counts: u16) -> u16 {
+        match self.kind {
+            Kind::Piecewise { .. } => interpolate(self.points(), counts),
+            Kind::PowerLaw { scale, exponent } => {
+                if counts == 0 {
+                    return u16::MAX;
+                }
+                let distance = scale * exp(-exponent * ln(f32::from(counts)));
+                (distance + 0.5) as u16
+            }
+        }
+    }
+}
+
+fn interpolate(points: &[(u16, u16)], counts: u16) -> u16 {
+    // a deserialized model may have no points
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return u16::MAX,
+    };
+    if counts <= first.0 {
+        return first.1;
+    }
+    if counts >= last.0 {
+        return last.1;
+    }
+    let index = points
+        .iter()
+        .position(|point| point.0 > counts)
+        .unwrap_or(0);
+    let ((c0, d0), (c1, d1)) = (points[index - 1], points[index]);
+    let offset = i64::from(d1) - i64::from(d0);
+    let distance = i64::from(d0) + offset * i64::from(counts - c0) / i64::from(c1 - c0);
+    distance as u16
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Read the PS counts and convert them to a distance in millimetres
+    /// with `model`.
+    pub fn get_distance_mm(&mut self, model: &DistanceModel) -> Result<u16, Error<E>> {
+        let (counts, _) = self.get_ps_data()?;
+        Ok(model.distance_mm(counts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    #[test]
+    fn piecewise_interpolates_and_clamps() {
+        assert_eq!(DistanceModel::piecewise(&[(10, 100)]), None);
+        assert_eq!(DistanceModel::piecewise(&[(10, 100), (10, 50)]), None);
+        let model = DistanceModel::piecewise(&[(800, 20), (100, 90), (300, 50)]).unwrap();
+        assert_eq!(model.points(), &[(100, 90), (300, 50), (800, 20)]);
+        assert_eq!(model.distance_mm(200), 70);
+        assert_eq!(model.distance_mm(550), 35);
+        assert_eq!(model.distance_mm(50), 90);
+        assert_eq!(model.distance_mm(2000), 20);
+        assert_eq!(model.power_law_parameters(), None);
+        let full_range = DistanceModel::piecewise(&[(0, 0), (u16::MAX, u16::MAX)]).unwrap();
+        assert_eq!(full_range.distance_mm(40000), 40000);
+    }
+
+    #[test]
+    fn fits_power_law() {
+        assert_eq!(DistanceModel::fit_power_law(&[(0, 10), (10, 5)]), None);
+        assert_eq!(DistanceModel::fit_power_law(&[(10, 10), (10, 5)]), None);
+        let model = DistanceModel::fit_power_law(&[(100, 100), (400, 50), (1600, 25)]).unwrap();
+        let (scale, exponent) = model.power_law_parameters().unwrap();
+        assert!((scale - 1000.0).abs() < 0.1);
+        assert!((exponent - 0.5).abs() < 1e-4);
+        assert_eq!(model.distance_mm(0), u16::MAX);
+        assert_eq!(model.distance_mm(1), 1000);
+        assert!(model.points().is_empty());
+    }
+
+    #[test]
+    fn reads_distance() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x90, 0x01]);
+        let model = DistanceModel::power_law(1000.0, 0.5);
+        assert_eq!(sensor.get_distance_mm(&model).unwrap(), 50);
+    }
+}
@@ -0,0 +1,229 @@
+//! Typed sensor events decoded from the status and data registers
+use crate::device_impl::{decode_ps_data, Register};
+use crate::hal::blocking::i2c;
+use crate::{Clock, Error, Ltr559, Status, Timestamped};
+use heapless::Vec;
+
+/// A sensor event. See [`Ltr559::poll_events()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// ALS interrupt with CH0 above the upper threshold. Contains CH0.
+    AlsAboveHigh(u16),
+    /// ALS interrupt with CH0 below the lower threshold. Contains CH0.
+    AlsBelowLow(u16),
+    /// PS interrupt with the counts above the upper threshold, i.e. an
+    /// object came near. Contains the PS counts.
+    PsNear(u16),
+    /// PS interrupt with the counts below the lower threshold, i.e. an
+    /// object went away. Contains the PS counts.
+    PsFar(u16),
+    /// New ALS data with a channel at full scale
+    AlsSaturated,
+    /// New PS data with the saturation indicator asserted
+    PsSaturated,
+}
+
+/// Events decoded from one read. At most one ALS threshold event, one PS
+/// threshold event and the two saturation events can occur at once.
+pub type Events = Vec<Event, 4>;
+
+/// Receives the events decoded by [`Ltr559::tick()`]
+///
+/// Implemented for every `FnMut(Event)` closure.
+pub trait EventHandler {
+    /// Handle one event
+    fn on_event(&mut self, event: Event);
+}
+
+impl<F: FnMut(Event)> EventHandler for F {
+    fn on_event(&mut self, event: Event) {
+        self(event)
+    }
+}
+
+/// Number of registers from ALS_DATA_CH1_0 to ALS_THRES_LOW_1
+const BLOCK_SIZE: usize = (Register::ALS_THRES_LOW_1 - Register::ALS_DATA_CH1_0 + 1) as usize;
+
+fn at(block: &[u8; BLOCK_SIZE], register: u8) -> u8 {
+    block[usize::from(register - Register::ALS_DATA_CH1_0)]
+}
+
+fn u16_at(block: &[u8; BLOCK_SIZE], register: u8) -> u16 {
+    u16::from_le_bytes([at(block, register), at(block, register + 1)])
+}
+
+/// Decode the events from the registers ALS_DATA_CH1_0 to ALS_THRES_LOW_1
+fn decode(status: &Status, block: &[u8; BLOCK_SIZE]) -> Events {
+    let ch0 = u16_at(block, Register::ALS_DATA_CH0_0);
+    let ch1 = u16_at(block, Register::ALS_DATA_CH1_0);
+    let (ps, _) = decode_ps_data(
+        at(block, Register::PS_DATA_0),
+        at(block, Register::PS_DATA_1),
+    );
+    let ps_high = u16_at(block, Register::PS_THRES_UP_0) & 0x07FF;
+    let ps_low = u16_at(block, Register::PS_THRES_LOW_0) & 0x07FF;
+
+    // the capacity covers every combination, pushing cannot fail
+    let mut events = Events::new();
+    if status.als_interrupt_status {
+        if ch0 > u16_at(block, Register::ALS_THRES_UP_0) {
+            let _ = events.push(Event::AlsAboveHigh(ch0));
+        } else if ch0 < u16_at(block, Register::ALS_THRES_LOW_0) {
+            let _ = events.push(Event::AlsBelowLow(ch0));
+        }
+    }
+    if status.ps_interrupt_status {
+        if ps > ps_high {
+            let _ = events.push(Event::PsNear(ps));
+        } else if ps < ps_low {
+            let _ = events.push(Event::PsFar(ps));
+        }
+    }
+    if status.als_data_status && (ch0 == u16::MAX || ch1 == u16::MAX) {
+        let _ = events.push(Event::AlsSaturated);
+    }
+    if status.ps_data_status && status.ps_saturated {
+        let _ = events.push(Event::PsSaturated);
+    }
+    events
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+{
+    /// Read the status, the data and the thresholds in a single burst and
+    /// decode the pending events.
+    ///
+    /// Threshold events are reported when the corresponding interrupt flag
+    /// is set, saturation events when new saturated data is available. Note
+    /// that reading the status clears the flags, so every event is reported
+    /// once.
+    pub fn poll_events(&mut self) -> Result<Events, Error<E>> {
+        let mut block = [0; BLOCK_SIZE];
+        self.read_registers(Register::ALS_DATA_CH1_0, &mut block)?;
+        let status = Status::from_registers(
+            at(&block, Register::ALS_PS_STATUS),
+            at(&block, Register::PS_DATA_1),
+        );
+        self.record_status(status);
+        let events = decode(&status, &block);
+        if events.contains(&Event::AlsSaturated) {
+            self.diagnostics.als_saturations = self.diagnostics.als_saturations.wrapping_add(1);
+        }
+        Ok(events)
+    }
+
+    /// Poll the events (see [`poll_events()`](#method.poll_events)),
+    /// stamped with the time of `clock` at the read.
+    pub fn poll_events_timestamped<C: Clock>(
+        &mut self,
+        clock: &mut C,
+    ) -> Result<Timestamped<Events>, Error<E>> {
+        let value = self.poll_events()?;
+        Ok(Timestamped {
+            timestamp_ms: clock.now_ms(),
+            value,
+        })
+    }
+
+    /// Poll the events (see [`poll_events()`](#method.poll_events)) and
+    /// pass each one to `handler`, in the order they were decoded.
+    ///
+    /// Call this periodically or when the interrupt pin is asserted.
+    /// Returns the number of events handled.
+    pub fn tick<H: EventHandler>(&mut self, handler: &mut H) -> Result<usize, Error<E>> {
+        let events = self.poll_events()?;
+        for event in &events {
+            handler.on_event(*event);
+        }
+        Ok(events.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    fn sensor() -> Ltr559<I2cMock, crate::ic::Ltr559> {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        // ALS window 100..1000, PS window 200..600
+        sensor.i2c.registers[0x97..0x9B].copy_from_slice(&[0xE8, 0x03, 100, 0]);
+        sensor.i2c.registers[0x90..0x94].copy_from_slice(&[0x58, 0x02, 200, 0]);
+        sensor
+    }
+
+    struct Counter {
+        near: u32,
+        other: u32,
+    }
+
+    impl EventHandler for Counter {
+        fn on_event(&mut self, event: Event) {
+            match event {
+                Event::PsNear(_) => self.near += 1,
+                _ => self.other += 1,
+            }
+        }
+    }
+
+    #[test]
+    fn tick_dispatches_to_handlers() {
+        let mut sensor = sensor();
+        sensor.i2c.registers[0x8C] = 0x0A;
+        sensor.i2c.registers[0x8A] = 50;
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x20, 0x03]);
+        let mut seen = Events::new();
+        let handled = sensor.tick(&mut |event| seen.push(event).unwrap()).unwrap();
+        assert_eq!(handled, 2);
+        assert_eq!(&seen[..], &[Event::AlsBelowLow(50), Event::PsNear(0x320)]);
+
+        let mut counter = Counter { near: 0, other: 0 };
+        sensor.tick(&mut counter).unwrap();
+        assert_eq!((counter.near, counter.other), (1, 1));
+    }
+
+    #[test]
+    fn events_are_stamped() {
+        let mut sensor = sensor();
+        sensor.i2c.registers[0x8C] = 0x08;
+        sensor.i2c.registers[0x8A] = 50;
+        let events = sensor.poll_events_timestamped(&mut || 77).unwrap();
+        assert_eq!(events.timestamp_ms, 77);
+        assert_eq!(&events.value[..], &[Event::AlsBelowLow(50)]);
+    }
+
+    #[test]
+    fn no_event_without_flags() {
+        let mut sensor = sensor();
+        sensor.i2c.registers[0x8A] = 50;
+        assert!(sensor.poll_events().unwrap().is_empty());
+    }
+
+    #[test]
+    fn threshold_events_by_direction() {
+        let mut sensor = sensor();
+        sensor.i2c.registers[0x8C] = 0x0A;
+        sensor.i2c.registers[0x8A] = 50;
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x20, 0x03]);
+        let events = sensor.poll_events().unwrap();
+        assert_eq!(&events[..], &[Event::AlsBelowLow(50), Event::PsNear(0x320)]);
+
+        sensor.i2c.registers[0x8A..0x8C].copy_from_slice(&[0xFF, 0xFF]);
+        sensor.i2c.registers[0x8C] = 0x0F;
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[10, 0x80]);
+        let events = sensor.poll_events().unwrap();
+        assert_eq!(
+            &events[..],
+            &[
+                Event::AlsAboveHigh(0xFFFF),
+                Event::PsFar(10),
+                Event::AlsSaturated,
+                Event::PsSaturated
+            ]
+        );
+    }
+}
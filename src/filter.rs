@@ -0,0 +1,378 @@
+//! Composable filters for measurement values
+//!
+//! Filters can be chained with [`Filter::then()`], for example
+//! `Median::<f32, 5>::new().then(Ewma::new(0.2))`, and plugged into the
+//! acquisition loop with [`Ltr559::run_filtered()`](crate::Ltr559::run_filtered).
+//!
+//! A [`Median`] also rejects single-sample spikes in PS counts:
+//!
+//! ```
+//! use ltr_559::Median;
+//!
+//! let mut ps = Median::<u16, 3>::new();
+//! assert_eq!(ps.update(10), None);
+//! assert_eq!(ps.update(900), None);
+//! assert_eq!(ps.update(12), Some(12));
+//! ```
+#[cfg(feature = "float")]
+use crate::AlsMeasRate;
+
+/// A filter processing one value at a time
+#[cfg(feature = "float")]
+pub trait Filter {
+    /// Feed a new value into the filter.
+    ///
+    /// Returns the filtered value, or `None` while the filter has not
+    /// collected enough values to produce an output.
+    fn update(&mut self, value: f32) -> Option<f32>;
+
+    /// Chain this filter with another one, feeding its output into `next`
+    fn then<F: Filter>(self, next: F) -> Chain<Self, F>
+    where
+        Self: Sized,
+    {
+        Chain { first: self, next }
+    }
+}
+
+/// Two filters applied one after the other. See [`Filter::then()`].
+#[cfg(feature = "float")]
+#[derive(Debug, Clone, Copy)]
+pub struct Chain<A, B> {
+    first: A,
+    next: B,
+}
+
+#[cfg(feature = "float")]
+impl<A: Filter, B: Filter> Filter for Chain<A, B> {
+    fn update(&mut self, value: f32) -> Option<f32> {
+        self.first.update(value).and_then(|v| self.next.update(v))
+    }
+}
+
+/// Exponentially weighted moving average
+#[cfg(feature = "float")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ewma {
+    alpha: f32,
+    state: Option<f32>,
+}
+
+#[cfg(feature = "float")]
+impl Ewma {
+    /// Create a new filter with the given smoothing factor.
+    ///
+    /// `alpha` is clamped to `0.0..=1.0`. Higher values follow the input
+    /// more closely.
+    pub fn new(alpha: f32) -> Self {
+        Ewma {
+            alpha: alpha.clamp(0.0, 1.0),
+            state: None,
+        }
+    }
+
+    /// Create a new filter with the given time constant, for values
+    /// sampled at the ALS measurement rate `rate`.
+    ///
+    /// The smoothing factor is `T / (time_constant_ms + T)` where `T` is the
+    /// measurement period, which approximates `1 - exp(-T / time_constant)`.
+    pub fn from_time_constant(time_constant_ms: u32, rate: AlsMeasRate) -> Self {
+        let period_ms = rate.milliseconds() as f32;
+        Self::new(period_ms / (time_constant_ms as f32 + period_ms))
+    }
+
+    /// Smoothing factor
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    /// Latest filtered value, `None` before the first update
+    pub fn value(&self) -> Option<f32> {
+        self.state
+    }
+}
+
+#[cfg(feature = "float")]
+impl Filter for Ewma {
+    fn update(&mut self, value: f32) -> Option<f32> {
+        let next = match self.state {
+            Some(state) => state + self.alpha * (value - state),
+            None => value,
+        };
+        self.state = Some(next);
+        self.state
+    }
+}
+
+/// Median over the last `N` values, e.g. lux or PS counts
+///
+/// Use one instance per channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Median<T, const N: usize> {
+    window: Window<T, N>,
+}
+
+impl<T: Copy + PartialOrd, const N: usize> Median<T, N> {
+    /// Create a new filter
+    pub fn new() -> Self {
+        Median {
+            window: Window::new(),
+        }
+    }
+
+    /// Feed a new value.
+    ///
+    /// Returns the median of the last `N` values, or `None` until `N`
+    /// values were collected. For an even `N` the lower of the two middle
+    /// values is returned. Unordered values such as NaN are treated as
+    /// equal to any other value.
+    pub fn update(&mut self, value: T) -> Option<T> {
+        let mut sorted = self.window.push(value)?;
+        sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+        Some(sorted[(N - 1) / 2])
+    }
+
+    /// Discard the collected values
+    pub fn reset(&mut self) {
+        self.window = Window::new();
+    }
+}
+
+impl<T: Copy + PartialOrd, const N: usize> Default for Median<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "float")]
+impl<const N: usize> Filter for Median<f32, N> {
+    fn update(&mut self, value: f32) -> Option<f32> {
+        Median::update(self, value)
+    }
+}
+
+/// Arithmetic mean over the last `N` values
+#[cfg(feature = "float")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovingAverage<const N: usize> {
+    window: Window<f32, N>,
+}
+
+#[cfg(feature = "float")]
+impl<const N: usize> MovingAverage<N> {
+    /// Create a new filter
+    pub fn new() -> Self {
+        MovingAverage {
+            window: Window::new(),
+        }
+    }
+}
+
+#[cfg(feature = "float")]
+impl<const N: usize> Default for MovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "float")]
+impl<const N: usize> Filter for MovingAverage<N> {
+    fn update(&mut self, value: f32) -> Option<f32> {
+        let values = self.window.push(value)?;
+        Some(values.iter().sum::<f32>() / N as f32)
+    }
+}
+
+/// One-dimensional Kalman filter for a slowly varying lux level
+///
+/// The light level is modeled as a random walk: between two measurements
+/// its variance grows by the process noise times the measurement period.
+#[cfg(feature = "kalman")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Kalman {
+    process_noise: f32,
+    measurement_noise: f32,
+    estimate: Option<KalmanEstimate>,
+}
+
+/// Lux estimate of a [`Kalman`] filter
+#[cfg(feature = "kalman")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KalmanEstimate {
+    /// Estimated lux
+    pub lux: f32,
+    /// Variance of the estimate in lux²
+    pub variance: f32,
+}
+
+#[cfg(feature = "kalman")]
+impl Kalman {
+    /// Create a new filter for values sampled at the ALS measurement rate
+    /// `rate`, as read with `get_als_meas_rate()`.
+    ///
+    /// `process_noise` is the expected variance growth of the light level in
+    /// lux² per second, `measurement_noise` the variance of a single reading
+    /// in lux². Negative values are treated as 0.
+    pub fn new(process_noise: f32, measurement_noise: f32, rate: AlsMeasRate) -> Self {
+        Kalman {
+            process_noise: process_noise.max(0.0) * rate.milliseconds() as f32 / 1000.0,
+            measurement_noise: measurement_noise.max(0.0),
+            estimate: None,
+        }
+    }
+
+    /// Feed a new reading and return the updated estimate.
+    ///
+    /// The first reading is taken over with the measurement variance.
+    pub fn update_estimate(&mut self, lux: f32) -> KalmanEstimate {
+        let estimate = match self.estimate {
+            None => KalmanEstimate {
+                lux,
+                variance: self.measurement_noise,
+            },
+            Some(previous) => {
+                let predicted = previous.variance + self.process_noise;
+                let total = predicted + self.measurement_noise;
+                let gain = if total > 0.0 { predicted / total } else { 1.0 };
+                KalmanEstimate {
+                    lux: previous.lux + gain * (lux - previous.lux),
+                    variance: (1.0 - gain) * predicted,
+                }
+            }
+        };
+        self.estimate = Some(estimate);
+        estimate
+    }
+
+    /// Latest estimate, `None` before the first update
+    pub fn estimate(&self) -> Option<KalmanEstimate> {
+        self.estimate
+    }
+}
+
+#[cfg(feature = "kalman")]
+impl Filter for Kalman {
+    fn update(&mut self, value: f32) -> Option<f32> {
+        Some(self.update_estimate(value).lux)
+    }
+}
+
+/// Ring buffer holding the last `N` values
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Window<T, const N: usize> {
+    values: Option<[T; N]>,
+    next: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> Window<T, N> {
+    fn new() -> Self {
+        Window {
+            values: None,
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Store `value` and return a copy of the window once it is full
+    fn push(&mut self, value: T) -> Option<[T; N]> {
+        if N == 0 {
+            return None;
+        }
+        let values = self.values.get_or_insert([value; N]);
+        values[self.next] = value;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        if self.len == N {
+            Some(*values)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn median_rejects_spike() {
+        let mut filter = Median::<f32, 3>::new();
+        assert_eq!(filter.update(1.0), None);
+        assert_eq!(filter.update(100.0), None);
+        assert_eq!(filter.update(2.0), Some(2.0));
+        assert_eq!(filter.update(3.0), Some(3.0));
+    }
+
+    #[test]
+    fn median_of_ps_counts() {
+        let mut filter = Median::<u16, 5>::new();
+        for value in [10, 11, 500, 12] {
+            assert_eq!(filter.update(value), None);
+        }
+        assert_eq!(filter.update(13), Some(12));
+        assert_eq!(filter.update(14), Some(13));
+        filter.reset();
+        assert_eq!(filter.update(14), None);
+    }
+
+    #[test]
+    fn median_of_even_window_is_lower_middle() {
+        let mut filter = Median::<u16, 4>::new();
+        let medians = [40, 10, 30, 20].map(|value| filter.update(value));
+        assert_eq!(medians, [None, None, None, Some(20)]);
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn moving_average_over_window() {
+        let mut filter = MovingAverage::<2>::new();
+        assert_eq!(filter.update(1.0), None);
+        assert_eq!(filter.update(3.0), Some(2.0));
+        assert_eq!(filter.update(5.0), Some(4.0));
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn ewma_starts_at_first_value() {
+        let mut filter = Ewma::new(0.5);
+        assert_eq!(filter.update(10.0), Some(10.0));
+        assert_eq!(filter.update(20.0), Some(15.0));
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn ewma_from_time_constant() {
+        let mut filter = Ewma::from_time_constant(300, AlsMeasRate::_100ms);
+        assert_eq!(filter.alpha(), 0.25);
+        assert_eq!(filter.value(), None);
+        filter.update(0.0);
+        filter.update(100.0);
+        assert_eq!(filter.value(), Some(25.0));
+    }
+
+    #[cfg(feature = "kalman")]
+    #[test]
+    fn kalman_weights_by_variance() {
+        // 1 lux² per second at 500 ms: 0.5 lux² per step
+        let mut filter = Kalman::new(1.0, 1.5, AlsMeasRate::_500ms);
+        assert_eq!(filter.update_estimate(10.0).variance, 1.5);
+        // predicted variance 2.0, gain 2.0 / 3.5
+        let estimate = filter.update_estimate(17.0);
+        assert!((estimate.lux - 14.0).abs() < 1e-5);
+        assert!((estimate.variance - 1.5 * 2.0 / 3.5).abs() < 1e-6);
+        assert_eq!(filter.estimate(), Some(estimate));
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn chain_feeds_first_output_into_next() {
+        let mut filter = Median::<f32, 3>::new().then(Ewma::new(0.5));
+        assert_eq!(filter.update(1.0), None);
+        assert_eq!(filter.update(50.0), None);
+        assert_eq!(filter.update(3.0), Some(3.0));
+        assert_eq!(filter.update(5.0), Some(4.0));
+    }
+}
@@ -0,0 +1,269 @@
+//! Proximity gesture detection
+//!
+//! A [`GestureDetector`] is fed with PS counts and millisecond timestamps
+//! and recognizes a hand waved over the sensor (the counts rise above the
+//! near threshold and fall back below it within a short time window), two
+//! such waves in quick succession, and a hand hovering over the sensor.
+//! For reliable detection, sample the PS at the fast 10 ms rate (see
+//! [`Ltr559::set_ps_fast_mode()`]).
+//!
+//! ```
+//! use ltr_559::{Gesture, GestureConfig, GestureDetector};
+//!
+//! let mut detector = GestureDetector::new(GestureConfig::default());
+//! assert_eq!(detector.update(50, 0), None);
+//! assert_eq!(detector.update(900, 10), None);
+//! assert_eq!(detector.update(40, 150), Some(Gesture::Wave));
+//! ```
+use crate::hal::blocking::i2c;
+use crate::{marker, Clock, Error, Ltr559, Timestamped};
+
+/// A gesture recognized by [`GestureDetector`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gesture {
+    /// An object came near and went away again within
+    /// [`GestureConfig::wave_max_ms`]
+    Wave,
+    /// A second wave started within [`GestureConfig::double_tap_gap_ms`]
+    /// after the end of a first one. Reported instead of the second wave.
+    DoubleTap,
+    /// An object stayed near for at least [`GestureConfig::hover_min_ms`].
+    /// Reported when it goes away.
+    Hover {
+        /// Time in milliseconds the object stayed near
+        duration: u32,
+    },
+}
+
+/// Gesture detection parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GestureConfig {
+    /// PS counts above which an object is near
+    pub near_threshold: u16,
+    /// The object is away again once the counts fall below
+    /// `near_threshold - hysteresis`
+    pub hysteresis: u16,
+    /// Longest time in milliseconds an object may stay near for a wave
+    pub wave_max_ms: u32,
+    /// Longest time in milliseconds between two waves for a double tap
+    pub double_tap_gap_ms: u32,
+    /// Shortest time in milliseconds an object must stay near for a hover
+    pub hover_min_ms: u32,
+}
+
+impl Default for GestureConfig {
+    /// Near above 400 counts with a hysteresis of 100 counts, waves up to
+    /// 500 ms at most 300 ms apart for a double tap, hovers from 1 s
+    fn default() -> Self {
+        GestureConfig {
+            near_threshold: 400,
+            hysteresis: 100,
+            wave_max_ms: 500,
+            double_tap_gap_ms: 300,
+            hover_min_ms: 1000,
+        }
+    }
+}
+
+/// Recognizes gestures in a series of PS samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureDetector {
+    config: GestureConfig,
+    near_since: Option<u32>,
+    last_wave_end: Option<u32>,
+}
+
+impl GestureDetector {
+    /// Create a new detector
+    pub fn new(config: GestureConfig) -> Self {
+        GestureDetector {
+            config,
+            near_since: None,
+            last_wave_end: None,
+        }
+    }
+
+    /// Detection parameters
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    /// Feed a PS sample taken at `now_ms`.
+    ///
+    /// `now_ms` is a monotonic millisecond timestamp from the application
+    /// (it may wrap around). Returns the gesture completed by this sample,
+    /// if any.
+    pub fn update(&mut self, ps: u16, now_ms: u32) -> Option<Gesture> {
+        match self.near_since {
+            None => {
+                if ps > self.config.near_threshold {
+                    self.near_since = Some(now_ms);
+                }
+                None
+            }
+            Some(since) => {
+                let far = self
+                    .config
+                    .near_threshold
+                    .saturating_sub(self.config.hysteresis);
+                if ps >= far {
+                    return None;
+                }
+                self.near_since = None;
+                let duration = now_ms.wrapping_sub(since);
+                if duration > self.config.wave_max_ms {
+                    self.last_wave_end = None;
+                    return if duration >= self.config.hover_min_ms {
+                        Some(Gesture::Hover { duration })
+                    } else {
+                        None
+                    };
+                }
+                match self.last_wave_end.take() {
+                    Some(end) if since.wrapping_sub(end) <= self.config.double_tap_gap_ms => {
+                        Some(Gesture::DoubleTap)
+                    }
+                    _ => {
+                        self.last_wave_end = Some(now_ms);
+                        Some(Gesture::Wave)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether an object is currently near
+    pub fn is_near(&self) -> bool {
+        self.near_since.is_some()
+    }
+
+    /// Forget any gesture in progress
+    pub fn reset(&mut self) {
+        self.near_since = None;
+        self.last_wave_end = None;
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Read the PS counts and feed them to `detector` with the timestamp
+    /// `now_ms`.
+    ///
+    /// Call this at least once per PS measurement period. Returns the
+    /// gesture completed by this sample, if any.
+    pub fn update_gesture(
+        &mut self,
+        detector: &mut GestureDetector,
+        now_ms: u32,
+    ) -> Result<Option<Gesture>, Error<E>> {
+        let (counts, _) = self.get_ps_data()?;
+        Ok(detector.update(counts, now_ms))
+    }
+
+    /// Read the PS counts and feed them to `detector`, with the time of
+    /// `clock` as timestamp (see [`update_gesture()`](#method.update_gesture)).
+    ///
+    /// Returns the completed gesture with the time it was recognized at.
+    pub fn update_gesture_timestamped<C: Clock>(
+        &mut self,
+        detector: &mut GestureDetector,
+        clock: &mut C,
+    ) -> Result<Option<Timestamped<Gesture>>, Error<E>> {
+        let (counts, _) = self.get_ps_data()?;
+        let timestamp_ms = clock.now_ms();
+        Ok(detector
+            .update(counts, timestamp_ms)
+            .map(|value| Timestamped {
+                timestamp_ms,
+                value,
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    #[test]
+    fn slow_presence_is_not_a_wave() {
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        assert_eq!(detector.update(500, 0), None);
+        assert!(detector.is_near());
+        // within the hysteresis the object is still near
+        assert_eq!(detector.update(350, 100), None);
+        assert!(detector.is_near());
+        assert_eq!(detector.update(100, 800), None);
+        assert!(!detector.is_near());
+    }
+
+    #[test]
+    fn wave_across_timestamp_wrap_around() {
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        detector.update(1000, u32::MAX - 50);
+        assert_eq!(detector.update(0, 100), Some(Gesture::Wave));
+    }
+
+    #[test]
+    fn second_quick_wave_is_a_double_tap() {
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        detector.update(1000, 0);
+        assert_eq!(detector.update(0, 100), Some(Gesture::Wave));
+        detector.update(1000, 350);
+        assert_eq!(detector.update(0, 450), Some(Gesture::DoubleTap));
+        // a third wave starts a new sequence
+        detector.update(1000, 500);
+        assert_eq!(detector.update(0, 600), Some(Gesture::Wave));
+        detector.update(1000, 1000);
+        assert_eq!(detector.update(0, 1100), Some(Gesture::Wave));
+    }
+
+    #[test]
+    fn hover_reports_duration() {
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        detector.update(1000, 0);
+        assert_eq!(detector.update(900, 1500), None);
+        assert_eq!(
+            detector.update(0, 2500),
+            Some(Gesture::Hover { duration: 2500 })
+        );
+        // a hover in between breaks a double tap
+        detector.update(1000, 2600);
+        assert_eq!(detector.update(0, 2700), Some(Gesture::Wave));
+    }
+
+    #[test]
+    fn wave_from_device_samples() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x20, 0x03]);
+        assert_eq!(sensor.update_gesture(&mut detector, 0).unwrap(), None);
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x10, 0x00]);
+        let gesture = sensor.update_gesture(&mut detector, 200).unwrap();
+        assert_eq!(gesture, Some(Gesture::Wave));
+    }
+
+    #[test]
+    fn timestamped_wave_from_device_samples() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        let mut now = 0;
+        let mut clock = || {
+            now += 200;
+            now
+        };
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x20, 0x03]);
+        let gesture = sensor.update_gesture_timestamped(&mut detector, &mut clock);
+        assert_eq!(gesture.unwrap(), None);
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0x10, 0x00]);
+        let gesture = sensor.update_gesture_timestamped(&mut detector, &mut clock);
+        let gesture = gesture.unwrap().unwrap();
+        assert_eq!((gesture.timestamp_ms, gesture.value), (400, Gesture::Wave));
+    }
+}
@@ -0,0 +1,230 @@
+//! Traits used by the driver to access the bus, wait and read pins
+//!
+//! They mirror the embedded-hal 0.2 traits used by the driver and are
+//! implemented:
+//! - with the `eh0` feature, for every embedded-hal 0.2 I²C bus,
+//!   `DelayMs<u32>` provider and `InputPin`, so those can be passed to the
+//!   driver directly.
+//! - with the `eh1` feature, for every embedded-hal 1.0 `I2c` bus, `DelayNs`
+//!   provider and `InputPin` wrapped in `Eh1`.
+//!
+//! Both features can be enabled at the same time. A type implementing both
+//! the embedded-hal 0.2 and 1.0 traits is used through the 0.2 traits unless
+//! it is wrapped in `Eh1`.
+
+/// Blocking traits
+pub mod blocking {
+    /// I²C traits
+    pub mod i2c {
+        /// Write bytes to a device
+        pub trait Write {
+            /// Error type
+            type Error;
+
+            /// Write `bytes` to the device at `address`
+            fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error>;
+        }
+
+        /// Read bytes from a device
+        pub trait Read {
+            /// Error type
+            type Error;
+
+            /// Fill `buffer` with bytes read from the device at `address`
+            fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error>;
+        }
+
+        /// Write bytes then read bytes without a stop condition in between
+        pub trait WriteRead {
+            /// Error type
+            type Error;
+
+            /// Write `bytes` then fill `buffer` with a repeated start
+            fn write_read(
+                &mut self,
+                address: u8,
+                bytes: &[u8],
+                buffer: &mut [u8],
+            ) -> Result<(), Self::Error>;
+        }
+
+        #[cfg(feature = "eh0")]
+        impl<T: embedded_hal::blocking::i2c::Write> Write for T {
+            type Error = T::Error;
+
+            fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+                embedded_hal::blocking::i2c::Write::write(self, address, bytes)
+            }
+        }
+
+        #[cfg(feature = "eh0")]
+        impl<T: embedded_hal::blocking::i2c::Read> Read for T {
+            type Error = T::Error;
+
+            fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+                embedded_hal::blocking::i2c::Read::read(self, address, buffer)
+            }
+        }
+
+        #[cfg(feature = "eh0")]
+        impl<T: embedded_hal::blocking::i2c::WriteRead> WriteRead for T {
+            type Error = T::Error;
+
+            fn write_read(
+                &mut self,
+                address: u8,
+                bytes: &[u8],
+                buffer: &mut [u8],
+            ) -> Result<(), Self::Error> {
+                embedded_hal::blocking::i2c::WriteRead::write_read(self, address, bytes, buffer)
+            }
+        }
+
+        #[cfg(feature = "eh1")]
+        mod eh1 {
+            use crate::hal::Eh1;
+            use embedded_hal_1::i2c::{I2c, Operation};
+
+            impl<T: I2c> super::Write for Eh1<T> {
+                type Error = T::Error;
+
+                fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+                    self.0.write(address, bytes)
+                }
+            }
+
+            impl<T: I2c> super::Read for Eh1<T> {
+                type Error = T::Error;
+
+                fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+                    self.0.read(address, buffer)
+                }
+            }
+
+            impl<T: I2c> super::WriteRead for Eh1<T> {
+                type Error = T::Error;
+
+                /// Performed as one explicit transaction so that no stop
+                /// condition is inserted between the write and the read.
+                fn write_read(
+                    &mut self,
+                    address: u8,
+                    bytes: &[u8],
+                    buffer: &mut [u8],
+                ) -> Result<(), Self::Error> {
+                    self.0.transaction(
+                        address,
+                        &mut [Operation::Write(bytes), Operation::Read(buffer)],
+                    )
+                }
+            }
+        }
+    }
+
+    /// Delay traits
+    pub mod delay {
+        /// Millisecond delay
+        pub trait DelayMs<UXX> {
+            /// Pause execution for `ms` milliseconds
+            fn delay_ms(&mut self, ms: UXX);
+        }
+
+        #[cfg(feature = "eh0")]
+        impl<T: embedded_hal::blocking::delay::DelayMs<u32>> DelayMs<u32> for T {
+            fn delay_ms(&mut self, ms: u32) {
+                embedded_hal::blocking::delay::DelayMs::delay_ms(self, ms)
+            }
+        }
+
+        #[cfg(feature = "eh1")]
+        impl<T: embedded_hal_1::delay::DelayNs> DelayMs<u32> for crate::hal::Eh1<T> {
+            fn delay_ms(&mut self, ms: u32) {
+                embedded_hal_1::delay::DelayNs::delay_ms(&mut self.0, ms)
+            }
+        }
+    }
+}
+
+/// Digital I/O traits
+pub mod digital {
+    /// Fallible digital I/O traits
+    pub mod v2 {
+        /// Single digital input pin
+        pub trait InputPin {
+            /// Error type
+            type Error;
+
+            /// Is the input pin high?
+            fn is_high(&mut self) -> Result<bool, Self::Error>;
+
+            /// Is the input pin low?
+            fn is_low(&mut self) -> Result<bool, Self::Error>;
+        }
+
+        #[cfg(feature = "eh0")]
+        impl<T: embedded_hal::digital::v2::InputPin> InputPin for T {
+            type Error = T::Error;
+
+            fn is_high(&mut self) -> Result<bool, Self::Error> {
+                embedded_hal::digital::v2::InputPin::is_high(self)
+            }
+
+            fn is_low(&mut self) -> Result<bool, Self::Error> {
+                embedded_hal::digital::v2::InputPin::is_low(self)
+            }
+        }
+
+        #[cfg(feature = "eh1")]
+        impl<T: embedded_hal_1::digital::InputPin> InputPin for crate::hal::Eh1<T> {
+            type Error = T::Error;
+
+            fn is_high(&mut self) -> Result<bool, Self::Error> {
+                embedded_hal_1::digital::InputPin::is_high(&mut self.0)
+            }
+
+            fn is_low(&mut self) -> Result<bool, Self::Error> {
+                embedded_hal_1::digital::InputPin::is_low(&mut self.0)
+            }
+        }
+    }
+}
+
+/// Adapter passing an embedded-hal 1.0 I²C bus, delay provider or input pin
+/// to the driver
+///
+/// ```no_run
+/// use embedded_hal_1::{delay::DelayNs, i2c::I2c};
+/// use ltr_559::{Eh1, Ltr559, SlaveAddr};
+///
+/// fn reset_and_identify<I2C: I2c, D: DelayNs>(i2c: I2C, delay: &mut D) -> u8 {
+///     let mut sensor = Ltr559::new_device(Eh1(i2c), SlaveAddr::default());
+///     sensor.sw_reset(&mut Eh1(delay)).unwrap();
+///     sensor.get_part_id().unwrap()
+/// }
+/// ```
+#[cfg(feature = "eh1")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Eh1<T>(pub T);
+
+#[cfg(all(test, feature = "eh0"))]
+mod tests {
+    use crate::{Ltr559, SlaveAddr};
+    use embedded_hal::blocking::i2c;
+
+    /// embedded-hal 0.2 bus always returning the same byte
+    struct Eh0Bus(u8);
+
+    impl i2c::WriteRead for Eh0Bus {
+        type Error = ();
+        fn write_read(&mut self, _: u8, _: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            buffer.fill(self.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn accepts_eh0_bus() {
+        let mut sensor = Ltr559::new_device(Eh0Bus(0x05), SlaveAddr::default());
+        assert_eq!(sensor.get_manufacturer_id().unwrap(), 0x05);
+    }
+}
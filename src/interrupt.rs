@@ -0,0 +1,105 @@
+//! Interrupt acknowledgement split between an ISR and the main loop
+//!
+//! The interrupt service routine of the INT pin must not access the I²C
+//! bus. It only records through an [`InterruptHandle`] that the pin fired;
+//! the main loop then reads and clears the interrupt with
+//! [`Ltr559::service_interrupt()`].
+//!
+//! ```
+//! use ltr_559::{InterruptHandle, InterruptState};
+//!
+//! static INTERRUPT: InterruptState = InterruptState::new();
+//!
+//! // in the ISR, e.g. moved into an RTIC task or a closure
+//! let handle: InterruptHandle<'static> = INTERRUPT.handle();
+//! handle.signal();
+//!
+//! // in the main loop: `sensor.service_interrupt(&INTERRUPT)`
+//! assert!(INTERRUPT.is_pending());
+//! ```
+use crate::hal::blocking::i2c;
+use crate::{Error, InterruptCause, Ltr559};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Shared pending-interrupt flag, usable in a `static`
+#[derive(Debug, Default)]
+pub struct InterruptState {
+    pending: AtomicBool,
+}
+
+impl InterruptState {
+    /// Create a new state with no interrupt pending
+    pub const fn new() -> Self {
+        InterruptState {
+            pending: AtomicBool::new(false),
+        }
+    }
+
+    /// Handle to pass to the interrupt service routine
+    pub fn handle(&self) -> InterruptHandle<'_> {
+        InterruptHandle { state: self }
+    }
+
+    /// Whether the INT pin fired since the last service
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+}
+
+/// Lightweight handle recording that the INT pin fired
+///
+/// It does not access the bus, so it can be moved into an interrupt
+/// service routine.
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptHandle<'a> {
+    state: &'a InterruptState,
+}
+
+impl InterruptHandle<'_> {
+    /// Record that the INT pin fired
+    pub fn signal(&self) {
+        self.state.pending.store(true, Ordering::Release);
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+{
+    /// If the INT pin fired according to `state`, read and clear the
+    /// interrupt and return its cause.
+    ///
+    /// The pending flag is cleared before the status is read, so an
+    /// interrupt signaled meanwhile is serviced on the next call rather
+    /// than lost. Returns `None` if no interrupt is pending.
+    pub fn service_interrupt(
+        &mut self,
+        state: &InterruptState,
+    ) -> Result<Option<InterruptCause>, Error<E>> {
+        if !state.is_pending() {
+            return Ok(None);
+        }
+        state.pending.store(false, Ordering::Release);
+        self.read_interrupt_cause().map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    #[test]
+    fn interrupt_is_serviced_once() {
+        let state = InterruptState::new();
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        sensor.i2c.registers[0x8C] = 0x02;
+        assert_eq!(sensor.service_interrupt(&state).unwrap(), None);
+        state.handle().signal();
+        let cause = sensor.service_interrupt(&state).unwrap().unwrap();
+        assert!(cause.ps && !cause.als);
+        assert!(!state.is_pending());
+        assert_eq!(sensor.service_interrupt(&state).unwrap(), None);
+    }
+}
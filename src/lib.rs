@@ -23,6 +23,92 @@
 //! - Set PS Offset. See: [`set_ps_offset()`].
 //! - Set PS N Pulses. See: [`set_ps_n_pulses()`].
 //! - Set Interrupt Mode and Polarity. See: [`set_interrupt()`].
+//! - Configure wake on darkness or light. See: [`wake_on_darkness()`], [`wake_on_light()`].
+//! - Run an acquisition loop with backpressure control. See: [`run()`].
+//! - Run an acquisition loop through a filter pipeline. See: [`run_filtered()`].
+//! - Change the ALS gain safely. See: [`change_gain_safely()`].
+//! - Set PS fast mode (10 ms) with a pulse count checked against an average LED current limit. See: [`set_ps_fast_mode()`], [`set_ps_fast_mode_with_limit()`].
+//! - Access the I²C bus directly without destroying the driver. See: [`with_bus()`].
+//! - Perform a one-shot measurement and return to standby. See: [`measure_blocking()`].
+//! - Tune the PS thresholds with a guided routine. See: [`tune_ps_thresholds()`].
+//! - Estimate when the next sample is due. See: [`time_until_next_sample()`].
+//! - Report batches of measurements to a telemetry sink. See: [`report_to()`].
+//! - Create a driver instance with a verified configuration. See: [`with_config()`].
+//! - Start from predefined configuration profiles. See: `Config::low_power()`,
+//!   `Config::high_accuracy()`, `Config::pimoroni_defaults()`.
+//! - Use the driver asynchronously (`async` feature). See: `Ltr559Async`.
+//! - Stream fresh lux measurements (`async` feature). See: `Ltr559Async::lux_stream()`.
+//! - Stream measurements taken on each sensor interrupt (`async` feature). See: `Ltr559Async::run_interrupt_driven()`.
+//! - Wait for new data with a timeout and read it. See: [`read_lux_blocking()`], [`read_ps_blocking()`].
+//! - Read the raw ALS and PS data at once. See: [`get_raw_measurement()`].
+//! - Write or read back the whole device configuration. See: [`apply_config()`], [`read_config()`].
+//! - Build a configured driver instance. See: [`builder()`].
+//! - Reset, identify and configure the device in one step. See: [`new_with_config()`].
+//! - Read back the control and measurement rate settings. See: [`get_als_contr()`], [`get_ps_contr()`], [`get_als_meas_rate()`], [`get_ps_meas_rate()`].
+//! - Read back the PS LED, pulse count and offset settings. See: [`get_ps_led()`], [`get_ps_n_pulses()`], [`get_ps_offset()`].
+//! - Read back the interrupt and persist settings. See: [`get_interrupt()`], [`get_interrupt_persist()`].
+//! - Read all interrupt thresholds at once. See: [`get_thresholds()`].
+//! - Set both ALS thresholds with ordering validation. See: [`set_als_thresholds()`].
+//! - Set both PS thresholds with range validation. See: [`set_ps_thresholds()`].
+//! - Set a threshold window around a center value. See: [`set_als_window()`], [`set_ps_window()`].
+//! - Re-center the ALS thresholds on the last reading. See: [`rearm_als_window()`].
+//! - Stage configuration changes and write them in one pass. See: [`begin_staging()`], [`commit()`], [`discard_staged()`], [`is_staging()`].
+//! - Save and restore the raw configuration registers. See: [`save_state()`], [`restore_state()`].
+//! - Detect configuration drift. See: [`verify_config()`].
+//! - Re-apply the configuration after a detected reset. See: [`ensure_configured()`], [`applied_config()`].
+//! - Build a validated configuration at compile time. See: `ltr559_config!`.
+//! - Set the PS LED drive checked against an average current limit. See: [`set_led_config()`], [`set_led_config_unchecked()`].
+//! - Put the device in standby and wake it up again. See: [`standby()`], [`wake()`], [`is_active()`].
+//! - Reset the device through software and wait for it. See: [`sw_reset()`].
+//! - Put the device in standby before releasing the bus. See: [`destroy_and_shutdown()`].
+//! - Read the ALS, status and PS data registers in one burst. See: [`read_all()`].
+//! - Read a coherent ALS and PS snapshot with lux. See: [`get_measurement()`].
+//! - Read the ALS channels only if new valid data is available. See: [`get_als_data_checked()`].
+//! - Read the ALS channels as a typed struct. See: [`get_als_data()`].
+//! - Read the PS data as a typed struct with a freshness flag. See: [`get_ps()`].
+//! - Compute lux in millilux with integer arithmetic only. See: [`get_lux_millis()`].
+//! - Compute lux as a fixed-point number (`fixed` feature). See: [`get_lux_fixed()`].
+//! - Compute lux from logged raw data, e.g. on the host. See: `calc::compute_lux()`.
+//! - Set the coefficients of the lux formula. See: [`set_lux_coefficients()`], [`lux_coefficients()`].
+//! - Select the lux calculation mode, e.g. to match the Pimoroni Python library. See: [`set_lux_algorithm()`], [`lux_algorithm()`].
+//! - Compensate the attenuation of cover glass or a diffuser, and a lux offset. See: [`set_window_factor()`], [`window_factor()`], [`set_lux_offset()`], [`lux_offset()`].
+//! - Read the IR ratio and estimate the light source type. See: [`get_ir_ratio()`], [`get_light_source()`], [`get_als_data_coherent()`].
+//! - Read the PS saturation indicator. See: [`is_ps_saturated()`].
+//! - Wait for new PS data with a timeout. See: [`wait_for_ps_data()`].
+//! - Read lux without blocking. See: [`try_get_lux()`].
+//! - Emulate a single-shot ALS and PS measurement. See: [`measure_once()`].
+//! - Average several lux readings. See: [`get_lux_averaged()`].
+//! - Oversample raw ALS data for extra resolution. See: `Oversampler`.
+//! - React only to meaningful lux changes, with hysteresis. See: `ChangeDetector`.
+//! - Detect dawn and dusk with hysteresis and a minimum dwell time. See: `DayNight`.
+//! - Classify lux into named zones with hysteresis at each boundary. See: `ZoneClassifier`.
+//! - Lock out repeated PS "near" triggers for a time window. See: `PsDebouncer`.
+//! - Track the minimum, maximum and mean lux (`stats` feature). See: [`lux_stats()`], [`reset_lux_stats()`].
+//! - Keep recent measurements with timestamps in a ring buffer (`heapless` feature). See: [`log_measurement()`].
+//! - Reject single-sample spikes in lux or PS counts with a median. See: `Median`.
+//! - Read PS counts as a trimmed mean of several samples. See: [`get_ps_data_robust()`].
+//! - Decode typed threshold and saturation events (`heapless` feature). See: [`poll_events()`].
+//! - Read the interrupt cause, or the last status without clearing flags. See: [`read_interrupt_cause()`], [`peek_status()`].
+//! - Wait for the interrupt pin with a timeout and read the cause. See: [`wait_for_interrupt()`].
+//! - Detect hand waves, double taps and hovering over the sensor with a `GestureDetector`. See: [`update_gesture()`].
+//! - Dispatch the decoded events to a handler closure or `EventHandler` (`heapless` feature). See: [`tick()`].
+//! - Adapt the ALS thresholds to the recent variability of the light with `AdaptiveThresholds`. See: [`rearm_als_adaptive()`].
+//! - Record the INT pin in an ISR through an `InterruptHandle` and service it later from the main loop. See: [`service_interrupt()`].
+//! - Clear the latched interrupt, return the triggering values and optionally track them with new thresholds. See: [`acknowledge_and_rearm()`].
+//! - Check the INT pin wiring and polarity by forcing an ALS interrupt. See: [`interrupt_self_test()`].
+//! - Stamp measurements, events and gestures with the time of a `Clock`. See: [`get_measurement_timestamped()`], [`poll_events_timestamped()`], [`update_gesture_timestamped()`].
+//! - Count interrupts, saturations and bus errors for field diagnostics. See: [`diagnostics()`], [`reset_diagnostics()`].
+//! - Interrupt while a value is inside or outside of a band, see `WindowMode`. See: [`set_als_window_mode()`], [`set_ps_window_mode()`].
+//! - Track Far/Mid/Near/Touch proximity zones by reprogramming the PS thresholds, see `ProximityZones`. See: [`update_proximity_zone()`].
+//! - Wait for the INT line on Linux through `gpio-cdev` and poll the events (`linux-gpio` feature). See: [`wait_events()`].
+//! - Measure the PS crosstalk without target and compensate it with the PS offset. See: [`calibrate_ps_offset()`].
+//! - Track the PS level without target as it drifts and compensate it. See: [`get_ps_data_compensated()`].
+//! - Estimate the target distance with a piecewise or power law model fitted from calibration points. See: [`get_distance_mm()`].
+//! - Apply stored calibration data: PS offset, lux scale and offset. See: [`apply_calibration()`].
+//! - Measure and subtract the ALS dark counts before lux is computed. See: [`measure_dark_counts()`], [`set_dark_counts()`], [`dark_counts()`].
+//! - Step the ALS gain automatically to keep the counts in range. See: [`get_measurement_auto_gain()`].
+//! - Adjust the ALS integration time automatically within a latency limit. See: [`get_measurement_auto_int_time()`], [`change_int_time_safely()`].
+//! - Manage the ALS gain and integration time together, and get the full-scale lux of the current range. See: [`get_measurement_ranged()`], [`change_range_safely()`], [`full_scale_lux()`].
 //!
 //! [`get_lux()`]: struct.Ltr559.html#method.get_lux
 //! [`get_als_raw_data()`]: struct.Ltr559.html#method.get_als_raw_data
@@ -43,6 +129,116 @@
 //! [`set_ps_offset()`]: struct.Ltr559.html#method.set_ps_offset
 //! [`set_ps_n_pulses()`]: struct.Ltr559.html#method.set_ps_n_pulses
 //! [`set_interrupt()`]: struct.Ltr559.html#method.set_interrupt
+//! [`wake_on_darkness()`]: struct.Ltr559.html#method.wake_on_darkness
+//! [`wake_on_light()`]: struct.Ltr559.html#method.wake_on_light
+//! [`run()`]: struct.Ltr559.html#method.run
+//! [`run_filtered()`]: struct.Ltr559.html#method.run_filtered
+//! [`change_gain_safely()`]: struct.Ltr559.html#method.change_gain_safely
+//! [`set_ps_fast_mode()`]: struct.Ltr559.html#method.set_ps_fast_mode
+//! [`set_ps_fast_mode_with_limit()`]: struct.Ltr559.html#method.set_ps_fast_mode_with_limit
+//! [`with_bus()`]: struct.Ltr559.html#method.with_bus
+//! [`measure_blocking()`]: struct.Ltr559.html#method.measure_blocking
+//! [`tune_ps_thresholds()`]: struct.Ltr559.html#method.tune_ps_thresholds
+//! [`time_until_next_sample()`]: struct.Ltr559.html#method.time_until_next_sample
+//! [`report_to()`]: struct.Ltr559.html#method.report_to
+//! [`with_config()`]: struct.Ltr559.html#method.with_config
+//! [`read_lux_blocking()`]: struct.Ltr559.html#method.read_lux_blocking
+//! [`read_ps_blocking()`]: struct.Ltr559.html#method.read_ps_blocking
+//! [`get_raw_measurement()`]: struct.Ltr559.html#method.get_raw_measurement
+//! [`apply_config()`]: struct.Ltr559.html#method.apply_config
+//! [`read_config()`]: struct.Ltr559.html#method.read_config
+//! [`builder()`]: struct.Ltr559.html#method.builder
+//! [`new_with_config()`]: struct.Ltr559.html#method.new_with_config
+//! [`get_als_contr()`]: struct.Ltr559.html#method.get_als_contr
+//! [`get_ps_contr()`]: struct.Ltr559.html#method.get_ps_contr
+//! [`get_als_meas_rate()`]: struct.Ltr559.html#method.get_als_meas_rate
+//! [`get_ps_meas_rate()`]: struct.Ltr559.html#method.get_ps_meas_rate
+//! [`get_ps_led()`]: struct.Ltr559.html#method.get_ps_led
+//! [`get_ps_n_pulses()`]: struct.Ltr559.html#method.get_ps_n_pulses
+//! [`get_ps_offset()`]: struct.Ltr559.html#method.get_ps_offset
+//! [`get_interrupt()`]: struct.Ltr559.html#method.get_interrupt
+//! [`get_interrupt_persist()`]: struct.Ltr559.html#method.get_interrupt_persist
+//! [`get_thresholds()`]: struct.Ltr559.html#method.get_thresholds
+//! [`set_als_thresholds()`]: struct.Ltr559.html#method.set_als_thresholds
+//! [`set_ps_thresholds()`]: struct.Ltr559.html#method.set_ps_thresholds
+//! [`set_als_window()`]: struct.Ltr559.html#method.set_als_window
+//! [`set_ps_window()`]: struct.Ltr559.html#method.set_ps_window
+//! [`rearm_als_window()`]: struct.Ltr559.html#method.rearm_als_window
+//! [`begin_staging()`]: struct.Ltr559.html#method.begin_staging
+//! [`commit()`]: struct.Ltr559.html#method.commit
+//! [`discard_staged()`]: struct.Ltr559.html#method.discard_staged
+//! [`is_staging()`]: struct.Ltr559.html#method.is_staging
+//! [`save_state()`]: struct.Ltr559.html#method.save_state
+//! [`restore_state()`]: struct.Ltr559.html#method.restore_state
+//! [`verify_config()`]: struct.Ltr559.html#method.verify_config
+//! [`ensure_configured()`]: struct.Ltr559.html#method.ensure_configured
+//! [`applied_config()`]: struct.Ltr559.html#method.applied_config
+//! [`set_led_config()`]: struct.Ltr559.html#method.set_led_config
+//! [`set_led_config_unchecked()`]: struct.Ltr559.html#method.set_led_config_unchecked
+//! [`standby()`]: struct.Ltr559.html#method.standby
+//! [`wake()`]: struct.Ltr559.html#method.wake
+//! [`is_active()`]: struct.Ltr559.html#method.is_active
+//! [`sw_reset()`]: struct.Ltr559.html#method.sw_reset
+//! [`destroy_and_shutdown()`]: struct.Ltr559.html#method.destroy_and_shutdown
+//! [`read_all()`]: struct.Ltr559.html#method.read_all
+//! [`get_measurement()`]: struct.Ltr559.html#method.get_measurement
+//! [`get_als_data_checked()`]: struct.Ltr559.html#method.get_als_data_checked
+//! [`get_als_data()`]: struct.Ltr559.html#method.get_als_data
+//! [`get_ps()`]: struct.Ltr559.html#method.get_ps
+//! [`get_lux_millis()`]: struct.Ltr559.html#method.get_lux_millis
+//! [`get_lux_fixed()`]: struct.Ltr559.html#method.get_lux_fixed
+//! [`set_lux_coefficients()`]: struct.Ltr559.html#method.set_lux_coefficients
+//! [`lux_coefficients()`]: struct.Ltr559.html#method.lux_coefficients
+//! [`set_lux_algorithm()`]: struct.Ltr559.html#method.set_lux_algorithm
+//! [`lux_algorithm()`]: struct.Ltr559.html#method.lux_algorithm
+//! [`set_window_factor()`]: struct.Ltr559.html#method.set_window_factor
+//! [`window_factor()`]: struct.Ltr559.html#method.window_factor
+//! [`set_lux_offset()`]: struct.Ltr559.html#method.set_lux_offset
+//! [`lux_offset()`]: struct.Ltr559.html#method.lux_offset
+//! [`get_ir_ratio()`]: struct.Ltr559.html#method.get_ir_ratio
+//! [`get_light_source()`]: struct.Ltr559.html#method.get_light_source
+//! [`get_als_data_coherent()`]: struct.Ltr559.html#method.get_als_data_coherent
+//! [`is_ps_saturated()`]: struct.Ltr559.html#method.is_ps_saturated
+//! [`wait_for_ps_data()`]: struct.Ltr559.html#method.wait_for_ps_data
+//! [`try_get_lux()`]: struct.Ltr559.html#method.try_get_lux
+//! [`measure_once()`]: struct.Ltr559.html#method.measure_once
+//! [`get_lux_averaged()`]: struct.Ltr559.html#method.get_lux_averaged
+//! [`lux_stats()`]: struct.Ltr559.html#method.lux_stats
+//! [`reset_lux_stats()`]: struct.Ltr559.html#method.reset_lux_stats
+//! [`log_measurement()`]: struct.Ltr559.html#method.log_measurement
+//! [`get_ps_data_robust()`]: struct.Ltr559.html#method.get_ps_data_robust
+//! [`poll_events()`]: struct.Ltr559.html#method.poll_events
+//! [`read_interrupt_cause()`]: struct.Ltr559.html#method.read_interrupt_cause
+//! [`peek_status()`]: struct.Ltr559.html#method.peek_status
+//! [`wait_for_interrupt()`]: struct.Ltr559.html#method.wait_for_interrupt
+//! [`update_gesture()`]: struct.Ltr559.html#method.update_gesture
+//! [`tick()`]: struct.Ltr559.html#method.tick
+//! [`rearm_als_adaptive()`]: struct.Ltr559.html#method.rearm_als_adaptive
+//! [`service_interrupt()`]: struct.Ltr559.html#method.service_interrupt
+//! [`acknowledge_and_rearm()`]: struct.Ltr559.html#method.acknowledge_and_rearm
+//! [`interrupt_self_test()`]: struct.Ltr559.html#method.interrupt_self_test
+//! [`get_measurement_timestamped()`]: struct.Ltr559.html#method.get_measurement_timestamped
+//! [`poll_events_timestamped()`]: struct.Ltr559.html#method.poll_events_timestamped
+//! [`update_gesture_timestamped()`]: struct.Ltr559.html#method.update_gesture_timestamped
+//! [`diagnostics()`]: struct.Ltr559.html#method.diagnostics
+//! [`reset_diagnostics()`]: struct.Ltr559.html#method.reset_diagnostics
+//! [`set_als_window_mode()`]: struct.Ltr559.html#method.set_als_window_mode
+//! [`set_ps_window_mode()`]: struct.Ltr559.html#method.set_ps_window_mode
+//! [`update_proximity_zone()`]: struct.Ltr559.html#method.update_proximity_zone
+//! [`wait_events()`]: struct.Ltr559.html#method.wait_events
+//! [`calibrate_ps_offset()`]: struct.Ltr559.html#method.calibrate_ps_offset
+//! [`get_ps_data_compensated()`]: struct.Ltr559.html#method.get_ps_data_compensated
+//! [`get_distance_mm()`]: struct.Ltr559.html#method.get_distance_mm
+//! [`apply_calibration()`]: struct.Ltr559.html#method.apply_calibration
+//! [`measure_dark_counts()`]: struct.Ltr559.html#method.measure_dark_counts
+//! [`set_dark_counts()`]: struct.Ltr559.html#method.set_dark_counts
+//! [`dark_counts()`]: struct.Ltr559.html#method.dark_counts
+//! [`get_measurement_auto_gain()`]: struct.Ltr559.html#method.get_measurement_auto_gain
+//! [`get_measurement_auto_int_time()`]: struct.Ltr559.html#method.get_measurement_auto_int_time
+//! [`change_int_time_safely()`]: struct.Ltr559.html#method.change_int_time_safely
+//! [`get_measurement_ranged()`]: struct.Ltr559.html#method.get_measurement_ranged
+//! [`change_range_safely()`]: struct.Ltr559.html#method.change_range_safely
+//! [`full_scale_lux()`]: struct.Ltr559.html#method.full_scale_lux
 //!
 //!
 //! ## The devices
@@ -53,6 +249,42 @@
 //! Datasheets:
 //! - [LTR-559](https://optoelectronics.liteon.com/upload/download/DS86-2013-0003/LTR-559ALS-01_DS_V1.pdf)
 //!
+//! ## Cargo features
+//!
+//! - `eh0` (default): accept [`embedded-hal`] 0.2 buses, delays and pins.
+//! - `eh1`: accept [`embedded-hal`] 1.0 buses, delays and pins wrapped in
+//!   `Eh1`. It can be enabled together with `eh0`. See the [`hal`] module.
+//! - `async`: provide `Ltr559Async`, based on `embedded-hal-async`.
+//! - `embassy-time`: let `Ltr559Async` wait with an `embassy-time` timer
+//!   after a software reset, when waking the ALS up and while polling for new
+//!   data, without a delay provider. See `Ltr559Async::sw_reset_timed()`,
+//!   `wake_als()`, `wait_for_als_data_timed()`, `wait_for_ps_data_timed()` and
+//!   `lux_stream_timed()`. Implies `async`.
+//! - `defmt`: implement `defmt::Format` for the public types.
+//! - `ufmt`: implement `ufmt::uDebug` and `ufmt::uDisplay` for `Status` and
+//!   `RawMeasurement`.
+//! - `log`: emit a trace-level `log` record for every register read and write
+//!   (device address, register and value).
+//! - `fixed`: provide `get_lux_fixed()`, returning lux as a `fixed::types::I16F16`.
+//! - `float` (default): provide every API using `f32` (`get_lux()` and the
+//!   methods built on it, the `filter`, `change`, `daynight`, `zones`,
+//!   `distance`, `autorange`, `telemetry` and acquisition loop APIs). Without
+//!   it only the raw and integer APIs such as `get_lux_millis()` remain.
+//! - `stats`: track the minimum, maximum and mean of the lux values computed
+//!   by the driver. See `RunningStats`. Enables `float`.
+//! - `heapless`: provide the `events` module and the `logger` module keeping
+//!   the last measurements in a fixed-size log. Enables `float`.
+//! - `kalman`: provide a one-dimensional Kalman filter for lux in the
+//!   `filter` module. Enables `float`.
+//! - `serde`: derive `Serialize` and `Deserialize` for `CalibrationData` and
+//!   `DistanceModel`, to store the calibration e.g. with `postcard`.
+//! - `linux-gpio`: provide the `linux` module, waiting for the INT line through
+//!   the Linux GPIO character device (`gpio-cdev`), e.g. on a Raspberry Pi.
+//!   Requires `std` and enables `heapless`.
+//!
+//! With `eh1`, the bus can be shared with other devices through the
+//! `embedded-hal-bus` shared devices. See the [`bus`] module.
+//!
 //! ## Usage examples (see also examples folder)
 //!
 //! To use this driver, import this crate and an `embedded_hal` implementation,
@@ -84,6 +316,7 @@
 //! extern crate ltr_559;
 //! use ltr_559::{Ltr559, SlaveAddr, AlsGain, AlsIntTime, AlsMeasRate};
 //!
+//! # #[cfg(all(feature = "eh0", feature = "float"))]
 //! # fn main() {
 //! let dev = hal::I2cdev::new("/dev/i2c-1").unwrap();
 //! let address = SlaveAddr::default();
@@ -104,6 +337,8 @@
 //!         }
 //!     }
 //! # }
+//! # #[cfg(not(all(feature = "eh0", feature = "float")))]
+//! # fn main() {}
 //! ```
 //!
 //!
@@ -114,6 +349,7 @@
 //! extern crate ltr_559;
 //! use ltr_559::{AlsIntTime, AlsMeasRate, AlsGain, Ltr559, SlaveAddr};
 //!
+//! # #[cfg(feature = "eh0")]
 //! # fn main() {
 //! let dev = hal::I2cdev::new("/dev/i2c-1").unwrap();
 //! let address = SlaveAddr::default();
@@ -121,6 +357,8 @@
 //! sensor.set_als_meas_rate(AlsIntTime::_400ms, AlsMeasRate::_1000ms).unwrap();
 //! sensor.set_als_contr(AlsGain::Gain48x, false, true).unwrap();
 //! # }
+//! # #[cfg(not(feature = "eh0"))]
+//! # fn main() {}
 //! ```
 //!
 //! ### Configure interrupts
@@ -134,6 +372,7 @@
 //!     InterruptMode, InterruptPinPolarity, Ltr559, SlaveAddr, AlsPersist, PsPersist,
 //! };
 //!
+//! # #[cfg(feature = "eh0")]
 //! # fn main() {
 //! let dev = hal::I2cdev::new("/dev/i2c-1").unwrap();
 //! let address = SlaveAddr::default();
@@ -147,30 +386,143 @@
 //!     println!("status {:?}", status);
 //! }
 //! # }
+//! # #[cfg(not(feature = "eh0"))]
+//! # fn main() {}
 //! ```
 
 #![deny(unsafe_code, missing_docs)]
 #![no_std]
 
+#[macro_use]
+mod maybe_async;
+
+pub mod adaptive;
+#[cfg(feature = "async")]
+pub mod asynch;
+#[cfg(feature = "float")]
+pub mod autorange;
+pub mod baseline;
+pub mod builder;
+pub mod bus;
+pub mod calc;
+pub mod calibration;
+#[cfg(feature = "float")]
+pub mod change;
+pub mod clock;
+pub mod config;
+#[cfg(feature = "float")]
+pub mod daynight;
+pub mod debounce;
+#[cfg(feature = "float")]
+pub mod distance;
+#[cfg(feature = "heapless")]
+pub mod events;
+pub mod filter;
+pub mod gesture;
+pub mod hal;
+pub mod interrupt;
+#[cfg(feature = "linux-gpio")]
+pub mod linux;
+#[cfg(feature = "heapless")]
+pub mod logger;
+pub mod oversample;
+pub mod proximity;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "float")]
+pub mod telemetry;
 pub mod types;
+#[cfg(feature = "float")]
+pub mod zones;
+pub use crate::adaptive::AdaptiveThresholds;
+#[cfg(feature = "async")]
+pub use crate::asynch::Ltr559Async;
+#[cfg(feature = "float")]
+pub use crate::autorange::{
+    AutoGain, AutoIntegration, AutoRangeMeasurement, RangeManager, RangeStep,
+};
+pub use crate::bands::Transition;
+pub use crate::baseline::PsBaseline;
+pub use crate::builder::Ltr559Builder;
+pub use crate::bus::SeparateWriteRead;
+pub use crate::calc::{LuxAlgorithm, LuxCoefficients};
+#[cfg(feature = "float")]
+pub use crate::calibration::{CalibrationData, CALIBRATION_VERSION};
+pub use crate::calibration::{PsTuning, PsTuningOptions};
+#[cfg(feature = "float")]
+pub use crate::change::{ChangeDetector, LuxChange};
+pub use crate::clock::{Clock, Timestamped};
+pub use crate::config::{Config, ConfigFields, ConfigMismatch, DeviceState};
+#[cfg(feature = "float")]
+pub use crate::daynight::{DayNight, DayNightTransition, DayPhase};
+pub use crate::debounce::PsDebouncer;
+#[cfg(feature = "float")]
+pub use crate::distance::DistanceModel;
+#[cfg(feature = "heapless")]
+pub use crate::events::{Event, EventHandler, Events};
+pub use crate::filter::Median;
+#[cfg(feature = "float")]
+pub use crate::filter::{Ewma, Filter, MovingAverage};
+#[cfg(feature = "kalman")]
+pub use crate::filter::{Kalman, KalmanEstimate};
+pub use crate::gesture::{Gesture, GestureConfig, GestureDetector};
+#[cfg(feature = "eh1")]
+pub use crate::hal::Eh1;
+pub use crate::interrupt::{InterruptHandle, InterruptState};
+#[cfg(feature = "heapless")]
+pub use crate::logger::MeasurementLog;
+pub use crate::oversample::{OversampledAls, Oversampler};
+pub use crate::proximity::{ProximityChange, ProximityZone, ProximityZones};
+#[cfg(feature = "float")]
+pub use crate::run::{Overflow, Pacing, RunControl, RunOptions, RunSample};
+#[cfg(feature = "stats")]
+pub use crate::stats::RunningStats;
+#[cfg(feature = "float")]
+pub use crate::telemetry::{ErrorStats, TelemetryBatch, TelemetryReading, TelemetrySink};
 pub use crate::types::{
-    AlsGain, AlsIntTime, AlsMeasRate, AlsPersist, InterruptMode, LedCurrent, LedDutyCycle,
-    LedPulse, PsMeasRate, PsPersist,
+    AlsContr, AlsGain, AlsIntTime, AlsMeasRate, AlsPersist, InterruptMode, LedConfig, LedCurrent,
+    LedDutyCycle, LedPulse, PsContr, PsMeasRate, PsPersist, Thresholds, Tracking, WindowMode,
+    PS_FAST_MODE_MAX_AVERAGE_CURRENT_UA,
 };
+#[cfg(feature = "float")]
+pub use crate::zones::{LuxZone, ZoneClassifier, ZoneTransition};
 
 use core::marker::PhantomData;
-extern crate embedded_hal as hal;
 extern crate nb;
 
 /// Errors in this crate
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error<E> {
     /// I²C bus communication error
     I2C(E),
     /// Invalid input data provided
     InvalidInputData,
+    /// The device did not produce the expected data in time
+    Timeout,
+    /// Error while accessing the interrupt pin
+    Pin,
+    /// No new valid data is available
+    StaleData,
+    /// An ALS channel is at full scale, so lux cannot be computed
+    AlsSaturated,
+}
+
+impl<E: core::fmt::Debug> core::fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::I2C(e) => write!(f, "I2C bus error: {:?}", e),
+            Error::InvalidInputData => f.write_str("invalid input data"),
+            Error::Timeout => f.write_str("timeout waiting for data"),
+            Error::Pin => f.write_str("interrupt pin error"),
+            Error::StaleData => f.write_str("no new valid data available"),
+            Error::AlsSaturated => f.write_str("ALS channel saturated"),
+        }
+    }
 }
 
+impl<E: core::fmt::Debug> core::error::Error for Error<E> {}
+
 /// Error type for mode changes.
 ///
 /// This allows to retrieve the unchanged device in case of an error.
@@ -180,6 +532,19 @@ pub enum ModeChangeError<E, DEV> {
     /// `E` is the error that happened.
     /// `DEV` is the device with the mode unchanged.
     I2C(E, DEV),
+    /// Invalid input data provided.
+    ///
+    /// `DEV` is the device with the mode unchanged.
+    InvalidInputData(DEV),
+    /// The configuration read back from the device does not match the
+    /// requested one.
+    ///
+    /// `DEV` is the device.
+    ConfigMismatch(DEV),
+    /// The part ID read from the device is not the one of an LTR-559.
+    ///
+    /// Contains the part ID read and `DEV`, the device.
+    WrongPartId(u8, DEV),
 }
 
 /// IC markers
@@ -203,11 +568,41 @@ pub struct Ltr559<I2C, IC> {
     address: u8,
     als_gain: AlsGain,
     als_int: AlsIntTime,
+    als_meas_rate: AlsMeasRate,
+    /// (original, lengthened) rate of the last change_range_safely()
+    lengthened_meas_rate: Option<(AlsMeasRate, AlsMeasRate)>,
+    ps_meas_rate: PsMeasRate,
+    interrupt_polarity: InterruptPinPolarity,
+    last_als_ready_ms: Option<u32>,
+    last_ps_ready_ms: Option<u32>,
+    cache_dirty: bool,
+    staged: Option<staging::RegisterImage>,
+    applied_config: Option<Config>,
+    active_before_standby: Option<(bool, bool)>,
+    lux_coefficients: calc::LuxCoefficients,
+    lux_algorithm: calc::LuxAlgorithm,
+    held_als: (u16, u16),
+    dark_counts: (u16, u16),
+    #[cfg(feature = "float")]
+    window_factor: f32,
+    /// `window_factor` as a 16.16 fixed-point number for the integer variants
+    #[cfg(feature = "float")]
+    window_factor_q16: i32,
+    #[cfg(feature = "float")]
+    lux_offset: f32,
+    /// `lux_offset` as a 16.16 fixed-point number for the integer variants
+    #[cfg(feature = "float")]
+    lux_offset_q16: i32,
+    #[cfg(feature = "stats")]
+    lux_stats: stats::RunningStats,
+    last_status: Option<Status>,
+    diagnostics: DiagnosticCounters,
     _ic: PhantomData<IC>,
 }
 
 /// Possible slave addresses
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SlaveAddr {
     /// Default slave address
     Default,
@@ -216,9 +611,11 @@ pub enum SlaveAddr {
 }
 
 /// Interrupt pin polarity (active state)
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum InterruptPinPolarity {
     /// Active low (default)
+    #[default]
     Low,
     /// Active high
     High,
@@ -232,10 +629,20 @@ impl InterruptPinPolarity {
             InterruptPinPolarity::High => 1 << 2,
         }
     }
+
+    /// Decode from the INTERRUPT register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        if value & (1 << 2) != 0 {
+            InterruptPinPolarity::High
+        } else {
+            InterruptPinPolarity::Low
+        }
+    }
 }
 
 /// Conversion status
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Status {
     /// ALS Data Valid
     pub als_data_valid: bool,
@@ -249,10 +656,215 @@ pub struct Status {
     pub ps_interrupt_status: bool,
     /// PS Data Status
     pub ps_data_status: bool,
+    /// PS saturation indicator, only asserted when enabled with
+    /// `set_ps_contr()`
+    pub ps_saturated: bool,
+}
+
+/// Cause of an interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InterruptCause {
+    /// ALS interrupt (measurement outside the ALS thresholds)
+    pub als: bool,
+    /// PS interrupt (measurement outside the PS thresholds)
+    pub ps: bool,
+}
+
+/// Event and error counts kept by the driver
+///
+/// See [`Ltr559::diagnostics()`]. The counts wrap around on overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DiagnosticCounters {
+    /// Status reads with the ALS interrupt flag set
+    pub als_interrupts: u32,
+    /// Status reads with the PS interrupt flag set
+    pub ps_interrupts: u32,
+    /// Saturated ALS data reported by the lux, measurement and event methods
+    pub als_saturations: u32,
+    /// Status reads with new saturated PS data
+    pub ps_saturations: u32,
+    /// Failed I²C transactions
+    pub i2c_errors: u32,
+}
+
+/// Raw ALS and PS measurement
+///
+/// Holds integer values only, so it can be printed on targets that cannot
+/// afford floating-point formatting.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RawMeasurement {
+    /// ALS channel 0 (visible + IR) counts
+    pub als_ch0: u16,
+    /// ALS channel 1 (IR) counts
+    pub als_ch1: u16,
+    /// PS counts
+    pub ps: u16,
+    /// PS saturation flag
+    pub ps_saturated: bool,
+}
+
+/// ALS channel data
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AlsData {
+    /// Channel 0 (visible + IR) counts
+    pub ch0: u16,
+    /// Channel 1 (IR) counts
+    pub ch1: u16,
+}
+
+impl AlsData {
+    /// IR ratio CH1 / (CH0 + CH1), as used in the lux computation.
+    ///
+    /// Returns 1.0 if both channels are 0.
+    #[cfg(feature = "float")]
+    pub fn ratio(&self) -> f32 {
+        let total = u32::from(self.ch0) + u32::from(self.ch1);
+        if total == 0 {
+            1.0
+        } else {
+            self.ch1 as f32 / total as f32
+        }
+    }
+
+    /// IR ratio CH1 / (CH0 + CH1) in per mille, with integer arithmetic
+    /// only.
+    ///
+    /// Returns 1000 if both channels are 0.
+    pub fn ratio_per_mille(&self) -> u16 {
+        let total = u32::from(self.ch0) + u32::from(self.ch1);
+        (u32::from(self.ch1) * 1000)
+            .checked_div(total)
+            .unwrap_or(1000) as u16
+    }
+
+    /// Coarse estimation of the light source from the IR ratio.
+    ///
+    /// Returns `None` if both channels are 0.
+    pub fn light_source(&self) -> Option<LightSource> {
+        if self.ch0 == 0 && self.ch1 == 0 {
+            return None;
+        }
+        Some(match self.ratio_per_mille() {
+            0..=249 => LightSource::FluorescentOrLed,
+            250..=449 => LightSource::Sunlight,
+            _ => LightSource::Incandescent,
+        })
+    }
+
+    /// Whether either channel is at full scale
+    pub fn is_saturated(&self) -> bool {
+        self.ch0 == u16::MAX || self.ch1 == u16::MAX
+    }
+}
+
+/// Light source type estimated from the IR ratio, e.g. as a hint for
+/// camera white balance or display tuning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LightSource {
+    /// Little infrared: IR ratio below 25%
+    FluorescentOrLed,
+    /// Moderate infrared: IR ratio from 25% to 45%
+    Sunlight,
+    /// Strong infrared: IR ratio of 45% or more
+    Incandescent,
+}
+
+/// PS data
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PsData {
+    /// PS counts (11 bits)
+    pub counts: u16,
+    /// PS saturation flag
+    pub saturated: bool,
+    /// Whether the data is new since the status was last read
+    pub new_data: bool,
+}
+
+/// ALS channels, status and PS data read in a single burst
+///
+/// See [`Ltr559::read_all()`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AllData {
+    /// ALS channel 0 (visible + IR) counts
+    pub als_ch0: u16,
+    /// ALS channel 1 (IR) counts
+    pub als_ch1: u16,
+    /// Conversion status
+    pub status: Status,
+    /// PS counts
+    pub ps: u16,
+    /// PS saturation flag
+    pub ps_saturated: bool,
+}
+
+/// Coherent snapshot of the ALS and PS data
+///
+/// See [`Ltr559::get_measurement()`].
+#[cfg(feature = "float")]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurement {
+    /// Illuminance in lux
+    pub lux: f32,
+    /// ALS channel 0 (visible + IR) counts
+    pub als_ch0: u16,
+    /// ALS channel 1 (IR) counts
+    pub als_ch1: u16,
+    /// Whether an ALS channel is at full scale, in which case `lux` is not
+    /// meaningful
+    pub als_saturated: bool,
+    /// PS counts
+    pub ps: u16,
+    /// PS saturation flag
+    pub ps_saturated: bool,
+    /// ALS gain the data was measured with
+    pub als_gain_used: AlsGain,
+    /// ALS integration time the data was measured with
+    pub als_int_used: AlsIntTime,
+}
+
+/// Mean of several lux readings
+///
+/// See [`Ltr559::get_lux_averaged()`].
+#[cfg(feature = "float")]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LuxAverage {
+    /// Mean illuminance in lux
+    pub mean: f32,
+    /// Population variance of the readings in lux²
+    pub variance: f32,
+    /// Number of readings
+    pub samples: u8,
+}
+
+#[cfg(feature = "float")]
+impl LuxAverage {
+    /// Standard deviation of the readings in lux
+    pub fn std_dev(&self) -> f32 {
+        math::sqrt(self.variance)
+    }
 }
 
+mod bands;
 mod device_impl;
+#[cfg(feature = "float")]
+mod math;
+#[cfg(test)]
+mod mock;
+#[cfg(feature = "float")]
+mod run;
 mod slave_addr;
+mod staging;
+#[cfg(feature = "ufmt")]
+mod ufmt_impl;
 
 mod private {
     use super::ic;
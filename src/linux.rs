@@ -0,0 +1,88 @@
+//! Interrupt-driven event polling on Linux (`linux-gpio` feature)
+//!
+//! [`InterruptLine`] requests the GPIO connected to the INT pin through the
+//! GPIO character device and blocks until the device asserts it.
+//! [`Ltr559::wait_events()`] ties it to [`Ltr559::poll_events()`], so that
+//! a Raspberry Pi only reads the sensor when an interrupt is pending.
+//!
+//! ```no_run
+//! use ltr_559::linux::InterruptLine;
+//! use ltr_559::InterruptPinPolarity;
+//!
+//! // INT of the Enviro board on BCM 4
+//! let mut line = InterruptLine::open("/dev/gpiochip0", 4, InterruptPinPolarity::Low).unwrap();
+//! line.wait().unwrap();
+//! ```
+extern crate std;
+
+use crate::hal::blocking::i2c;
+use crate::{Error, Events, InterruptPinPolarity, Ltr559};
+use gpio_cdev::{Chip, EventRequestFlags, LineEventHandle, LineRequestFlags};
+use std::path::Path;
+
+/// Consumer label of the requested line, shown by `gpioinfo`
+const CONSUMER: &str = "ltr-559";
+
+/// The GPIO line connected to the INT pin
+#[derive(Debug)]
+pub struct InterruptLine {
+    events: LineEventHandle,
+    polarity: InterruptPinPolarity,
+}
+
+impl InterruptLine {
+    /// Request line `offset` of the GPIO chip at `chip` (e.g.
+    /// `/dev/gpiochip0`) as an input reporting the edges asserting the
+    /// interrupt.
+    ///
+    /// `polarity` must match the one configured with
+    /// [`Ltr559::set_interrupt()`].
+    pub fn open<P: AsRef<Path>>(
+        chip: P,
+        offset: u32,
+        polarity: InterruptPinPolarity,
+    ) -> Result<Self, gpio_cdev::Error> {
+        let edge = match polarity {
+            InterruptPinPolarity::Low => EventRequestFlags::FALLING_EDGE,
+            InterruptPinPolarity::High => EventRequestFlags::RISING_EDGE,
+        };
+        let events =
+            Chip::new(chip)?
+                .get_line(offset)?
+                .events(LineRequestFlags::INPUT, edge, CONSUMER)?;
+        Ok(InterruptLine { events, polarity })
+    }
+
+    /// Whether the interrupt is currently asserted
+    pub fn is_active(&self) -> Result<bool, gpio_cdev::Error> {
+        let high = self.events.get_value()? != 0;
+        Ok(high == (self.polarity == InterruptPinPolarity::High))
+    }
+
+    /// Block until the next edge asserting the interrupt.
+    ///
+    /// Edges are queued by the kernel from the moment the line is opened,
+    /// so an edge that occurred since the last call returns immediately.
+    pub fn wait(&mut self) -> Result<(), gpio_cdev::Error> {
+        self.events.get_event().map(|_| ())
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+{
+    /// Wait until `line` signals an interrupt, then poll the events (see
+    /// [`poll_events()`](#method.poll_events)).
+    ///
+    /// Does not block if the interrupt is already asserted. The events may
+    /// be empty if they were already read after a queued edge.
+    ///
+    /// Errors of the GPIO line are returned as `Error::Pin`.
+    pub fn wait_events(&mut self, line: &mut InterruptLine) -> Result<Events, Error<E>> {
+        if !line.is_active().map_err(|_| Error::Pin)? {
+            line.wait().map_err(|_| Error::Pin)?;
+        }
+        self.poll_events()
+    }
+}
@@ -0,0 +1,115 @@
+//! Recent measurement history in a fixed-size ring buffer
+//!
+//! The log keeps the last `N` measurements with the timestamps given by a
+//! [`Clock`], without allocating, so that devices can retain recent history
+//! across connectivity gaps.
+use crate::hal::blocking::i2c;
+use crate::{marker, Clock, Error, Ltr559, Measurement, Timestamped};
+use heapless::HistoryBuffer;
+
+/// The last `N` measurements, older entries being overwritten
+#[derive(Debug, Clone)]
+pub struct MeasurementLog<const N: usize> {
+    entries: HistoryBuffer<Timestamped<Measurement>, N>,
+}
+
+impl<const N: usize> MeasurementLog<N> {
+    /// Create an empty log
+    pub const fn new() -> Self {
+        MeasurementLog {
+            entries: HistoryBuffer::new(),
+        }
+    }
+
+    /// Add a measurement, overwriting the oldest one if the log is full
+    pub fn push(&mut self, timestamp_ms: u32, measurement: Measurement) {
+        self.entries.write(Timestamped {
+            timestamp_ms,
+            value: measurement,
+        });
+    }
+
+    /// Most recent entry
+    pub fn latest(&self) -> Option<&Timestamped<Measurement>> {
+        self.entries.recent()
+    }
+
+    /// Entries from the oldest to the most recent one
+    pub fn iter(&self) -> impl Iterator<Item = &Timestamped<Measurement>> {
+        self.entries.oldest_ordered()
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the log is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<const N: usize> Default for MeasurementLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Take a measurement (see [`get_measurement()`](#method.get_measurement))
+    /// and add it to `log` with the time of `clock` at the read.
+    ///
+    /// Nothing is logged in case of error.
+    pub fn log_measurement<C: Clock, const N: usize>(
+        &mut self,
+        log: &mut MeasurementLog<N>,
+        clock: &mut C,
+    ) -> Result<(), Error<E>> {
+        let entry = self.get_measurement_timestamped(clock)?;
+        log.entries.write(entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    #[test]
+    fn keeps_the_last_entries() {
+        let mut log = MeasurementLog::<2>::new();
+        for timestamp in 1..=3 {
+            log.push(timestamp, Measurement::default());
+        }
+        assert_eq!(log.len(), 2);
+        let timestamps = [
+            log.iter().next().unwrap().timestamp_ms,
+            log.latest().unwrap().timestamp_ms,
+        ];
+        assert_eq!(timestamps, [2, 3]);
+    }
+
+    #[test]
+    fn logs_measurement_with_clock() {
+        let mut i2c = I2cMock::new();
+        i2c.registers[0x8A] = 100;
+        let mut sensor = Ltr559::new_device(i2c, SlaveAddr::default());
+        let mut log = MeasurementLog::<4>::new();
+        sensor.log_measurement(&mut log, &mut || 1234).unwrap();
+        let entry = log.latest().unwrap();
+        assert_eq!(entry.timestamp_ms, 1234);
+        assert_eq!(entry.value.lux, 177.43);
+    }
+}
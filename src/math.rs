@@ -0,0 +1,73 @@
+//! Elementary functions missing from `core`
+use core::f32::consts::LN_2;
+
+/// Natural logarithm of a positive normal number
+pub(crate) fn ln(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let mut exponent = ((bits >> 23) & 0xFF) as i32 - 127;
+    let mut mantissa = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+    if mantissa > core::f32::consts::SQRT_2 {
+        mantissa /= 2.0;
+        exponent += 1;
+    }
+    // ln(m) = 2 atanh((m - 1) / (m + 1))
+    let z = (mantissa - 1.0) / (mantissa + 1.0);
+    let z2 = z * z;
+    let series = 1.0 + z2 * (1.0 / 3.0 + z2 * (1.0 / 5.0 + z2 * (1.0 / 7.0 + z2 / 9.0)));
+    exponent as f32 * LN_2 + 2.0 * z * series
+}
+
+/// Exponential function
+pub(crate) fn exp(y: f32) -> f32 {
+    if y > 88.0 {
+        return f32::INFINITY;
+    }
+    if y < -87.0 {
+        return 0.0;
+    }
+    // y = k ln(2) + r with |r| <= ln(2) / 2
+    let k = (y / LN_2 + if y < 0.0 { -0.5 } else { 0.5 }) as i32;
+    let r = y - k as f32 * LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for i in 1..8 {
+        term *= r / i as f32;
+        sum += term;
+    }
+    sum * f32::from_bits(((k + 127) as u32) << 23)
+}
+
+/// Square root by Newton's method, 0 for non-positive numbers
+pub(crate) fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let mut root = x.max(1.0);
+    for _ in 0..32 {
+        root = 0.5 * (root + x / root);
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ln_and_exp() {
+        for &x in &[1.0, 2.0, 10.0, 1234.5, 2047.0] {
+            assert!((exp(ln(x)) - x).abs() < x * 1e-5);
+        }
+        assert!((ln(100.0) - 4.605_17).abs() < 1e-5);
+        assert!((exp(-1.0) - 0.367_879_4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn square_root() {
+        assert_eq!(sqrt(-4.0), 0.0);
+        assert_eq!(sqrt(0.0), 0.0);
+        assert!((sqrt(2.0) - core::f32::consts::SQRT_2).abs() < 1e-6);
+        assert!((sqrt(0.25) - 0.5).abs() < 1e-6);
+        assert!((sqrt(1.0e6) - 1000.0).abs() < 1e-3);
+    }
+}
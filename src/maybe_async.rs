@@ -0,0 +1,1611 @@
+//! Register-level methods shared by the blocking and asynchronous drivers.
+//!
+//! The method bodies are written once and expanded for each driver. The
+//! first macro argument holds the `async` keyword (or nothing) placed in
+//! front of every `fn`, the second one the `.await` (or nothing) placed
+//! after every bus access, so that the blocking expansion is plain
+//! synchronous code.
+//!
+//! The expanding type needs the fields `i2c`, `address`, `als_gain`,
+//! `als_int`, `als_meas_rate`, `ps_meas_rate`, `interrupt_polarity`,
+//! `cache_dirty`, `staged`, `active_before_standby`, `lux_coefficients`,
+//! `lux_algorithm`, `held_als`, `dark_counts`, `last_status` and
+//! `diagnostics`, with the `float` feature also `window_factor` and
+//! `lux_offset` and with the `stats` feature `lux_stats`. The error type
+//! parameter of the bus must be called `E`.
+
+/// Status and read helpers. Requires a bus able to do write-read transactions.
+macro_rules! impl_read_methods {
+    ([$($async:tt)*] [$($await:tt)*]) => {
+        /// Read the status of the conversion.
+        ///
+        /// The PS saturation indicator is read in the same burst.
+        ///
+        /// Note that the conversion ready and interrupt flags are cleared
+        /// automatically after calling this method. See
+        /// [`peek_status()`](#method.peek_status) to look at the status
+        /// again without clearing them.
+        pub $($async)* fn get_status(&mut self) -> Result<$crate::Status, $crate::Error<E>> {
+            let mut data = [0; 3];
+            self.read_registers($crate::device_impl::Register::ALS_PS_STATUS, &mut data)$($await)*?;
+            let status = $crate::Status::from_registers(data[0], data[2]);
+            self.record_status(status);
+            Ok(status)
+        }
+
+        /// Read which of the ALS and the PS triggered the interrupt.
+        ///
+        /// The interrupt flags, and so the INT pin, are cleared by the read
+        /// of the ALS_PS_STATUS register done here, as by every status read
+        /// (e.g. [`get_status()`](#method.get_status)). The data-ready flags
+        /// are cleared as well. Use [`peek_status()`](#method.peek_status)
+        /// to look at the last status read without clearing anything.
+        pub $($async)* fn read_interrupt_cause(&mut self) -> Result<$crate::InterruptCause, $crate::Error<E>> {
+            let status = self.get_status()$($await)*?;
+            Ok($crate::InterruptCause {
+                als: status.als_interrupt_status,
+                ps: status.ps_interrupt_status,
+            })
+        }
+
+        /// Whether the PS saturation indicator is asserted.
+        ///
+        /// The indicator has to be enabled with
+        /// [`set_ps_contr()`](#method.set_ps_contr), otherwise this always
+        /// returns `false`.
+        pub $($async)* fn is_ps_saturated(&mut self) -> Result<bool, $crate::Error<E>> {
+            let mut data = [0; 2];
+            self.read_registers($crate::device_impl::Register::PS_DATA_0, &mut data)$($await)*?;
+            Ok($crate::device_impl::decode_ps_data(data[0], data[1]).1)
+        }
+
+        /// Read and decode the ALS_CONTR register
+        ///
+        /// Returns `Error::InvalidInputData` if the gain field holds a reserved value.
+        pub $($async)* fn get_als_contr(&mut self) -> Result<$crate::AlsContr, $crate::Error<E>> {
+            let value = self.read_register($crate::device_impl::Register::ALS_CONTR)$($await)*?;
+            $crate::AlsContr::from_register(value).ok_or($crate::Error::InvalidInputData)
+        }
+
+        /// Read and decode the PS_CONTR register
+        pub $($async)* fn get_ps_contr(&mut self) -> Result<$crate::PsContr, $crate::Error<E>> {
+            let value = self.read_register($crate::device_impl::Register::PS_CONTR)$($await)*?;
+            Ok($crate::PsContr::from_register(value))
+        }
+
+        /// Read the ALS integration time and measurement repeat rate
+        pub $($async)* fn get_als_meas_rate(
+            &mut self,
+        ) -> Result<($crate::AlsIntTime, $crate::AlsMeasRate), $crate::Error<E>> {
+            let value = self.read_register($crate::device_impl::Register::ALS_MEAS_RATE)$($await)*?;
+            Ok((
+                $crate::AlsIntTime::from_register(value),
+                $crate::AlsMeasRate::from_register(value),
+            ))
+        }
+
+        /// Read the PS measurement repeat rate
+        pub $($async)* fn get_ps_meas_rate(&mut self) -> Result<$crate::PsMeasRate, $crate::Error<E>> {
+            let value = self.read_register($crate::device_impl::Register::PS_MEAS_RATE)$($await)*?;
+            Ok($crate::PsMeasRate::from_register(value))
+        }
+
+        /// Read the PS LED pulse frequency, duty cycle and peak current
+        pub $($async)* fn get_ps_led(
+            &mut self,
+        ) -> Result<($crate::LedPulse, $crate::LedDutyCycle, $crate::LedCurrent), $crate::Error<E>> {
+            let value = self.read_register($crate::device_impl::Register::PS_LED)$($await)*?;
+            Ok((
+                $crate::LedPulse::from_register(value),
+                $crate::LedDutyCycle::from_register(value),
+                $crate::LedCurrent::from_register(value),
+            ))
+        }
+
+        /// Read the number of LED pulses per PS measurement
+        pub $($async)* fn get_ps_n_pulses(&mut self) -> Result<u8, $crate::Error<E>> {
+            let value = self.read_register($crate::device_impl::Register::PS_N_PULSES)$($await)*?;
+            Ok(value & 0x0F)
+        }
+
+        /// Read the PS offset
+        pub $($async)* fn get_ps_offset(&mut self) -> Result<u16, $crate::Error<E>> {
+            let value = self.read_u16($crate::device_impl::Register::PS_OFFSET_0)$($await)*?;
+            Ok(value & 0x03FF)
+        }
+
+        /// Read the interrupt pin polarity and interrupt mode
+        pub $($async)* fn get_interrupt(
+            &mut self,
+        ) -> Result<($crate::InterruptPinPolarity, $crate::InterruptMode), $crate::Error<E>> {
+            let value = self.read_register($crate::device_impl::Register::INTERRUPT)$($await)*?;
+            Ok((
+                $crate::InterruptPinPolarity::from_register(value),
+                $crate::InterruptMode::from_register(value),
+            ))
+        }
+
+        /// Read the fault count for both ALS and PS
+        pub $($async)* fn get_interrupt_persist(
+            &mut self,
+        ) -> Result<($crate::AlsPersist, $crate::PsPersist), $crate::Error<E>> {
+            let value = self.read_register($crate::device_impl::Register::INTERRUPT_PERSIST)$($await)*?;
+            Ok((
+                $crate::AlsPersist::from_register(value),
+                $crate::PsPersist::from_register(value),
+            ))
+        }
+
+        /// Read the ALS and PS interrupt thresholds
+        pub $($async)* fn get_thresholds(&mut self) -> Result<$crate::Thresholds, $crate::Error<E>> {
+            Ok($crate::Thresholds {
+                als_low: self.read_u16($crate::device_impl::Register::ALS_THRES_LOW_0)$($await)*?,
+                als_high: self.read_u16($crate::device_impl::Register::ALS_THRES_UP_0)$($await)*?,
+                ps_low: self.read_u16($crate::device_impl::Register::PS_THRES_LOW_0)$($await)*? & 0x07FF,
+                ps_high: self.read_u16($crate::device_impl::Register::PS_THRES_UP_0)$($await)*? & 0x07FF,
+            })
+        }
+
+        /// Re-read the cached configuration (ALS gain, integration time and
+        /// measurement rate) from the device and clear the dirty flag.
+        pub $($async)* fn resync_internal_driver_state(&mut self) -> Result<(), $crate::Error<E>> {
+            let contr = self.read_register($crate::device_impl::Register::ALS_CONTR)$($await)*?;
+            let meas_rate = self.read_register($crate::device_impl::Register::ALS_MEAS_RATE)$($await)*?;
+            if let Some(gain) = $crate::AlsGain::from_register(contr) {
+                self.als_gain = gain;
+            }
+            self.als_int = $crate::AlsIntTime::from_register(meas_rate);
+            self.als_meas_rate = $crate::AlsMeasRate::from_register(meas_rate);
+            self.cache_dirty = false;
+            Ok(())
+        }
+
+        /// Read a register with a write-read transaction (repeated start).
+        ///
+        /// See the [`bus`](crate::bus) module for controllers that cannot do that.
+        pub(crate) $($async)* fn read_register(&mut self, register: u8) -> Result<u8, $crate::Error<E>> {
+            if let Some(value) = self.staged.as_ref().and_then(|staged| staged.get(register)) {
+                return Ok(value);
+            }
+            let mut data = [0];
+            let result = self.i2c
+                .write_read(self.address, &[register], &mut data)
+                $($await)*;
+            self.check_bus(result)?;
+            #[cfg(feature = "log")]
+            log::trace!("read 0x{:02x}: [0x{:02x}] = 0x{:02x}", self.address, register, data[0]);
+            Ok(data[0])
+        }
+
+        pub(crate) $($async)* fn read_u16(&mut self, register_low: u8) -> Result<u16, $crate::Error<E>> {
+            let low = self.read_register(register_low)$($await)*?;
+            let high = self.read_register(register_low + 1)$($await)*?;
+            Ok(u16::from(high) << 8 | u16::from(low))
+        }
+
+        /// Read consecutive registers starting at `register` in one
+        /// transaction. Staged values are not taken into account.
+        pub(crate) $($async)* fn read_registers(&mut self, register: u8, data: &mut [u8]) -> Result<(), $crate::Error<E>> {
+            let result = self.i2c
+                .write_read(self.address, &[register], data)
+                $($await)*;
+            self.check_bus(result)?;
+            #[cfg(feature = "log")]
+            log::trace!("read 0x{:02x}: [0x{:02x}..] = {:02x?}", self.address, register, data);
+            Ok(())
+        }
+    };
+}
+
+/// Configuration setters. Requires a bus able to do write transactions.
+macro_rules! impl_write_methods {
+    ([$($async:tt)*] [$($await:tt)*]) => {
+        /// Set ALS_CONTR Register
+        ///
+        pub $($async)* fn set_als_contr(
+            &mut self,
+            als_gain: $crate::AlsGain,
+            sw_reset: bool,
+            als_active: bool,
+        ) -> Result<(), $crate::Error<E>> {
+            let mut value: u8 = als_gain.value();
+            if sw_reset {
+                value += 2;
+            }
+            if als_active {
+                value += 1;
+            }
+
+            self.write_register($crate::device_impl::Register::ALS_CONTR, value)$($await)*?;
+            self.als_gain = als_gain;
+            Ok(())
+        }
+
+        /// Set PS_CONTR Register
+        ///
+        pub $($async)* fn set_ps_contr(
+            &mut self,
+            ps_saturation_indicator_enable: bool,
+            ps_active: bool,
+        ) -> Result<(), $crate::Error<E>> {
+            let mut value: u8 = 0;
+            if ps_saturation_indicator_enable {
+                value += 1 << 5;
+            }
+            if ps_active {
+                value += 3;
+            }
+
+            self.write_register($crate::device_impl::Register::PS_CONTR, value)$($await)*
+        }
+
+        /// Set PS LED controls
+        ///
+        /// The average LED current is not checked, see
+        /// [`set_led_config()`](#method.set_led_config).
+        pub $($async)* fn set_ps_led(
+            &mut self,
+            led_pulse_freq: $crate::LedPulse,
+            led_duty_cycle: $crate::LedDutyCycle,
+            led_peak_current: $crate::LedCurrent,
+        ) -> Result<(), $crate::Error<E>> {
+            let value = led_pulse_freq.value() | led_duty_cycle.value() | led_peak_current.value();
+            self.write_register($crate::device_impl::Register::PS_LED, value)$($await)*
+        }
+
+        /// Set the PS LED drive settings and pulse count, checking that the
+        /// average LED current at the current PS measurement rate does not
+        /// exceed `max_average_current_ua`.
+        ///
+        /// The datasheet only rates the peak current, so the limit comes
+        /// from the power and thermal budget of the product.
+        ///
+        /// The check is not repeated when the measurement rate is changed
+        /// afterwards, so set the rate first.
+        ///
+        /// Returns `Error::InvalidInputData` if the pulse count is out of
+        /// range or the limit is exceeded, in which case nothing is written.
+        pub $($async)* fn set_led_config(
+            &mut self,
+            config: &$crate::LedConfig,
+            max_average_current_ua: u32,
+        ) -> Result<(), $crate::Error<E>> {
+            if !config.is_within(self.ps_meas_rate, max_average_current_ua) {
+                return Err($crate::Error::InvalidInputData);
+            }
+            self.set_led_config_unchecked(config)$($await)*
+        }
+
+        /// Set the PS LED drive settings and pulse count without checking
+        /// the average LED current.
+        ///
+        /// Returns `Error::InvalidInputData` if the pulse count is out of
+        /// range, in which case nothing is written.
+        pub $($async)* fn set_led_config_unchecked(&mut self, config: &$crate::LedConfig) -> Result<(), $crate::Error<E>> {
+            if config.n_pulses == 0 || config.n_pulses > 15 {
+                return Err($crate::Error::InvalidInputData);
+            }
+            self.set_ps_led(config.pulse, config.duty_cycle, config.current)$($await)*?;
+            self.set_ps_n_pulses(config.n_pulses)$($await)*
+        }
+
+        /// Set the fault count for both ALS and PS
+        ///
+        pub $($async)* fn set_interrupt_persist(
+            &mut self,
+            als_count: $crate::AlsPersist,
+            ps_count: $crate::PsPersist,
+        ) -> Result<(), $crate::Error<E>> {
+            let value = ps_count.value() | als_count.value();
+            self.write_register($crate::device_impl::Register::INTERRUPT_PERSIST, value)$($await)*
+        }
+
+        /// Set the integration (conversion) time and measurement repeat timer
+        pub $($async)* fn set_als_meas_rate(
+            &mut self,
+            als_int: $crate::AlsIntTime,
+            als_meas_rate: $crate::AlsMeasRate,
+        ) -> Result<(), $crate::Error<E>> {
+            let value = (als_int.value() << 3) | als_meas_rate.value();
+            self.write_register($crate::device_impl::Register::ALS_MEAS_RATE, value)$($await)*?;
+            self.als_int = als_int;
+            self.als_meas_rate = als_meas_rate;
+            Ok(())
+        }
+
+        /// Set the lux low limit in raw format
+        pub $($async)* fn set_als_low_limit_raw(&mut self, value: u16) -> Result<(), $crate::Error<E>> {
+            self.write_u16($crate::device_impl::Register::ALS_THRES_LOW_0, value)$($await)*
+        }
+
+        /// Set the lux high limit in raw format
+        pub $($async)* fn set_als_high_limit_raw(&mut self, value: u16) -> Result<(), $crate::Error<E>> {
+            self.write_u16($crate::device_impl::Register::ALS_THRES_UP_0, value)$($await)*
+        }
+
+        /// Set both ALS thresholds in raw format.
+        ///
+        /// Returns `Error::InvalidInputData` without writing anything if
+        /// `low` is greater than `high`.
+        pub $($async)* fn set_als_thresholds(&mut self, low: u16, high: u16) -> Result<(), $crate::Error<E>> {
+            if low > high {
+                return Err($crate::Error::InvalidInputData);
+            }
+            self.write_u16($crate::device_impl::Register::ALS_THRES_UP_0, high)$($await)*?;
+            self.write_u16($crate::device_impl::Register::ALS_THRES_LOW_0, low)$($await)*
+        }
+
+        /// Set the ps low limit in raw format
+        pub $($async)* fn set_ps_low_limit_raw(&mut self, value: u16) -> Result<(), $crate::Error<E>> {
+            self.write_u16($crate::device_impl::Register::PS_THRES_LOW_0, value)$($await)*
+        }
+
+        /// Set the ps high limit in raw format
+        pub $($async)* fn set_ps_high_limit_raw(&mut self, value: u16) -> Result<(), $crate::Error<E>> {
+            self.write_u16($crate::device_impl::Register::PS_THRES_UP_0, value)$($await)*
+        }
+
+        /// Set both PS thresholds in raw format.
+        ///
+        /// Returns `Error::InvalidInputData` without writing anything if a
+        /// value exceeds the 11-bit PS data range (2047) or if `low` is
+        /// greater than `high`.
+        pub $($async)* fn set_ps_thresholds(&mut self, low: u16, high: u16) -> Result<(), $crate::Error<E>> {
+            if high > 0x07FF || low > high {
+                return Err($crate::Error::InvalidInputData);
+            }
+            self.write_u16($crate::device_impl::Register::PS_THRES_UP_0, high)$($await)*?;
+            self.write_u16($crate::device_impl::Register::PS_THRES_LOW_0, low)$($await)*
+        }
+
+        /// Set the ALS thresholds to `center` ± `delta`.
+        ///
+        /// The limits saturate at the register bounds.
+        pub $($async)* fn set_als_window(&mut self, center: u16, delta: u16) -> Result<(), $crate::Error<E>> {
+            self.set_als_thresholds(center.saturating_sub(delta), center.saturating_add(delta))$($await)*
+        }
+
+        /// Set the PS thresholds to `center` ± `delta`.
+        ///
+        /// The limits saturate at the bounds of the 11-bit PS data range.
+        /// Returns `Error::InvalidInputData` if `center` is outside of it.
+        pub $($async)* fn set_ps_window(&mut self, center: u16, delta: u16) -> Result<(), $crate::Error<E>> {
+            if center > 0x07FF {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let high = center.saturating_add(delta).min(0x07FF);
+            self.set_ps_thresholds(center.saturating_sub(delta), high)$($await)*
+        }
+
+        /// Set PS Meas Rate
+        pub $($async)* fn set_ps_meas_rate(
+            &mut self,
+            ps_meas_rate: $crate::PsMeasRate,
+        ) -> Result<(), $crate::Error<E>> {
+            self.write_register($crate::device_impl::Register::PS_MEAS_RATE, ps_meas_rate.value())$($await)*?;
+            self.ps_meas_rate = ps_meas_rate;
+            Ok(())
+        }
+
+        /// Set PS OFFSET.
+        ///
+        /// Values that exceed 1023 will cause an Err to be returned
+        pub $($async)* fn set_ps_offset(&mut self, value: u16) -> Result<(), $crate::Error<E>> {
+            if value > 1023 {
+                return Err($crate::Error::InvalidInputData);
+            }
+            self.write_u16($crate::device_impl::Register::PS_OFFSET_0, value)$($await)*
+        }
+
+        /// Set PS N Pulses
+        ///
+        /// Accepted values are 1 to 15
+        pub $($async)* fn set_ps_n_pulses(&mut self, value: u8) -> Result<(), $crate::Error<E>> {
+            if value > 0 && value < 16 {
+                self.write_register($crate::device_impl::Register::PS_N_PULSES, value)$($await)*
+            } else {
+                Err($crate::Error::InvalidInputData)
+            }
+        }
+
+        /// Set Interrupt Polarity and Enable
+        pub $($async)* fn set_interrupt(
+            &mut self,
+            polarity: $crate::InterruptPinPolarity,
+            mode: $crate::InterruptMode,
+        ) -> Result<(), $crate::Error<E>> {
+            let value = mode.value() | polarity.value();
+            self.write_register($crate::device_impl::Register::INTERRUPT, value)$($await)*?;
+            self.interrupt_polarity = polarity;
+            Ok(())
+        }
+
+        /// Write all staged register values and leave staging mode.
+        ///
+        /// Consecutive registers are written in a single burst. The ALS and
+        /// PS control registers are written last, so that measurements start
+        /// with the complete configuration. On error the staged values are
+        /// kept and the commit can be retried.
+        pub $($async)* fn commit(&mut self) -> Result<(), $crate::Error<E>> {
+            use $crate::staging::{CONTROL_END, SIZE};
+            let staged = match self.staged {
+                Some(staged) => staged,
+                None => return Ok(()),
+            };
+            let mut buffer = [0; SIZE + 1];
+            for &(from, to) in &[(CONTROL_END, SIZE), (0, CONTROL_END)] {
+                let mut index = from;
+                while let Some((start, len)) = staged.next_run(index, to) {
+                    let count = staged.burst(start, len, &mut buffer);
+                    #[cfg(feature = "log")]
+                    log::trace!("write 0x{:02x}: {:02x?}", self.address, &buffer[..count]);
+                    let result = self.i2c
+                        .write(self.address, &buffer[..count])
+                        $($await)*;
+                    self.check_bus(result)?;
+                    index = start + len;
+                }
+            }
+            self.staged = None;
+            Ok(())
+        }
+
+        pub(crate) $($async)* fn write_register(&mut self, register: u8, value: u8) -> Result<(), $crate::Error<E>> {
+            if let Some(staged) = self.staged.as_mut() {
+                if staged.set(register, value) {
+                    return Ok(());
+                }
+            }
+            let data = [register, value];
+            #[cfg(feature = "log")]
+            log::trace!("write 0x{:02x}: [0x{:02x}] = 0x{:02x}", self.address, register, value);
+            let result = self.i2c
+                .write(self.address, &data)
+                $($await)*;
+            self.check_bus(result)
+        }
+
+        pub(crate) $($async)* fn write_u16(&mut self, register_low: u8, value: u16) -> Result<(), $crate::Error<E>> {
+            self.write_register(register_low, (value & 0xff) as u8)$($await)*?;
+            self.write_register(register_low + 1, (value >> 8) as u8)$($await)*
+        }
+    };
+}
+
+/// Identification and measurement data getters.
+macro_rules! impl_data_methods {
+    ([$($async:tt)*] [$($await:tt)*]) => {
+        /// Read the manufacturer ID
+        pub $($async)* fn get_manufacturer_id(&mut self) -> Result<u8, $crate::Error<E>> {
+            self.read_register($crate::device_impl::Register::MANUFAC_ID)$($await)*
+        }
+
+        /// Read the device part number and revision id
+        pub $($async)* fn get_part_id(&mut self) -> Result<u8, $crate::Error<E>> {
+            self.read_register($crate::device_impl::Register::PART_ID)$($await)*
+        }
+
+        /// Get ALS Data in (als_ch0, als_ch1) format
+        pub $($async)* fn get_als_raw_data(&mut self) -> Result<(u16, u16), $crate::Error<E>> {
+            // CH1 has to be read first
+            let ch1 = self.read_u16($crate::device_impl::Register::ALS_DATA_CH1_0)$($await)*?;
+            let ch0 = self.read_u16($crate::device_impl::Register::ALS_DATA_CH0_0)$($await)*?;
+            Ok((ch0, ch1))
+        }
+
+        /// Read the ALS channels
+        pub $($async)* fn get_als_data(&mut self) -> Result<$crate::AlsData, $crate::Error<E>> {
+            let (ch0, ch1) = self.get_als_raw_data()$($await)*?;
+            Ok($crate::AlsData { ch0, ch1 })
+        }
+
+        /// Read both ALS channels in a single burst, so that they belong to
+        /// the same measurement.
+        pub $($async)* fn get_als_data_coherent(&mut self) -> Result<$crate::AlsData, $crate::Error<E>> {
+            let mut data = [0; 4];
+            self.read_registers($crate::device_impl::Register::ALS_DATA_CH1_0, &mut data)$($await)*?;
+            Ok($crate::AlsData {
+                ch0: u16::from_le_bytes([data[2], data[3]]),
+                ch1: u16::from_le_bytes([data[0], data[1]]),
+            })
+        }
+
+        /// Read the IR ratio CH1 / (CH0 + CH1) of the latest ALS data.
+        ///
+        /// Returns 1.0 if both channels are 0.
+        #[cfg(feature = "float")]
+        pub $($async)* fn get_ir_ratio(&mut self) -> Result<f32, $crate::Error<E>> {
+            Ok(self.get_als_data_coherent()$($await)*?.ratio())
+        }
+
+        /// Estimate the light source from the IR ratio of the latest ALS
+        /// data.
+        ///
+        /// Returns `None` if both channels are 0.
+        pub $($async)* fn get_light_source(&mut self) -> Result<Option<$crate::LightSource>, $crate::Error<E>> {
+            Ok(self.get_als_data_coherent()$($await)*?.light_source())
+        }
+
+        /// Read the ALS channels, checking the status first.
+        ///
+        /// Returns `Error::StaleData` if no new data is available since the
+        /// status was last read or if the data is flagged as invalid. Note
+        /// that reading the status clears the data-ready flags.
+        pub $($async)* fn get_als_data_checked(&mut self) -> Result<$crate::AlsData, $crate::Error<E>> {
+            let status = self.get_status()$($await)*?;
+            if !status.als_data_status || !status.als_data_valid {
+                return Err($crate::Error::StaleData);
+            }
+            self.get_als_data()$($await)*
+        }
+
+        /// Return calculated lux
+        ///
+        /// Returns `Error::AlsSaturated` if an ALS channel is at full scale,
+        /// except with `LuxAlgorithm::Pimoroni`.
+        #[cfg(feature = "float")]
+        pub $($async)* fn get_lux(&mut self) -> Result<f32, $crate::Error<E>> {
+            if self.cache_dirty {
+                self.resync_internal_driver_state()$($await)*?;
+            }
+            let (als_data_ch0, als_data_ch1) = self.lux_input()$($await)*?;
+            let lux = self.active_lux_coefficients().lux(
+                als_data_ch0,
+                als_data_ch1,
+                self.als_gain,
+                self.als_int,
+            );
+            let lux = self.corrected_lux(lux);
+            #[cfg(feature = "stats")]
+            self.lux_stats.update(lux);
+            Ok(lux)
+        }
+
+        /// Return calculated lux in millilux, using integer arithmetic only.
+        ///
+        /// This avoids pulling in software floating-point routines on
+        /// targets without an FPU. Returns `Error::AlsSaturated` as
+        /// [`get_lux()`](#method.get_lux) does.
+        pub $($async)* fn get_lux_millis(&mut self) -> Result<u32, $crate::Error<E>> {
+            if self.cache_dirty {
+                self.resync_internal_driver_state()$($await)*?;
+            }
+            let (als_data_ch0, als_data_ch1) = self.lux_input()$($await)*?;
+            let millilux = self.active_lux_coefficients().millilux(
+                als_data_ch0,
+                als_data_ch1,
+                self.als_gain,
+                self.als_int,
+            );
+            #[cfg(feature = "float")]
+            let millilux = self.corrected_millilux(millilux);
+            Ok(millilux)
+        }
+
+        /// Return calculated lux as a fixed-point number, using integer
+        /// arithmetic only.
+        ///
+        /// Values above the I16F16 range saturate at `I16F16::MAX`. Returns
+        /// `Error::AlsSaturated` as [`get_lux()`](#method.get_lux) does.
+        #[cfg(feature = "fixed")]
+        pub $($async)* fn get_lux_fixed(&mut self) -> Result<fixed::types::I16F16, $crate::Error<E>> {
+            if self.cache_dirty {
+                self.resync_internal_driver_state()$($await)*?;
+            }
+            let (als_data_ch0, als_data_ch1) = self.lux_input()$($await)*?;
+            let lux = self.active_lux_coefficients().lux_fixed(
+                als_data_ch0,
+                als_data_ch1,
+                self.als_gain,
+                self.als_int,
+            );
+            #[cfg(feature = "float")]
+            let lux = self.corrected_lux_fixed(lux);
+            Ok(lux)
+        }
+
+        /// Set the factor applied to the lux computed by
+        /// [`get_lux()`](#method.get_lux) and
+        /// [`get_measurement()`](#method.get_measurement), e.g. to
+        /// compensate the attenuation of tinted cover glass or of a diffuser.
+        ///
+        /// For a window transmitting 40% of the light, use `1.0 / 0.4`. The
+        /// default is `1.0`. The integer variants such as
+        /// [`get_lux_millis()`](#method.get_lux_millis) apply it with a
+        /// 16.16 fixed-point copy of the factor, and
+        /// [`lux_to_raw()`](#method.lux_to_raw) divides by it, so that the
+        /// ALS thresholds match the corrected lux.
+        ///
+        /// Returns `Error::InvalidInputData` unless `factor` is at least
+        /// 1/65536 and below 32768, the range of the fixed-point copy. In
+        /// particular zero, negative, NaN and infinite factors are rejected.
+        #[cfg(feature = "float")]
+        pub fn set_window_factor(&mut self, factor: f32) -> Result<(), $crate::Error<E>> {
+            self.window_factor_q16 = $crate::calc::window_factor_q16(factor)
+                .ok_or($crate::Error::InvalidInputData)?;
+            self.window_factor = factor;
+            Ok(())
+        }
+
+        /// Set the lux added to the computed lux after the window factor,
+        /// e.g. from a two-point calibration against a reference meter.
+        ///
+        /// The result is limited to 0. The default is `0.0`. As the window
+        /// factor, it is also applied by the integer variants and
+        /// subtracted by [`lux_to_raw()`](#method.lux_to_raw).
+        ///
+        /// Returns `Error::InvalidInputData` unless `offset` is above -32768
+        /// and below 32768, the range of its fixed-point copy. In particular
+        /// NaN and infinite offsets are rejected.
+        #[cfg(feature = "float")]
+        pub fn set_lux_offset(&mut self, offset: f32) -> Result<(), $crate::Error<E>> {
+            self.lux_offset_q16 =
+                $crate::calc::lux_offset_q16(offset).ok_or($crate::Error::InvalidInputData)?;
+            self.lux_offset = offset;
+            Ok(())
+        }
+
+        /// Return PS Data in format (value, saturated)
+        ///
+        /// See [`get_ps()`](#method.get_ps) for a typed result that also
+        /// tells whether the data is new.
+        pub $($async)* fn get_ps_data(&mut self) -> Result<(u16, bool), $crate::Error<E>> {
+            let ps0 = self.read_register($crate::device_impl::Register::PS_DATA_0)$($await)*?;
+            let ps1 = self.read_register($crate::device_impl::Register::PS_DATA_1)$($await)*?;
+            Ok($crate::device_impl::decode_ps_data(ps0, ps1))
+        }
+
+        /// Read the status and the PS data in a single burst.
+        ///
+        /// Note that reading the status clears the ALS and PS data-ready
+        /// flags, as with [`get_status()`](#method.get_status).
+        pub $($async)* fn get_ps(&mut self) -> Result<$crate::PsData, $crate::Error<E>> {
+            let mut data = [0; 3];
+            self.read_registers($crate::device_impl::Register::ALS_PS_STATUS, &mut data)$($await)*?;
+            let (counts, saturated) = $crate::device_impl::decode_ps_data(data[1], data[2]);
+            let status = $crate::Status::from_registers(data[0], data[2]);
+            self.record_status(status);
+            Ok($crate::PsData {
+                counts,
+                saturated,
+                new_data: status.ps_data_status,
+            })
+        }
+
+        /// Read the ALS channels, the status and the PS data in a single
+        /// burst, so that all values belong to the same measurement cycle.
+        ///
+        /// Note that reading the status clears the data-ready flags, as
+        /// with [`get_status()`](#method.get_status).
+        pub $($async)* fn read_all(&mut self) -> Result<$crate::AllData, $crate::Error<E>> {
+            let mut data = [0; 7];
+            self.read_registers($crate::device_impl::Register::ALS_DATA_CH1_0, &mut data)$($await)*?;
+            let (ps, ps_saturated) = $crate::device_impl::decode_ps_data(data[5], data[6]);
+            let status = $crate::Status::from_registers(data[4], data[6]);
+            self.record_status(status);
+            Ok($crate::AllData {
+                als_ch0: u16::from_le_bytes([data[2], data[3]]),
+                als_ch1: u16::from_le_bytes([data[0], data[1]]),
+                status,
+                ps,
+                ps_saturated,
+            })
+        }
+
+        /// Read a coherent snapshot of the ALS and PS data and compute lux.
+        ///
+        /// The data registers are read in one burst (see
+        /// [`read_all()`](#method.read_all)). Lux is computed with the gain
+        /// reported in the status register for this data, falling back to
+        /// the configured gain, and with the configured integration time.
+        #[cfg(feature = "float")]
+        pub $($async)* fn get_measurement(&mut self) -> Result<$crate::Measurement, $crate::Error<E>> {
+            if self.cache_dirty {
+                self.resync_internal_driver_state()$($await)*?;
+            }
+            let data = self.read_all()$($await)*?;
+            let als_gain_used = match self.lux_algorithm {
+                $crate::calc::LuxAlgorithm::Datasheet => {
+                    $crate::AlsGain::from_register(data.status.als_gain << 2).unwrap_or(self.als_gain)
+                }
+                $crate::calc::LuxAlgorithm::Pimoroni => self.als_gain,
+            };
+            let als_saturated = data.als_ch0 == u16::MAX || data.als_ch1 == u16::MAX;
+            if als_saturated {
+                self.diagnostics.als_saturations = self.diagnostics.als_saturations.wrapping_add(1);
+            }
+            let (lux_ch0, lux_ch1) = self.subtract_dark_counts(data.als_ch0, data.als_ch1);
+            Ok($crate::Measurement {
+                lux: self.corrected_lux(self.active_lux_coefficients().lux(
+                    lux_ch0,
+                    lux_ch1,
+                    als_gain_used,
+                    self.als_int,
+                )),
+                als_ch0: data.als_ch0,
+                als_ch1: data.als_ch1,
+                als_saturated,
+                ps: data.ps,
+                ps_saturated: data.ps_saturated,
+                als_gain_used,
+                als_int_used: self.als_int,
+            })
+        }
+
+        /// ALS channels to compute lux from, according to the lux algorithm
+        $($async)* fn lux_input(&mut self) -> Result<(u16, u16), $crate::Error<E>> {
+            match self.lux_algorithm {
+                $crate::calc::LuxAlgorithm::Datasheet => {
+                    let data = self.get_als_data()$($await)*?;
+                    if data.is_saturated() {
+                        self.diagnostics.als_saturations = self.diagnostics.als_saturations.wrapping_add(1);
+                        return Err($crate::Error::AlsSaturated);
+                    }
+                    Ok(self.subtract_dark_counts(data.ch0, data.ch1))
+                }
+                $crate::calc::LuxAlgorithm::Pimoroni => {
+                    let data = self.read_all()$($await)*?;
+                    if data.status.als_data_status || data.status.als_interrupt_status {
+                        self.held_als = (data.als_ch0, data.als_ch1);
+                    }
+                    Ok(self.subtract_dark_counts(self.held_als.0, self.held_als.1))
+                }
+            }
+        }
+
+        /// Read the ALS channels and the PS data at once
+        pub $($async)* fn get_raw_measurement(&mut self) -> Result<$crate::RawMeasurement, $crate::Error<E>> {
+            let (als_ch0, als_ch1) = self.get_als_raw_data()$($await)*?;
+            let (ps, ps_saturated) = self.get_ps_data()$($await)*?;
+            Ok($crate::RawMeasurement {
+                als_ch0,
+                als_ch1,
+                ps,
+                ps_saturated,
+            })
+        }
+    };
+}
+
+/// Staging mode control. Does not access the bus.
+macro_rules! impl_staging_methods {
+    () => {
+        /// Enter staging mode.
+        ///
+        /// Until [`commit()`](#method.commit) is called, the setters only
+        /// update an in-memory image of the configuration registers and
+        /// reading a staged register returns the staged value. Cached
+        /// settings such as the ALS gain are updated right away.
+        pub fn begin_staging(&mut self) {
+            if self.staged.is_none() {
+                self.staged = Some($crate::staging::RegisterImage::new());
+            }
+        }
+
+        /// Leave staging mode without writing the staged values.
+        ///
+        /// The cached settings are marked as possibly out of sync with the
+        /// device.
+        pub fn discard_staged(&mut self) {
+            if self.staged.take().is_some() {
+                self.cache_dirty = true;
+            }
+        }
+
+        /// Whether the driver is in staging mode
+        pub fn is_staging(&self) -> bool {
+            self.staged.is_some()
+        }
+    };
+}
+
+/// Power management. Requires a bus able to do both writes and write-read
+/// transactions.
+///
+/// The third macro argument is the delay trait, as for `impl_wait_methods`.
+macro_rules! impl_power_methods {
+    ([$($async:tt)*] [$($await:tt)*] [$($delay:tt)*]) => {
+        /// Perform a software reset and wait until the device is up again.
+        ///
+        /// Staged values are discarded, the internal driver state is reset to
+        /// the power-up values and ALS_CONTR is read back to check that the
+        /// device responds. Returns `Error::Timeout` if the reset has not
+        /// completed after the startup time.
+        pub $($async)* fn sw_reset<D: $($delay)*>(&mut self, delay: &mut D) -> Result<(), $crate::Error<E>> {
+            use $crate::device_impl::{BitFlags, Register, STARTUP_TIME_MS};
+            self.staged = None;
+            self.set_als_contr($crate::AlsGain::default(), true, false)$($await)*?;
+            delay.delay_ms(STARTUP_TIME_MS)$($await)*;
+            self.reset_internal_driver_state();
+            if self.read_register(Register::ALS_CONTR)$($await)*? & BitFlags::R80_SW_RESET != 0 {
+                return Err($crate::Error::Timeout);
+            }
+            Ok(())
+        }
+
+        /// Put the ALS and the PS in standby, keeping all other settings.
+        ///
+        /// Which of them were active is remembered for
+        /// [`wake()`](#method.wake).
+        pub $($async)* fn standby(&mut self) -> Result<(), $crate::Error<E>> {
+            use $crate::device_impl::Register;
+            let als = self.read_register(Register::ALS_CONTR)$($await)*?;
+            let ps = self.read_register(Register::PS_CONTR)$($await)*?;
+            if als & 0x01 != 0 || ps & 0x03 != 0 {
+                self.active_before_standby = Some((als & 0x01 != 0, ps & 0x03 != 0));
+            }
+            self.write_register(Register::ALS_CONTR, als & !0x03)$($await)*?;
+            self.write_register(Register::PS_CONTR, ps & !0x03)$($await)*
+        }
+
+        /// Activate again what was active before [`standby()`](#method.standby),
+        /// or both the ALS and the PS if the device was not put in standby
+        /// through this driver.
+        ///
+        /// The other settings are kept. Note that no wakeup time is waited for.
+        pub $($async)* fn wake(&mut self) -> Result<(), $crate::Error<E>> {
+            use $crate::device_impl::Register;
+            let (als_active, ps_active) = self.active_before_standby.take().unwrap_or((true, true));
+            let als = self.read_register(Register::ALS_CONTR)$($await)*? & !0x02;
+            let ps = self.read_register(Register::PS_CONTR)$($await)*?;
+            if als_active {
+                self.write_register(Register::ALS_CONTR, als | 0x01)$($await)*?;
+            }
+            if ps_active {
+                self.write_register(Register::PS_CONTR, ps | 0x03)$($await)*?;
+            }
+            Ok(())
+        }
+
+        /// Put the ALS and the PS in standby, which also stops the LED
+        /// pulses, then destroy the driver instance and return the I²C bus.
+        ///
+        /// Staged values are discarded. In case of error the I²C bus is
+        /// returned inside the error, so the shutdown can be retried with a
+        /// new driver instance.
+        pub $($async)* fn destroy_and_shutdown(mut self) -> Result<I2C, $crate::ModeChangeError<E, I2C>> {
+            self.staged = None;
+            match self.standby()$($await)* {
+                Ok(()) => Ok(self.i2c),
+                Err($crate::Error::I2C(e)) => Err($crate::ModeChangeError::I2C(e, self.i2c)),
+                Err(_) => Err($crate::ModeChangeError::InvalidInputData(self.i2c)),
+            }
+        }
+
+        /// Whether the ALS or the PS is active
+        pub $($async)* fn is_active(&mut self) -> Result<bool, $crate::Error<E>> {
+            use $crate::device_impl::Register;
+            let als = self.read_register(Register::ALS_CONTR)$($await)*?;
+            let ps = self.read_register(Register::PS_CONTR)$($await)*?;
+            Ok(als & 0x01 != 0 || ps & 0x03 != 0)
+        }
+    };
+}
+
+/// Cached device state. Does not access the bus.
+macro_rules! impl_cached_state_methods {
+    () => {
+        /// Last status read through this driver, without accessing the bus
+        /// and so without clearing any flag.
+        ///
+        /// Returns `None` if no status was read yet.
+        pub fn peek_status(&self) -> Option<$crate::Status> {
+            self.last_status
+        }
+
+        /// Interrupt, saturation and bus error counts since the creation of
+        /// the driver or the last [`reset_diagnostics()`](#method.reset_diagnostics)
+        pub fn diagnostics(&self) -> $crate::DiagnosticCounters {
+            self.diagnostics
+        }
+
+        /// Reset the counts returned by [`diagnostics()`](#method.diagnostics)
+        pub fn reset_diagnostics(&mut self) {
+            self.diagnostics = $crate::DiagnosticCounters::default();
+        }
+
+        /// Cache a status read from the device and count its events
+        pub(crate) fn record_status(&mut self, status: $crate::Status) {
+            let counters = &mut self.diagnostics;
+            if status.als_interrupt_status {
+                counters.als_interrupts = counters.als_interrupts.wrapping_add(1);
+            }
+            if status.ps_interrupt_status {
+                counters.ps_interrupts = counters.ps_interrupts.wrapping_add(1);
+            }
+            if status.ps_data_status && status.ps_saturated {
+                counters.ps_saturations = counters.ps_saturations.wrapping_add(1);
+            }
+            self.last_status = Some(status);
+        }
+
+        /// Convert a bus result, counting the errors
+        pub(crate) fn check_bus<T, BusError>(
+            &mut self,
+            result: Result<T, BusError>,
+        ) -> Result<T, $crate::Error<BusError>> {
+            result.map_err(|e| {
+                self.diagnostics.i2c_errors = self.diagnostics.i2c_errors.wrapping_add(1);
+                $crate::Error::I2C(e)
+            })
+        }
+    };
+}
+
+/// Lux computation settings. Does not access the bus.
+macro_rules! impl_lux_settings {
+    () => {
+        /// Set the coefficients of the lux formula, e.g. coefficients tuned
+        /// for the optics in front of the sensor.
+        ///
+        /// The coefficients of the datasheet are used by default.
+        pub fn set_lux_coefficients(&mut self, coefficients: $crate::calc::LuxCoefficients) {
+            self.lux_coefficients = coefficients;
+        }
+
+        /// Coefficients of the lux formula set with
+        /// [`set_lux_coefficients()`](#method.set_lux_coefficients)
+        pub fn lux_coefficients(&self) -> &$crate::calc::LuxCoefficients {
+            &self.lux_coefficients
+        }
+
+        /// Select how lux is computed, e.g. to match the readings of
+        /// existing deployments of the Pimoroni Python library.
+        ///
+        /// The default is `LuxAlgorithm::Datasheet`.
+        pub fn set_lux_algorithm(&mut self, algorithm: $crate::calc::LuxAlgorithm) {
+            self.lux_algorithm = algorithm;
+            self.held_als = (0, 0);
+        }
+
+        /// Lux calculation mode in use
+        pub fn lux_algorithm(&self) -> $crate::calc::LuxAlgorithm {
+            self.lux_algorithm
+        }
+
+        /// Set the ALS dark counts `(ch0, ch1)` subtracted from the channels
+        /// before lux is computed.
+        ///
+        /// They are the counts read with the sensor covered, at the gain and
+        /// integration time in use. See also
+        /// [`measure_dark_counts()`](#method.measure_dark_counts). The
+        /// default is `(0, 0)`. The raw data methods are not affected.
+        pub fn set_dark_counts(&mut self, ch0: u16, ch1: u16) {
+            self.dark_counts = (ch0, ch1);
+        }
+
+        /// ALS dark counts `(ch0, ch1)` subtracted before lux is computed
+        pub fn dark_counts(&self) -> (u16, u16) {
+            self.dark_counts
+        }
+
+        /// Subtract the dark counts from the ALS channels, saturating at 0
+        fn subtract_dark_counts(&self, ch0: u16, ch1: u16) -> (u16, u16) {
+            (
+                ch0.saturating_sub(self.dark_counts.0),
+                ch1.saturating_sub(self.dark_counts.1),
+            )
+        }
+
+        /// Factor applied to the computed lux
+        #[cfg(feature = "float")]
+        pub fn window_factor(&self) -> f32 {
+            self.window_factor
+        }
+
+        /// Lux added to the computed lux
+        #[cfg(feature = "float")]
+        pub fn lux_offset(&self) -> f32 {
+            self.lux_offset
+        }
+
+        /// Apply the window factor and the lux offset
+        #[cfg(feature = "float")]
+        fn corrected_lux(&self, lux: f32) -> f32 {
+            (lux * self.window_factor + self.lux_offset).max(0.0)
+        }
+
+        /// Apply the window factor and the lux offset to millilux with
+        /// integer arithmetic only
+        #[cfg(feature = "float")]
+        fn corrected_millilux(&self, millilux: u32) -> u32 {
+            let millilux = (i64::from(millilux) * i64::from(self.window_factor_q16)
+                + i64::from(self.lux_offset_q16) * 1000)
+                >> 16;
+            millilux.max(0).min(i64::from(u32::MAX)) as u32
+        }
+
+        /// Apply the window factor and the lux offset to fixed-point lux
+        /// with integer arithmetic only
+        #[cfg(all(feature = "float", feature = "fixed"))]
+        fn corrected_lux_fixed(&self, lux: fixed::types::I16F16) -> fixed::types::I16F16 {
+            let bits = ((i64::from(lux.to_bits()) * i64::from(self.window_factor_q16)) >> 16)
+                + i64::from(self.lux_offset_q16);
+            fixed::types::I16F16::from_bits(bits.max(0).min(i64::from(i32::MAX)) as i32)
+        }
+
+        /// Convert a lux value into the raw ALS CH0 count for the current gain
+        /// and integration time.
+        ///
+        /// The ALS thresholds are compared against CH0 only, so this assumes
+        /// light with negligible infrared content (CH1 ≈ 0) and uses the CH0
+        /// coefficient of the lowest IR ratio of the active lux coefficients.
+        /// The lux offset is subtracted from `lux` and the result divided by
+        /// the window factor first, as `lux` is in the units returned by
+        /// [`get_lux()`](#method.get_lux). The result saturates at the bounds
+        /// of the 16-bit register.
+        #[cfg(feature = "float")]
+        pub fn lux_to_raw(&self, lux: f32) -> u16 {
+            let lux = (lux - self.lux_offset) / self.window_factor;
+            // lux per CH0 count at 1x gain and 100 ms without infrared
+            let ch0_factor = self.active_lux_coefficients().ch0[0] as f32 / 10000.0;
+            let raw = lux * self.als_gain.lux_compute_value() * self.als_int.lux_compute_value()
+                / ch0_factor;
+            if raw <= 0.0 {
+                0
+            } else if raw >= 65535.0 {
+                0xFFFF
+            } else {
+                raw as u16
+            }
+        }
+
+        /// Lux at which CH0 reaches full scale with the current gain and
+        /// integration time, i.e. the largest lux that can be measured.
+        ///
+        /// As for [`lux_to_raw()`](#method.lux_to_raw), light with negligible
+        /// infrared content is assumed. The window factor and the lux offset
+        /// are applied.
+        #[cfg(feature = "float")]
+        pub fn full_scale_lux(&self) -> f32 {
+            let lux = self
+                .active_lux_coefficients()
+                .lux(u16::MAX, 0, self.als_gain, self.als_int);
+            self.corrected_lux(lux)
+        }
+
+        /// Statistics of the lux values returned by
+        /// [`get_lux()`](#method.get_lux) since the last reset
+        #[cfg(feature = "stats")]
+        pub fn lux_stats(&self) -> &$crate::stats::RunningStats {
+            &self.lux_stats
+        }
+
+        /// Reset the lux statistics
+        #[cfg(feature = "stats")]
+        pub fn reset_lux_stats(&mut self) {
+            self.lux_stats.reset();
+        }
+
+        /// Coefficients used by the selected lux algorithm
+        fn active_lux_coefficients(&self) -> &$crate::calc::LuxCoefficients {
+            match self.lux_algorithm {
+                $crate::calc::LuxAlgorithm::Datasheet => &self.lux_coefficients,
+                $crate::calc::LuxAlgorithm::Pimoroni => &$crate::calc::LuxCoefficients::DATASHEET,
+            }
+        }
+    };
+}
+
+/// Waiting for new data. Requires a bus able to do write-read transactions.
+///
+/// The third macro argument is the delay trait, e.g. `DelayMs<u32>` for the
+/// blocking driver.
+macro_rules! impl_wait_methods {
+    ([$($async:tt)*] [$($await:tt)*] [$($delay:tt)*]) => {
+        /// Wait until new valid ALS data measured with the current gain is
+        /// available.
+        ///
+        /// The status is polled with `delay` ten times per ALS measurement
+        /// period. Returns `Error::Timeout` if no such data arrives within
+        /// three measurement periods.
+        pub $($async)* fn wait_for_als_data<D: $($delay)*>(
+            &mut self,
+            delay: &mut D,
+        ) -> Result<(), $crate::Error<E>> {
+            let period = self.als_meas_rate.milliseconds();
+            let gain = self.als_gain.value() >> 2;
+            self.poll_status(delay, period, 3 * period, |status| {
+                status.als_data_status && status.als_data_valid && status.als_gain == gain
+            })$($await)*
+        }
+
+        /// Wait for new PS data and return it.
+        ///
+        /// The status is polled with `delay` ten times per PS measurement
+        /// period. Returns `Error::Timeout` if no new data arrives within
+        /// `timeout_ms` milliseconds.
+        ///
+        /// Reading the status also clears a pending PS interrupt, so the
+        /// interrupt flag is cleared when this returns.
+        pub $($async)* fn wait_for_ps_data<D: $($delay)*>(
+            &mut self,
+            delay: &mut D,
+            timeout_ms: u32,
+        ) -> Result<$crate::PsData, $crate::Error<E>> {
+            let period = self.ps_meas_rate.milliseconds();
+            self.poll_status(delay, period, timeout_ms, |status| status.ps_data_status)$($await)*?;
+            let (counts, saturated) = self.get_ps_data()$($await)*?;
+            Ok($crate::PsData {
+                counts,
+                saturated,
+                new_data: true,
+            })
+        }
+
+        /// Take `n` new PS samples and return their mean without the lowest
+        /// and the highest one.
+        ///
+        /// Each sample is waited for as in
+        /// [`wait_for_ps_data()`](#method.wait_for_ps_data), with a timeout of
+        /// three measurement periods. With fewer than 3 samples the plain mean
+        /// is returned. Returns `Error::InvalidInputData` if `n` is 0.
+        pub $($async)* fn get_ps_data_robust<D: $($delay)*>(
+            &mut self,
+            n: u8,
+            delay: &mut D,
+        ) -> Result<u16, $crate::Error<E>> {
+            if n == 0 {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let timeout_ms = 3 * self.ps_meas_rate.milliseconds();
+            let (mut sum, mut min, mut max) = (0_u32, u16::MAX, 0);
+            for _ in 0..n {
+                let counts = self.wait_for_ps_data(delay, timeout_ms)$($await)*?.counts;
+                sum += u32::from(counts);
+                min = min.min(counts);
+                max = max.max(counts);
+            }
+            let mut count = u32::from(n);
+            if count >= 3 {
+                sum -= u32::from(min) + u32::from(max);
+                count -= 2;
+            }
+            Ok((sum / count) as u16)
+        }
+
+        /// Poll the status ten times per `period_ms` until `ready` or timeout.
+        pub(crate) $($async)* fn poll_status<D, F>(
+            &mut self,
+            delay: &mut D,
+            period_ms: u32,
+            timeout_ms: u32,
+            mut ready: F,
+        ) -> Result<(), $crate::Error<E>>
+        where
+            D: $($delay)*,
+            F: FnMut(&$crate::Status) -> bool,
+        {
+            let poll_ms = (period_ms / 10).max(1);
+            let mut waited = 0;
+            loop {
+                if ready(&self.get_status()$($await)*?) {
+                    return Ok(());
+                }
+                if waited >= timeout_ms {
+                    return Err($crate::Error::Timeout);
+                }
+                let step = poll_ms.min(timeout_ms - waited);
+                delay.delay_ms(step)$($await)*;
+                waited += step;
+            }
+        }
+    };
+}
+
+/// Threshold and interrupt presets. Requires a bus able to do both writes
+/// and write-read transactions.
+macro_rules! impl_threshold_methods {
+    ([$($async:tt)*] [$($await:tt)*]) => {
+        /// Configure the device to raise an interrupt when it gets darker than `lux`.
+        ///
+        /// Enables the ALS with the current gain and integration time at the
+        /// low-power 2000 ms repeat rate, programs the low threshold from `lux`
+        /// (see [`lux_to_raw()`](#method.lux_to_raw)) with the high threshold
+        /// disabled, and enables the ALS interrupt. The PS persist and interrupt
+        /// settings as well as the pin polarity are left untouched.
+        #[cfg(feature = "float")]
+        pub $($async)* fn wake_on_darkness(
+            &mut self,
+            lux: f32,
+            persist: $crate::AlsPersist,
+        ) -> Result<(), $crate::Error<E>> {
+            self.set_als_meas_rate(self.als_int, $crate::AlsMeasRate::_2000ms)$($await)*?;
+            let raw = self.lux_to_raw(lux);
+            self.set_als_low_limit_raw(raw)$($await)*?;
+            self.set_als_high_limit_raw(0xFFFF)$($await)*?;
+            self.enable_als_wake(persist)$($await)*
+        }
+
+        /// Configure the device to raise an interrupt when it gets brighter than `lux`.
+        ///
+        /// Same as [`wake_on_darkness()`](#method.wake_on_darkness) but programs
+        /// the high threshold from `lux` and disables the low threshold.
+        #[cfg(feature = "float")]
+        pub $($async)* fn wake_on_light(
+            &mut self,
+            lux: f32,
+            persist: $crate::AlsPersist,
+        ) -> Result<(), $crate::Error<E>> {
+            self.set_als_meas_rate(self.als_int, $crate::AlsMeasRate::_2000ms)$($await)*?;
+            let raw = self.lux_to_raw(lux);
+            self.set_als_low_limit_raw(0)$($await)*?;
+            self.set_als_high_limit_raw(raw)$($await)*?;
+            self.enable_als_wake(persist)$($await)*
+        }
+
+        #[cfg(feature = "float")]
+        $($async)* fn enable_als_wake(&mut self, persist: $crate::AlsPersist) -> Result<(), $crate::Error<E>> {
+            use $crate::device_impl::{BitFlags, Register};
+            self.update_register(
+                Register::INTERRUPT_PERSIST,
+                BitFlags::R9E_ALS_PERSIST,
+                persist.value(),
+            )$($await)*?;
+            self.update_register(
+                Register::INTERRUPT,
+                BitFlags::R8F_INTERRUPT_ALS,
+                BitFlags::R8F_INTERRUPT_ALS,
+            )$($await)*?;
+            self.set_als_contr(self.als_gain, false, true)$($await)*
+        }
+
+        /// Reprogram the ALS thresholds to ±`percent` % around the current reading.
+        ///
+        /// The thresholds are compared against CH0, so the window is centered
+        /// on the last CH0 value. Calling this after each ALS interrupt gives
+        /// change-driven interrupts. The window is at least ±1 count wide, so
+        /// that a zero reading does not trigger on every conversion. The limits
+        /// saturate at the register bounds.
+        pub $($async)* fn rearm_als_window(&mut self, percent: u8) -> Result<(), $crate::Error<E>> {
+            let (ch0, _) = self.get_als_raw_data()$($await)*?;
+            self.set_als_window(ch0, $crate::device_impl::als_window_delta(ch0, percent))$($await)*
+        }
+
+        /// Read the status and the data to clear the latched interrupt and
+        /// return the values that triggered it.
+        ///
+        /// The INT pin stays asserted until the status is read, so this must be
+        /// called after each interrupt. With `tracking`, the thresholds of the
+        /// sensors that triggered are then reprogrammed around the new values,
+        /// so that the next interrupt reports the next change. The interrupt
+        /// flags are in the returned `status`.
+        pub $($async)* fn acknowledge_and_rearm(
+            &mut self,
+            tracking: Option<$crate::Tracking>,
+        ) -> Result<$crate::AllData, $crate::Error<E>> {
+            let data = self.read_all()$($await)*?;
+            if let Some(tracking) = tracking {
+                if data.status.als_interrupt_status {
+                    let delta = $crate::device_impl::als_window_delta(data.als_ch0, tracking.als_percent);
+                    self.set_als_window(data.als_ch0, delta)$($await)*?;
+                }
+                if data.status.ps_interrupt_status {
+                    self.set_ps_window(data.ps, tracking.ps_delta)$($await)*?;
+                }
+            }
+            Ok(data)
+        }
+
+        /// Program the ALS thresholds for an interrupt while CH0 is inside or
+        /// outside of the band `low..=high`.
+        ///
+        /// The device raises an interrupt when CH0 is above the high or below
+        /// the low threshold, so [`WindowMode::Outside`](crate::WindowMode::Outside)
+        /// programs the band as is. [`WindowMode::Inside`](crate::WindowMode::Inside)
+        /// depends on the current CH0 value: inside of the band the thresholds
+        /// are inverted (low above high), so that every conversion raises an
+        /// interrupt, and outside of it a single threshold is set at the near
+        /// edge of the band, so that the interrupt is raised once CH0 enters
+        /// it. In this mode, call this again after each interrupt.
+        ///
+        /// Returns `Error::InvalidInputData` if `low` is greater than `high`.
+        pub $($async)* fn set_als_window_mode(
+            &mut self,
+            low: u16,
+            high: u16,
+            mode: $crate::WindowMode,
+        ) -> Result<(), $crate::Error<E>> {
+            if low > high {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let (ch0, _) = self.get_als_raw_data()$($await)*?;
+            let (low, high) = $crate::device_impl::window_limits(ch0, low, high, u16::MAX, mode);
+            self.set_als_low_limit_raw(low)$($await)*?;
+            self.set_als_high_limit_raw(high)$($await)*
+        }
+
+        /// Program the PS thresholds for an interrupt while the counts are
+        /// inside or outside of the band `low..=high`.
+        ///
+        /// See [`set_als_window_mode()`](#method.set_als_window_mode). Returns
+        /// `Error::InvalidInputData` if `low` is greater than `high` or if
+        /// `high` exceeds the 11-bit PS data range (2047).
+        pub $($async)* fn set_ps_window_mode(
+            &mut self,
+            low: u16,
+            high: u16,
+            mode: $crate::WindowMode,
+        ) -> Result<(), $crate::Error<E>> {
+            if high > 0x07FF || low > high {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let (counts, _) = self.get_ps_data()$($await)*?;
+            let (low, high) = $crate::device_impl::window_limits(counts, low, high, 0x07FF, mode);
+            self.set_ps_low_limit_raw(low)$($await)*?;
+            self.set_ps_high_limit_raw(high)$($await)*
+        }
+
+        /// Put the PS in fast mode: 10 ms measurement rate with `n_pulses` LED pulses.
+        ///
+        /// The pulse count is validated against the LED settings currently
+        /// programmed (see [`set_ps_led()`](#method.set_ps_led)): the average
+        /// LED current at the 10 ms rate may not exceed
+        /// [`PS_FAST_MODE_MAX_AVERAGE_CURRENT_UA`](crate::PS_FAST_MODE_MAX_AVERAGE_CURRENT_UA).
+        /// Use [`set_ps_fast_mode_with_limit()`](#method.set_ps_fast_mode_with_limit)
+        /// for another limit. Accepted pulse counts are 1 to 15.
+        ///
+        /// Returns `Error::InvalidInputData` if the combination is not allowed,
+        /// in which case nothing is written.
+        pub $($async)* fn set_ps_fast_mode(&mut self, n_pulses: u8) -> Result<(), $crate::Error<E>> {
+            self.set_ps_fast_mode_with_limit(n_pulses, $crate::PS_FAST_MODE_MAX_AVERAGE_CURRENT_UA)$($await)*
+        }
+
+        /// Put the PS in fast mode like [`set_ps_fast_mode()`](#method.set_ps_fast_mode),
+        /// checking the average LED current at the 10 ms rate against
+        /// `max_average_current_ua` instead of the driver default, as with
+        /// [`set_led_config()`](#method.set_led_config).
+        ///
+        /// Returns `Error::InvalidInputData` if the combination is not allowed,
+        /// in which case nothing is written.
+        pub $($async)* fn set_ps_fast_mode_with_limit(
+            &mut self,
+            n_pulses: u8,
+            max_average_current_ua: u32,
+        ) -> Result<(), $crate::Error<E>> {
+            if n_pulses == 0 || n_pulses > 15 {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let (pulse, duty_cycle, current) = self.get_ps_led()$($await)*?;
+            let config = $crate::LedConfig {
+                pulse,
+                duty_cycle,
+                current,
+                n_pulses,
+            };
+            if !config.is_within($crate::PsMeasRate::_10ms, max_average_current_ua) {
+                return Err($crate::Error::InvalidInputData);
+            }
+            self.write_register($crate::device_impl::Register::PS_N_PULSES, n_pulses)$($await)*?;
+            self.set_ps_meas_rate($crate::PsMeasRate::_10ms)$($await)*
+        }
+
+        /// Change the bits of `register` selected by `mask` to `value`
+        #[cfg(feature = "float")]
+        pub(crate) $($async)* fn update_register(
+            &mut self,
+            register: u8,
+            mask: u8,
+            value: u8,
+        ) -> Result<(), $crate::Error<E>> {
+            let current = self.read_register(register)$($await)*?;
+            self.write_register(register, (current & !mask) | (value & mask))$($await)*
+        }
+    };
+}
+
+/// Register snapshots. Requires a bus able to do both writes and
+/// write-read transactions.
+macro_rules! impl_state_methods {
+    ([$($async:tt)*] [$($await:tt)*]) => {
+        /// Read every configuration register into a snapshot that can be
+        /// written back with [`restore_state()`](#method.restore_state), for
+        /// example after power-cycling the device.
+        ///
+        /// The software reset bit of ALS_CONTR is not included.
+        pub $($async)* fn save_state(&mut self) -> Result<$crate::DeviceState, $crate::Error<E>> {
+            let mut registers = [0; $crate::DeviceState::SIZE];
+            for (value, &register) in registers.iter_mut().zip($crate::config::STATE_REGISTERS.iter()) {
+                *value = self.read_register(register)$($await)*?;
+            }
+            registers[0] &= !0x02;
+            Ok($crate::DeviceState { registers })
+        }
+
+        /// Write back a snapshot taken with [`save_state()`](#method.save_state).
+        ///
+        /// As in `apply_config()` of the blocking driver, the ALS and the PS
+        /// are put in standby first and activated last. Note that no wakeup
+        /// time is waited for.
+        pub $($async)* fn restore_state(&mut self, state: &$crate::DeviceState) -> Result<(), $crate::Error<E>> {
+            use $crate::device_impl::Register;
+            let registers = &state.registers;
+            self.write_register(Register::ALS_CONTR, registers[0] & !0x03)$($await)*?;
+            self.write_register(Register::PS_CONTR, registers[1] & !0x03)$($await)*?;
+            for (&register, &value) in $crate::config::STATE_REGISTERS.iter().zip(registers).skip(2) {
+                self.write_register(register, value)$($await)*?;
+            }
+            self.write_register(Register::ALS_CONTR, registers[0])$($await)*?;
+            self.write_register(Register::PS_CONTR, registers[1])$($await)*?;
+            if let Some(gain) = $crate::AlsGain::from_register(registers[0]) {
+                self.als_gain = gain;
+            }
+            self.ps_meas_rate = $crate::PsMeasRate::from_register(registers[4]);
+            self.als_int = $crate::AlsIntTime::from_register(registers[5]);
+            self.als_meas_rate = $crate::AlsMeasRate::from_register(registers[5]);
+            self.interrupt_polarity = $crate::InterruptPinPolarity::from_register(registers[6]);
+            Ok(())
+        }
+    };
+}
+
+/// Calibration procedures. Requires a bus able to do both writes and
+/// write-read transactions.
+///
+/// The third macro argument is the delay trait, as for `impl_wait_methods`.
+macro_rules! impl_calibration_methods {
+    ([$($async:tt)*] [$($await:tt)*] [$($delay:tt)*]) => {
+        /// Determine and program the PS thresholds for the current setup.
+        ///
+        /// The PS must already be active. This first averages the PS counts
+        /// without any target, then calls `prompt_target`, which should return
+        /// once the target has been placed in front of the sensor, and averages
+        /// the PS counts again. The near (high) and far (low) thresholds are
+        /// placed between both levels, keeping `options.margin_percent` of their
+        /// distance from each level.
+        ///
+        /// Returns `Error::InvalidInputData` if the options are out of range or
+        /// if the target does not increase the PS counts.
+        pub $($async)* fn tune_ps_thresholds<D, F>(
+            &mut self,
+            delay: &mut D,
+            options: $crate::PsTuningOptions,
+            mut prompt_target: F,
+        ) -> Result<$crate::PsTuning, $crate::Error<E>>
+        where
+            D: $($delay)*,
+            F: FnMut(),
+        {
+            if options.samples == 0 || options.margin_percent >= 50 {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let baseline = self.average_ps(delay, options.samples)$($await)*?;
+            prompt_target();
+            let target = self.average_ps(delay, options.samples)$($await)*?;
+            if target <= baseline {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let margin = u32::from(target - baseline) * u32::from(options.margin_percent) / 100;
+            let low = baseline + margin as u16;
+            let high = target - margin as u16;
+            self.set_ps_low_limit_raw(low)$($await)*?;
+            self.set_ps_high_limit_raw(high)$($await)*?;
+            Ok($crate::PsTuning {
+                baseline,
+                target,
+                low,
+                high,
+            })
+        }
+
+        /// Measure the optical crosstalk and compensate it with PS_OFFSET.
+        ///
+        /// The PS must already be active and no target may be in front of the
+        /// sensor. This clears PS_OFFSET, discards one sample and averages
+        /// `samples` PS samples. The mean counts are programmed as the offset,
+        /// limited to 1023, so that the PS reads close to zero without target.
+        ///
+        /// Returns the measured crosstalk in counts, or
+        /// `Error::InvalidInputData` if `samples` is 0. The previous offset is
+        /// restored if a sample cannot be read.
+        pub $($async)* fn calibrate_ps_offset<D: $($delay)*>(
+            &mut self,
+            delay: &mut D,
+            samples: u8,
+        ) -> Result<u16, $crate::Error<E>> {
+            if samples == 0 {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let previous = self.get_ps_offset()$($await)*?;
+            self.set_ps_offset(0)$($await)*?;
+            let crosstalk = match self.measure_crosstalk(delay, samples)$($await)* {
+                Ok(crosstalk) => crosstalk,
+                Err(e) => {
+                    self.set_ps_offset(previous)$($await)*?;
+                    return Err(e);
+                }
+            };
+            self.set_ps_offset(crosstalk.min($crate::calibration::PS_OFFSET_MAX))$($await)*?;
+            Ok(crosstalk)
+        }
+
+        /// Measure the ALS dark counts and subtract them from now on before
+        /// lux is computed.
+        ///
+        /// The ALS must already be active, with the gain and integration time
+        /// to use, and the sensor must be covered. This averages `samples` new
+        /// readings of each channel and sets them with
+        /// [`set_dark_counts()`](#method.set_dark_counts).
+        ///
+        /// Returns the dark counts `(ch0, ch1)`, or `Error::InvalidInputData`
+        /// if `samples` is 0.
+        pub $($async)* fn measure_dark_counts<D: $($delay)*>(
+            &mut self,
+            delay: &mut D,
+            samples: u8,
+        ) -> Result<(u16, u16), $crate::Error<E>> {
+            if samples == 0 {
+                return Err($crate::Error::InvalidInputData);
+            }
+            let (mut sum_ch0, mut sum_ch1) = (0_u32, 0_u32);
+            for _ in 0..samples {
+                self.wait_for_als_data(delay)$($await)*?;
+                let (ch0, ch1) = self.get_als_raw_data()$($await)*?;
+                sum_ch0 += u32::from(ch0);
+                sum_ch1 += u32::from(ch1);
+            }
+            let ch0 = (sum_ch0 / u32::from(samples)) as u16;
+            let ch1 = (sum_ch1 / u32::from(samples)) as u16;
+            self.set_dark_counts(ch0, ch1);
+            Ok((ch0, ch1))
+        }
+
+        /// Program the PS offset and set the lux scale, the lux offset and the
+        /// ALS dark counts of `data`.
+        ///
+        /// The lux scale and offset replace the window factor and lux offset
+        /// set before, see [`CalibrationData`](crate::CalibrationData).
+        ///
+        /// The distance model is not used by the driver, pass it to
+        /// `get_distance_mm()` of the blocking driver. Returns
+        /// `Error::InvalidInputData` if the `version` field of `data` is not
+        /// [`CALIBRATION_VERSION`](crate::CALIBRATION_VERSION) or if the PS
+        /// offset, the lux scale or the lux offset is out of range, see
+        /// [`set_window_factor()`](#method.set_window_factor) and
+        /// [`set_lux_offset()`](#method.set_lux_offset), before anything is
+        /// changed. Check the version of serialized data before deserializing
+        /// it, see
+        /// [`CalibrationData`](crate::CalibrationData).
+        #[cfg(feature = "float")]
+        pub $($async)* fn apply_calibration(
+            &mut self,
+            data: &$crate::CalibrationData,
+        ) -> Result<(), $crate::Error<E>> {
+            if data.version != $crate::CALIBRATION_VERSION
+                || $crate::calc::window_factor_q16(data.lux_scale).is_none()
+                || $crate::calc::lux_offset_q16(data.lux_offset).is_none()
+            {
+                return Err($crate::Error::InvalidInputData);
+            }
+            self.set_ps_offset(data.ps_offset)$($await)*?;
+            self.set_window_factor(data.lux_scale)?;
+            self.set_lux_offset(data.lux_offset)?;
+            self.set_dark_counts(data.als_dark_counts.0, data.als_dark_counts.1);
+            Ok(())
+        }
+
+        $($async)* fn measure_crosstalk<D: $($delay)*>(
+            &mut self,
+            delay: &mut D,
+            samples: u8,
+        ) -> Result<u16, $crate::Error<E>> {
+            // the first sample may have been measured with the previous offset
+            let timeout_ms = 3 * self.ps_meas_rate.milliseconds();
+            self.wait_for_ps_data(delay, timeout_ms)$($await)*?;
+            self.average_ps(delay, samples)$($await)*
+        }
+
+        $($async)* fn average_ps<D: $($delay)*>(
+            &mut self,
+            delay: &mut D,
+            samples: u8,
+        ) -> Result<u16, $crate::Error<E>> {
+            let mut sum = 0_u32;
+            for _ in 0..samples {
+                let timeout_ms = 3 * self.ps_meas_rate.milliseconds();
+                sum += u32::from(self.wait_for_ps_data(delay, timeout_ms)$($await)*?.counts);
+            }
+            Ok((sum / u32::from(samples)) as u16)
+        }
+    };
+}
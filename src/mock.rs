@@ -0,0 +1,320 @@
+//! Test doubles for the I²C bus and delay
+use crate::hal::blocking::i2c;
+use core::cell::{Cell, RefCell};
+
+/// I²C bus backed by a 256-byte register map
+pub struct I2cMock {
+    pub registers: [u8; 256],
+    /// Whether a software reset completes by the time ALS_CONTR is read
+    /// back, otherwise the SW reset bit stays set
+    pub reset_completes: bool,
+    /// Whether reading the status clears the new data flags like the
+    /// device does, otherwise they stay set
+    pub status_clears_on_read: bool,
+    /// Register whose writes are ignored, e.g. to make a configuration
+    /// read back differently than written
+    pub read_only: Option<u8>,
+    pointer: usize,
+}
+
+impl I2cMock {
+    pub fn new() -> Self {
+        I2cMock {
+            registers: [0; 256],
+            reset_completes: false,
+            status_clears_on_read: false,
+            read_only: None,
+            pointer: 0,
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        let register = bytes[0] as usize;
+        self.pointer = register;
+        for (i, byte) in bytes[1..].iter().enumerate() {
+            if self.read_only != Some((register + i) as u8) {
+                self.registers[register + i] = *byte;
+            }
+        }
+    }
+
+    fn read_bytes(&mut self, buffer: &mut [u8]) {
+        let register = self.pointer;
+        if self.reset_completes && (register..register + buffer.len()).contains(&0x80) {
+            self.registers[0x80] &= !0x02;
+        }
+        buffer.copy_from_slice(&self.registers[register..register + buffer.len()]);
+        if self.status_clears_on_read && (register..register + buffer.len()).contains(&0x8C) {
+            self.registers[0x8C] &= !0x05;
+        }
+    }
+}
+
+mod traits {
+    use super::{DelayMock, I2cMock, InputPinMock};
+    use crate::hal::blocking::{delay, i2c};
+    use crate::hal::digital::v2::InputPin;
+
+    impl i2c::Write for I2cMock {
+        type Error = ();
+        fn write(&mut self, _addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.write_bytes(bytes);
+            Ok(())
+        }
+    }
+
+    impl i2c::Read for I2cMock {
+        type Error = ();
+        fn read(&mut self, _addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+            self.read_bytes(buffer);
+            Ok(())
+        }
+    }
+
+    impl i2c::WriteRead for I2cMock {
+        type Error = ();
+        fn write_read(
+            &mut self,
+            _addr: u8,
+            bytes: &[u8],
+            buffer: &mut [u8],
+        ) -> Result<(), Self::Error> {
+            self.write_bytes(bytes);
+            self.read_bytes(buffer);
+            Ok(())
+        }
+    }
+
+    impl delay::DelayMs<u32> for DelayMock {
+        fn delay_ms(&mut self, ms: u32) {
+            self.elapsed_ms += ms;
+        }
+    }
+
+    impl InputPin for InputPinMock {
+        type Error = ();
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            Ok(self.level_high())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.level_high())
+        }
+    }
+}
+
+#[cfg(any(feature = "eh1", feature = "async"))]
+mod eh1 {
+    use super::I2cMock;
+    #[cfg(not(feature = "async"))]
+    use embedded_hal_1::i2c::{ErrorType, Operation};
+    #[cfg(feature = "async")]
+    use embedded_hal_async::i2c::{ErrorType, Operation};
+
+    impl ErrorType for I2cMock {
+        type Error = core::convert::Infallible;
+    }
+
+    impl I2cMock {
+        fn execute(&mut self, operations: &mut [Operation<'_>]) {
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => self.write_bytes(bytes),
+                    Operation::Read(buffer) => self.read_bytes(buffer),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "eh1")]
+    impl embedded_hal_1::i2c::I2c for I2cMock {
+        fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            self.execute(operations);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "eh1")]
+    impl embedded_hal_1::delay::DelayNs for super::DelayMock {
+        fn delay_ns(&mut self, ns: u32) {
+            self.elapsed_ms += ns / 1_000_000;
+        }
+
+        fn delay_ms(&mut self, ms: u32) {
+            self.elapsed_ms += ms;
+        }
+    }
+
+    #[cfg(feature = "eh1")]
+    impl embedded_hal_1::digital::ErrorType for super::InputPinMock {
+        type Error = core::convert::Infallible;
+    }
+
+    #[cfg(feature = "eh1")]
+    impl embedded_hal_1::digital::InputPin for super::InputPinMock {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            Ok(self.level_high())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.level_high())
+        }
+    }
+
+    #[cfg(feature = "async")]
+    impl embedded_hal_async::delay::DelayNs for super::DelayMock {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.elapsed_ms += ns / 1_000_000;
+        }
+
+        async fn delay_ms(&mut self, ms: u32) {
+            self.elapsed_ms += ms;
+        }
+    }
+
+    #[cfg(feature = "async")]
+    impl embedded_hal_async::i2c::I2c for I2cMock {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            self.execute(operations);
+            Ok(())
+        }
+    }
+}
+
+/// Shared access to an [`I2cMock`], for tests that need to change registers
+/// while the driver owns the bus
+pub struct SharedI2cMock<'a>(pub &'a RefCell<I2cMock>);
+
+impl i2c::Write for SharedI2cMock<'_> {
+    type Error = ();
+    fn write(&mut self, _addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().write_bytes(bytes);
+        Ok(())
+    }
+}
+
+impl i2c::WriteRead for SharedI2cMock<'_> {
+    type Error = ();
+    fn write_read(
+        &mut self,
+        _addr: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let mut mock = self.0.borrow_mut();
+        mock.write_bytes(bytes);
+        mock.read_bytes(buffer);
+        Ok(())
+    }
+}
+
+/// Delay that only records the total time requested
+#[derive(Default)]
+pub struct DelayMock {
+    pub elapsed_ms: u32,
+}
+
+/// Input pin that becomes active after a number of reads
+#[derive(Default)]
+pub struct InputPinMock {
+    /// Level of the pin once active
+    pub active_high: bool,
+    /// Number of reads before the pin is active
+    pub active_after: u32,
+    /// Number of reads the pin then stays active, `None` for ever
+    pub active_reads: Option<u32>,
+    /// Number of reads so far
+    pub reads: Cell<u32>,
+}
+
+impl InputPinMock {
+    fn level_high(&self) -> bool {
+        let reads = self.reads.get();
+        self.reads.set(reads + 1);
+        let active = reads >= self.active_after
+            && match self.active_reads {
+                Some(n) => reads - self.active_after < n,
+                None => true,
+            };
+        active == self.active_high
+    }
+}
+
+/// Poll a future to completion
+#[cfg(feature = "async")]
+pub fn block_on<F: core::future::Future>(future: F) -> F::Output {
+    use core::task::{Context, Poll};
+    let waker = noop_waker();
+    let mut future = core::pin::pin!(future);
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+/// Waker that does nothing, `Waker::noop()` needs Rust 1.85
+#[cfg(feature = "async")]
+#[allow(unsafe_code)]
+fn noop_waker() -> core::task::Waker {
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(core::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    // SAFETY: the vtable functions ignore the data pointer
+    unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+}
+
+/// Interrupt pin that is always at the level waited for
+#[cfg(feature = "async")]
+#[derive(Default)]
+pub struct PinMock {
+    /// Last level waited for (`true` for high)
+    pub waited_for: Option<bool>,
+}
+
+#[cfg(feature = "async")]
+mod pin {
+    use super::PinMock;
+    use embedded_hal_1::digital::ErrorType;
+    use embedded_hal_async::digital::Wait;
+
+    impl ErrorType for PinMock {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Wait for PinMock {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            self.waited_for = Some(true);
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            self.waited_for = Some(false);
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_high().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_low().await
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+}
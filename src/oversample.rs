@@ -0,0 +1,151 @@
+//! Oversampling and decimation of raw ALS data
+//!
+//! Summing 4^n conversions and dividing the sum by 2^n yields n extra bits
+//! of resolution, provided the readings carry some noise. This is useful
+//! when the 16-bit quantization is visible, e.g. in low light at low gain.
+//!
+//! ```
+//! use ltr_559::{AlsData, Oversampler};
+//!
+//! let mut oversampler = Oversampler::new(1);
+//! for ch0 in [100, 101, 100] {
+//!     assert_eq!(oversampler.update(AlsData { ch0, ch1: 0 }), None);
+//! }
+//! let reading = oversampler.update(AlsData { ch0: 101, ch1: 0 }).unwrap();
+//! // 100.5 counts with one extra bit
+//! assert_eq!(reading.ch0, 201);
+//! ```
+use crate::AlsData;
+#[cfg(feature = "float")]
+use crate::{calc::LuxCoefficients, AlsGain, AlsIntTime};
+
+/// Accumulates raw ALS data and outputs decimated readings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oversampler {
+    extra_bits: u8,
+    ch0: u32,
+    ch1: u32,
+    count: u32,
+}
+
+impl Oversampler {
+    /// Highest number of extra bits, so that the sums fit in 32 bits
+    pub const MAX_EXTRA_BITS: u8 = 8;
+
+    /// Create an oversampler adding `extra_bits` bits of resolution.
+    ///
+    /// `extra_bits` is clamped to [`MAX_EXTRA_BITS`](Self::MAX_EXTRA_BITS).
+    pub fn new(extra_bits: u8) -> Self {
+        Oversampler {
+            extra_bits: extra_bits.min(Self::MAX_EXTRA_BITS),
+            ch0: 0,
+            ch1: 0,
+            count: 0,
+        }
+    }
+
+    /// Number of conversions accumulated for one output: 4^`extra_bits`
+    pub fn samples_needed(&self) -> u32 {
+        1 << (2 * self.extra_bits)
+    }
+
+    /// Feed a new conversion.
+    ///
+    /// Returns the decimated reading once enough conversions have been
+    /// accumulated, then starts over.
+    pub fn update(&mut self, data: AlsData) -> Option<OversampledAls> {
+        self.ch0 += u32::from(data.ch0);
+        self.ch1 += u32::from(data.ch1);
+        self.count += 1;
+        if self.count < self.samples_needed() {
+            return None;
+        }
+        let reading = OversampledAls {
+            ch0: self.ch0 >> self.extra_bits,
+            ch1: self.ch1 >> self.extra_bits,
+            extra_bits: self.extra_bits,
+        };
+        self.reset();
+        Some(reading)
+    }
+
+    /// Discard the conversions accumulated so far
+    pub fn reset(&mut self) {
+        self.ch0 = 0;
+        self.ch1 = 0;
+        self.count = 0;
+    }
+}
+
+/// Decimated ALS reading with `16 + extra_bits` bits of resolution
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OversampledAls {
+    /// Channel 0 (visible + IR) counts, scaled up by 2^`extra_bits`
+    pub ch0: u32,
+    /// Channel 1 (IR) counts, scaled up by 2^`extra_bits`
+    pub ch1: u32,
+    /// Number of extra bits of resolution
+    pub extra_bits: u8,
+}
+
+impl OversampledAls {
+    /// Compute lux with the coefficients of the datasheet, for data
+    /// measured with `als_gain` and `als_int`
+    #[cfg(feature = "float")]
+    pub fn lux(&self, als_gain: AlsGain, als_int: AlsIntTime) -> f32 {
+        LuxCoefficients::DATASHEET.lux_scaled(
+            self.ch0,
+            self.ch1,
+            self.extra_bits,
+            als_gain,
+            als_int,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimates_after_enough_samples() {
+        let mut oversampler = Oversampler::new(2);
+        assert_eq!(oversampler.samples_needed(), 16);
+        for _ in 0..15 {
+            assert_eq!(oversampler.update(AlsData { ch0: 3, ch1: 1 }), None);
+        }
+        let reading = oversampler.update(AlsData { ch0: 4, ch1: 1 }).unwrap();
+        assert_eq!((reading.ch0, reading.ch1, reading.extra_bits), (12, 4, 2));
+        assert_eq!(oversampler.update(AlsData { ch0: 3, ch1: 1 }), None);
+    }
+
+    #[test]
+    fn full_scale_does_not_overflow() {
+        let mut oversampler = Oversampler::new(20);
+        let full_scale = AlsData {
+            ch0: u16::MAX,
+            ch1: u16::MAX,
+        };
+        let needed = oversampler.samples_needed();
+        let reading = (0..needed).find_map(|_| oversampler.update(full_scale));
+        assert_eq!(reading.map(|r| r.ch0), Some(u32::from(u16::MAX) << 8));
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn lux_without_extra_bits_matches_driver() {
+        let reading = OversampledAls {
+            ch0: 100,
+            ch1: 0,
+            extra_bits: 0,
+        };
+        assert_eq!(reading.lux(AlsGain::Gain1x, AlsIntTime::_100ms), 177.43);
+        let finer = OversampledAls {
+            ch0: 200,
+            ch1: 0,
+            extra_bits: 1,
+        };
+        assert_eq!(finer.lux(AlsGain::Gain1x, AlsIntTime::_100ms), 177.43);
+    }
+}
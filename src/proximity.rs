@@ -0,0 +1,165 @@
+//! Proximity zones tracked with the PS interrupt thresholds
+//!
+//! [`ProximityZones`] splits the PS counts into four bands. After each
+//! zone update with [`Ltr559::update_proximity_zone()`] the PS thresholds
+//! are reprogrammed to the edges of the current band, so that the device
+//! only raises an interrupt once the counts leave it and no polling is
+//! needed in between.
+//!
+//! ```
+//! use ltr_559::{ProximityZone, ProximityZones};
+//!
+//! let mut zones = ProximityZones::new([100, 400, 1500], 20);
+//! assert_eq!(zones.update(50).unwrap().current, ProximityZone::Far);
+//! assert_eq!(zones.thresholds(), (0, 119));
+//! assert_eq!(zones.update(500).unwrap().current, ProximityZone::Near);
+//! assert_eq!(zones.thresholds(), (380, 1519));
+//! ```
+use crate::bands::{Band, Boundary, Classifier, Transition};
+use crate::hal::blocking::i2c;
+use crate::{marker, Error, Ltr559};
+
+/// Largest PS count
+const PS_MAX: u16 = 0x07FF;
+
+/// Proximity band, from farthest to nearest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProximityZone {
+    /// Below the first edge
+    Far,
+    /// Between the first and the second edge
+    Mid,
+    /// Between the second and the third edge
+    Near,
+    /// Above the third edge
+    Touch,
+}
+
+impl Band for ProximityZone {
+    const ALL: &'static [Self] = &[
+        ProximityZone::Far,
+        ProximityZone::Mid,
+        ProximityZone::Near,
+        ProximityZone::Touch,
+    ];
+}
+
+/// A zone change reported by [`ProximityZones`]
+pub type ProximityChange = Transition<ProximityZone>;
+
+/// Maps PS counts into [`ProximityZone`]s with hysteresis at each edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProximityZones {
+    classifier: Classifier<u16, ProximityZone, 3>,
+}
+
+impl ProximityZones {
+    /// Create a new classifier.
+    ///
+    /// `edges` are the PS counts between the four zones. They are sorted
+    /// in ascending order and limited to the 11-bit PS data range. Once a
+    /// zone is known, an edge is only crossed upwards at
+    /// `edge + hysteresis` and downwards below `edge - hysteresis`.
+    pub fn new(mut edges: [u16; 3], hysteresis: u16) -> Self {
+        for edge in edges.iter_mut() {
+            *edge = (*edge).min(PS_MAX);
+        }
+        ProximityZones {
+            classifier: Classifier::new(edges, hysteresis),
+        }
+    }
+
+    /// Feed new PS counts.
+    ///
+    /// Returns the change if the zone changed. The first value is always
+    /// reported.
+    pub fn update(&mut self, counts: u16) -> Option<ProximityChange> {
+        self.classifier.update(counts)
+    }
+
+    /// Current zone, `None` before the first value
+    pub fn zone(&self) -> Option<ProximityZone> {
+        self.classifier.band()
+    }
+
+    /// `(low, high)` PS thresholds raising an interrupt once the counts
+    /// leave the current zone. The whole range before the first value.
+    pub fn thresholds(&self) -> (u16, u16) {
+        let index = match self.classifier.index() {
+            Some(index) => index,
+            None => return (0, PS_MAX),
+        };
+        let edges = self.classifier.boundaries();
+        let hysteresis = self.classifier.hysteresis();
+        let low = match index {
+            0 => 0,
+            _ => edges[index - 1].lowered(hysteresis),
+        };
+        let high = match edges.get(index) {
+            Some(edge) => edge.raised(hysteresis).saturating_sub(1).min(PS_MAX),
+            None => PS_MAX,
+        };
+        (low, high.max(low))
+    }
+
+    /// Forget the current zone, so that the next value is reported
+    pub fn reset(&mut self) {
+        self.classifier.reset();
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E> + i2c::Write<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Read the PS counts, update `zones` and program the PS thresholds to
+    /// the edges of the current zone.
+    ///
+    /// Call this once to start and then after each PS interrupt. Returns
+    /// the zone change, if any.
+    pub fn update_proximity_zone(
+        &mut self,
+        zones: &mut ProximityZones,
+    ) -> Result<Option<ProximityChange>, Error<E>> {
+        let (counts, _) = self.get_ps_data()?;
+        let change = zones.update(counts);
+        let (low, high) = zones.thresholds();
+        self.set_ps_thresholds(low, high)?;
+        Ok(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    #[test]
+    fn hysteresis_at_edges() {
+        let mut zones = ProximityZones::new([1500, 100, 400], 20);
+        assert_eq!(zones.thresholds(), (0, PS_MAX));
+        zones.update(2000);
+        assert_eq!(zones.zone(), Some(ProximityZone::Touch));
+        assert_eq!(zones.thresholds(), (1480, PS_MAX));
+        assert_eq!(zones.update(1490), None);
+        let change = zones.update(90).unwrap();
+        assert_eq!(change.previous, Some(ProximityZone::Touch));
+        assert_eq!(change.current, ProximityZone::Mid);
+        assert_eq!(zones.update(79).unwrap().current, ProximityZone::Far);
+    }
+
+    #[test]
+    fn thresholds_follow_zone() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut zones = ProximityZones::new([100, 400, 1500], 20);
+        sensor.i2c.registers[0x8D..0x8F].copy_from_slice(&[0xC8, 0x00]);
+        let change = sensor.update_proximity_zone(&mut zones).unwrap();
+        assert_eq!(change.unwrap().current, ProximityZone::Mid);
+        let thresholds = sensor.get_thresholds().unwrap();
+        assert_eq!((thresholds.ps_low, thresholds.ps_high), (80, 419));
+        assert_eq!(sensor.update_proximity_zone(&mut zones).unwrap(), None);
+    }
+}
@@ -0,0 +1,253 @@
+//! Acquisition loop with backpressure control
+use crate::filter::Filter;
+use crate::hal::blocking::{delay::DelayMs, i2c};
+use crate::{marker, Error, Ltr559};
+
+/// How [`Ltr559::run()`] paces the acquisition of samples
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pacing {
+    /// Deliver every new conversion exactly once, following the configured
+    /// ALS measurement rate (default)
+    #[default]
+    MeasurementRate,
+    /// Read the latest conversion every given number of milliseconds
+    FixedInterval(u32),
+    /// Read back-to-back without waiting for new conversions
+    AsFastAsPossible,
+}
+
+/// What to do with samples acquired while the handler is busy
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Overflow {
+    /// Discard them and report how many were dropped (default)
+    #[default]
+    Drop,
+    /// Average them into the next sample handed to the handler
+    Aggregate,
+}
+
+/// Options for [`Ltr559::run()`]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RunOptions {
+    /// Acquisition pacing
+    pub pacing: Pacing,
+    /// Policy for samples acquired while the handler is busy
+    pub overflow: Overflow,
+}
+
+/// Sample handed to the [`Ltr559::run()`] handler
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RunSample {
+    /// Lux value (mean of all aggregated conversions)
+    pub lux: f32,
+    /// Number of conversions averaged into `lux`
+    pub aggregated: u32,
+    /// Number of conversions dropped since the previous sample
+    pub dropped: u32,
+}
+
+/// Value returned by the [`Ltr559::run()`] handler
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RunControl {
+    /// Hand over the next sample as soon as it is available
+    Continue,
+    /// The handler is busy: do not call it for the given number of samples
+    Busy(u32),
+    /// Leave the acquisition loop
+    Stop,
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Run the acquisition loop, calling `handler` with each lux sample.
+    ///
+    /// The handler controls the backpressure through its return value. While
+    /// it reports itself busy, the loop keeps acquiring samples and drops or
+    /// aggregates them according to `options.overflow`.
+    ///
+    /// Returns the total number of dropped samples once the handler stops.
+    /// With [`Pacing::MeasurementRate`], returns `Error::Timeout` if no
+    /// conversion arrives within three measurement periods, e.g. because
+    /// the ALS is in standby.
+    pub fn run<D, F>(
+        &mut self,
+        delay: &mut D,
+        options: RunOptions,
+        mut handler: F,
+    ) -> Result<u32, Error<E>>
+    where
+        D: DelayMs<u32>,
+        F: FnMut(RunSample) -> RunControl,
+    {
+        let mut busy = 0;
+        let mut dropped = 0;
+        let mut total_dropped = 0;
+        let mut sum = 0.0;
+        let mut aggregated = 0;
+        loop {
+            self.wait_for_sample(delay, options.pacing)?;
+            let lux = self.get_lux()?;
+            sum += lux;
+            aggregated += 1;
+            if busy > 0 {
+                busy -= 1;
+                if options.overflow == Overflow::Drop {
+                    sum = 0.0;
+                    aggregated = 0;
+                    dropped += 1;
+                    total_dropped += 1;
+                }
+                continue;
+            }
+            let sample = RunSample {
+                lux: sum / aggregated as f32,
+                aggregated,
+                dropped,
+            };
+            sum = 0.0;
+            aggregated = 0;
+            dropped = 0;
+            match handler(sample) {
+                RunControl::Continue => {}
+                RunControl::Busy(count) => busy = count,
+                RunControl::Stop => return Ok(total_dropped),
+            }
+        }
+    }
+
+    /// Same as [`run()`](#method.run) but passes every sample through
+    /// `filter` first.
+    ///
+    /// Samples for which the filter does not produce an output yet are not
+    /// handed to the handler.
+    pub fn run_filtered<D, L, F>(
+        &mut self,
+        delay: &mut D,
+        options: RunOptions,
+        mut filter: L,
+        mut handler: F,
+    ) -> Result<u32, Error<E>>
+    where
+        D: DelayMs<u32>,
+        L: Filter,
+        F: FnMut(RunSample) -> RunControl,
+    {
+        let mut dropped = 0;
+        self.run(delay, options, |sample| {
+            dropped += sample.dropped;
+            match filter.update(sample.lux) {
+                Some(lux) => {
+                    let sample = RunSample {
+                        lux,
+                        dropped,
+                        ..sample
+                    };
+                    dropped = 0;
+                    handler(sample)
+                }
+                None => RunControl::Continue,
+            }
+        })
+    }
+
+    fn wait_for_sample<D: DelayMs<u32>>(
+        &mut self,
+        delay: &mut D,
+        pacing: Pacing,
+    ) -> Result<(), Error<E>> {
+        match pacing {
+            Pacing::MeasurementRate => {
+                let period = self.als_meas_rate.milliseconds();
+                self.poll_status(delay, period, 3 * period, |status| {
+                    status.als_data_status && status.als_data_valid
+                })
+            }
+            Pacing::FixedInterval(ms) => {
+                delay.delay_ms(ms);
+                Ok(())
+            }
+            Pacing::AsFastAsPossible => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{DelayMock, I2cMock};
+    use crate::SlaveAddr;
+
+    fn device() -> Ltr559<I2cMock, crate::ic::Ltr559> {
+        let mut i2c = I2cMock::new();
+        // CH0 = 100 counts, CH1 = 0, new valid data
+        i2c.registers[0x8A] = 100;
+        i2c.registers[0x8C] = 0x04;
+        Ltr559::new_device(i2c, SlaveAddr::default())
+    }
+
+    #[test]
+    fn run_drops_samples_while_busy() {
+        let mut sensor = device();
+        let mut delay = DelayMock::default();
+        let mut samples = [None; 3];
+        let mut calls = 0;
+        let total = sensor
+            .run(&mut delay, RunOptions::default(), |sample| {
+                samples[calls] = Some(sample);
+                calls += 1;
+                match calls {
+                    1 => RunControl::Busy(2),
+                    2 => RunControl::Busy(1),
+                    _ => RunControl::Stop,
+                }
+            })
+            .unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(samples[1].unwrap().dropped, 2);
+        assert_eq!(samples[2].unwrap().dropped, 1);
+        assert_eq!(samples[2].unwrap().aggregated, 1);
+    }
+
+    #[test]
+    fn run_aggregates_samples_while_busy() {
+        let mut sensor = device();
+        let mut delay = DelayMock::default();
+        let options = RunOptions {
+            pacing: Pacing::FixedInterval(20),
+            overflow: Overflow::Aggregate,
+        };
+        let mut last = None;
+        let total = sensor
+            .run(&mut delay, options, |sample| {
+                let first = last.is_none();
+                last = Some(sample);
+                if first {
+                    RunControl::Busy(3)
+                } else {
+                    RunControl::Stop
+                }
+            })
+            .unwrap();
+        assert_eq!(total, 0);
+        assert_eq!(last.unwrap().aggregated, 4);
+        assert_eq!(last.unwrap().dropped, 0);
+        assert_eq!(delay.elapsed_ms, 100);
+    }
+
+    #[test]
+    fn run_times_out_without_new_data() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut delay = DelayMock::default();
+        let result = sensor.run(&mut delay, RunOptions::default(), |_| RunControl::Continue);
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert_eq!(delay.elapsed_ms, 3 * 500);
+    }
+}
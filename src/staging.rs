@@ -0,0 +1,71 @@
+//! In-memory image of the configuration registers used in staging mode
+
+/// First configuration register (ALS_CONTR)
+const FIRST_REGISTER: u8 = 0x80;
+/// Number of registers from ALS_CONTR to INTERRUPT_PERSIST
+pub(crate) const SIZE: usize = 31;
+/// Index of the first register after the control registers
+pub(crate) const CONTROL_END: usize = 2;
+
+/// Staged register values with a dirty flag per register
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RegisterImage {
+    values: [u8; SIZE],
+    dirty: u32,
+}
+
+impl RegisterImage {
+    pub(crate) fn new() -> Self {
+        RegisterImage {
+            values: [0; SIZE],
+            dirty: 0,
+        }
+    }
+
+    /// Stage a value. Returns `false` if the register is not a
+    /// configuration register.
+    pub(crate) fn set(&mut self, register: u8, value: u8) -> bool {
+        match Self::index(register) {
+            Some(index) => {
+                self.values[index] = value;
+                self.dirty |= 1 << index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Staged value of a register, if any
+    pub(crate) fn get(&self, register: u8) -> Option<u8> {
+        Self::index(register)
+            .filter(|index| self.dirty & (1 << index) != 0)
+            .map(|index| self.values[index])
+    }
+
+    /// Find the next run of consecutive staged registers in `from..to`.
+    ///
+    /// Returns the index of the first register of the run and its length.
+    pub(crate) fn next_run(&self, from: usize, to: usize) -> Option<(usize, usize)> {
+        let is_dirty = |index: usize| self.dirty & (1 << index) != 0;
+        let start = (from..to).find(|&index| is_dirty(index))?;
+        let len = (start..to).take_while(|&index| is_dirty(index)).count();
+        Some((start, len))
+    }
+
+    /// Fill `buffer` with the register address and values of a run for a
+    /// burst write and return the number of bytes to write.
+    pub(crate) fn burst(&self, start: usize, len: usize, buffer: &mut [u8; SIZE + 1]) -> usize {
+        buffer[0] = FIRST_REGISTER + start as u8;
+        buffer[1..=len].copy_from_slice(&self.values[start..start + len]);
+        len + 1
+    }
+
+    fn index(register: u8) -> Option<usize> {
+        let index = usize::from(register.checked_sub(FIRST_REGISTER)?);
+        if index < SIZE {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Running statistics over readings
+//!
+//! With the `stats` feature the drivers feed every lux value they compute
+//! into a [`RunningStats`], see `Ltr559::lux_stats()`.
+//!
+//! ```
+//! use ltr_559::RunningStats;
+//!
+//! let mut stats = RunningStats::new();
+//! for lux in [10.0, 30.0, 20.0] {
+//!     stats.update(lux);
+//! }
+//! assert_eq!(stats.min(), Some(10.0));
+//! assert_eq!(stats.max(), Some(30.0));
+//! assert_eq!(stats.mean(), Some(20.0));
+//! assert_eq!(stats.count(), 3);
+//! ```
+
+/// Minimum, maximum and mean of the values since the last reset
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RunningStats {
+    min: f32,
+    max: f32,
+    mean: f32,
+    count: u32,
+}
+
+impl RunningStats {
+    /// Create an empty tracker
+    pub const fn new() -> Self {
+        RunningStats {
+            min: 0.0,
+            max: 0.0,
+            mean: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Add a value
+    pub fn update(&mut self, value: f32) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count = self.count.saturating_add(1);
+        // incremental mean, does not lose precision like a running sum
+        self.mean += (value - self.mean) / self.count as f32;
+    }
+
+    /// Smallest value, `None` if no value was added
+    pub fn min(&self) -> Option<f32> {
+        self.value(self.min)
+    }
+
+    /// Largest value, `None` if no value was added
+    pub fn max(&self) -> Option<f32> {
+        self.value(self.max)
+    }
+
+    /// Mean value, `None` if no value was added
+    pub fn mean(&self) -> Option<f32> {
+        self.value(self.mean)
+    }
+
+    /// Number of values added
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Forget all values
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn value(&self, value: f32) -> Option<f32> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_until_updated() {
+        let mut stats = RunningStats::new();
+        assert_eq!((stats.min(), stats.max(), stats.mean()), (None, None, None));
+        stats.update(-1.0);
+        stats.update(3.0);
+        assert_eq!(stats.min(), Some(-1.0));
+        assert_eq!(stats.mean(), Some(1.0));
+        stats.reset();
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.max(), None);
+    }
+}
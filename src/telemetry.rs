@@ -0,0 +1,215 @@
+//! Batched reporting of measurements to an application-defined sink
+use crate::hal::blocking::i2c;
+use crate::{marker, Error, Ltr559};
+
+/// A single measurement reported through a [`TelemetrySink`]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TelemetryReading {
+    /// Lux value
+    pub lux: f32,
+    /// PS counts
+    pub ps: u16,
+}
+
+/// Errors encountered while collecting a batch
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ErrorStats {
+    /// Number of failed I²C transactions, as counted in
+    /// [`DiagnosticCounters::i2c_errors`](crate::DiagnosticCounters::i2c_errors)
+    pub i2c: u32,
+    /// Number of other driver errors
+    pub other: u32,
+    /// Number of readings discarded because the batch was full and could
+    /// not be sent
+    pub dropped: u32,
+}
+
+/// Destination for batches of measurements, e.g. an MQTT or CoAP adapter
+pub trait TelemetrySink {
+    /// Error returned by the sink
+    type Error;
+
+    /// Send a batch of readings together with the error statistics
+    /// collected while acquiring them
+    fn send(
+        &mut self,
+        readings: &[TelemetryReading],
+        errors: &ErrorStats,
+    ) -> Result<(), Self::Error>;
+}
+
+/// Batch of up to `N` readings collected by [`Ltr559::report_to()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelemetryBatch<const N: usize> {
+    readings: [TelemetryReading; N],
+    len: usize,
+    errors: ErrorStats,
+}
+
+impl<const N: usize> TelemetryBatch<N> {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        TelemetryBatch {
+            readings: [TelemetryReading::default(); N],
+            len: 0,
+            errors: ErrorStats::default(),
+        }
+    }
+
+    /// Readings collected so far
+    pub fn readings(&self) -> &[TelemetryReading] {
+        &self.readings[..self.len]
+    }
+
+    /// Error statistics collected so far
+    pub fn errors(&self) -> &ErrorStats {
+        &self.errors
+    }
+
+    fn push(&mut self, reading: TelemetryReading) {
+        if N == 0 {
+            return;
+        }
+        if self.len == N {
+            self.readings.copy_within(1.., 0);
+            self.len -= 1;
+            self.errors.dropped += 1;
+        }
+        self.readings[self.len] = reading;
+        self.len += 1;
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+        self.errors = ErrorStats::default();
+    }
+}
+
+impl<const N: usize> Default for TelemetryBatch<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I2C, E, IC> Ltr559<I2C, IC>
+where
+    I2C: i2c::WriteRead<Error = E>,
+    IC: marker::WithDeviceId,
+{
+    /// Take a reading into `batch` and send the batch to `sink` once full.
+    ///
+    /// Call this periodically. Driver errors do not interrupt reporting: they
+    /// are counted in the batch error statistics instead. If the sink fails,
+    /// its error is returned and the batch is kept so that sending is retried
+    /// on the next call, the oldest readings being dropped to make room.
+    ///
+    /// Returns whether the batch was sent.
+    pub fn report_to<S, const N: usize>(
+        &mut self,
+        batch: &mut TelemetryBatch<N>,
+        sink: &mut S,
+    ) -> Result<bool, S::Error>
+    where
+        S: TelemetrySink,
+    {
+        let i2c_errors = self.diagnostics.i2c_errors;
+        let reading = self.get_lux().and_then(|lux| {
+            Ok(TelemetryReading {
+                lux,
+                ps: self.get_ps_data()?.0,
+            })
+        });
+        batch.errors.i2c += self.diagnostics.i2c_errors.wrapping_sub(i2c_errors);
+        match reading {
+            Ok(reading) => batch.push(reading),
+            Err(Error::I2C(_)) => {}
+            Err(_) => batch.errors.other += 1,
+        }
+        if batch.len < N {
+            return Ok(false);
+        }
+        sink.send(batch.readings(), &batch.errors)?;
+        batch.clear();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::I2cMock;
+    use crate::SlaveAddr;
+
+    struct Sink {
+        sent: usize,
+        fail: bool,
+    }
+
+    impl TelemetrySink for Sink {
+        type Error = ();
+        fn send(&mut self, readings: &[TelemetryReading], _: &ErrorStats) -> Result<(), ()> {
+            if self.fail {
+                return Err(());
+            }
+            self.sent += readings.len();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sends_full_batches() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut batch = TelemetryBatch::<2>::new();
+        let mut sink = Sink {
+            sent: 0,
+            fail: false,
+        };
+        assert_eq!(sensor.report_to(&mut batch, &mut sink), Ok(false));
+        assert_eq!(sensor.report_to(&mut batch, &mut sink), Ok(true));
+        assert_eq!(sink.sent, 2);
+        assert!(batch.readings().is_empty());
+    }
+
+    #[test]
+    fn keeps_batch_when_sink_fails() {
+        let mut sensor = Ltr559::new_device(I2cMock::new(), SlaveAddr::default());
+        let mut batch = TelemetryBatch::<2>::new();
+        let mut sink = Sink {
+            sent: 0,
+            fail: true,
+        };
+        sensor.report_to(&mut batch, &mut sink).ok();
+        assert_eq!(sensor.report_to(&mut batch, &mut sink), Err(()));
+        assert_eq!(sensor.report_to(&mut batch, &mut sink), Err(()));
+        assert_eq!(batch.readings().len(), 2);
+        assert_eq!(batch.errors().dropped, 1);
+        sink.fail = false;
+        assert_eq!(sensor.report_to(&mut batch, &mut sink), Ok(true));
+        assert_eq!(sink.sent, 2);
+    }
+
+    struct FailingBus;
+
+    impl i2c::WriteRead for FailingBus {
+        type Error = ();
+        fn write_read(&mut self, _: u8, _: &[u8], _: &mut [u8]) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn counts_bus_errors_of_the_driver() {
+        let mut sensor = Ltr559::new_device(FailingBus, SlaveAddr::default());
+        let mut batch = TelemetryBatch::<2>::new();
+        let mut sink = Sink {
+            sent: 0,
+            fail: false,
+        };
+        assert_eq!(sensor.report_to(&mut batch, &mut sink), Ok(false));
+        assert_eq!(batch.errors().i2c, sensor.diagnostics().i2c_errors);
+        assert!(batch.errors().i2c > 0);
+        assert!(batch.readings().is_empty());
+    }
+}
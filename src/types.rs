@@ -1,9 +1,11 @@
 //! Types used in LTR
 
 /// ALS Gain
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AlsGain {
     /// Gain 1x (1 lux to 64k lux default)
+    #[default]
     Gain1x,
     /// Gain 2x (0.5 lux to 32k lux)
     Gain2x,
@@ -17,13 +19,6 @@ pub enum AlsGain {
     Gain96x,
 }
 
-/// Default for AlsGain
-impl Default for AlsGain {
-    fn default() -> Self {
-        AlsGain::Gain1x
-    }
-}
-
 impl AlsGain {
     /// ALS Gain value
     pub fn value(&self) -> u8 {
@@ -38,7 +33,20 @@ impl AlsGain {
         }
     }
 
+    /// Gain factor
+    pub fn multiplier(&self) -> u32 {
+        match *self {
+            AlsGain::Gain1x => 1,
+            AlsGain::Gain2x => 2,
+            AlsGain::Gain4x => 4,
+            AlsGain::Gain8x => 8,
+            AlsGain::Gain48x => 48,
+            AlsGain::Gain96x => 96,
+        }
+    }
+
     /// ALS_GAIN value, used in lux computation
+    #[cfg(feature = "float")]
     pub fn lux_compute_value(&self) -> f32 {
         match *self {
             AlsGain::Gain1x => 1.0,
@@ -49,10 +57,24 @@ impl AlsGain {
             AlsGain::Gain96x => 96.0,
         }
     }
+
+    /// Decode from the ALS_CONTR register value. Reserved codes give `None`.
+    pub(crate) fn from_register(value: u8) -> Option<Self> {
+        match (value >> 2) & 7 {
+            0 => Some(AlsGain::Gain1x),
+            1 => Some(AlsGain::Gain2x),
+            2 => Some(AlsGain::Gain4x),
+            3 => Some(AlsGain::Gain8x),
+            6 => Some(AlsGain::Gain48x),
+            7 => Some(AlsGain::Gain96x),
+            _ => None,
+        }
+    }
 }
 
 /// LED Pulse Modulation Frequency
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LedPulse {
     /// Pulse 30khz
     Pulse30,
@@ -61,6 +83,7 @@ pub enum LedPulse {
     /// Pulse 50khz
     Pulse50,
     /// Pulse 60khz (default)
+    #[default]
     Pulse60,
     /// Pulse 70khz
     Pulse70,
@@ -72,12 +95,6 @@ pub enum LedPulse {
     Pulse100,
 }
 
-impl Default for LedPulse {
-    fn default() -> Self {
-        LedPulse::Pulse60
-    }
-}
-
 /// Implement something
 impl LedPulse {
     /// LED Pulse value
@@ -94,10 +111,39 @@ impl LedPulse {
             LedPulse::Pulse100 => 7 << BIT_OFFSET,
         }
     }
+
+    /// Pulse modulation frequency in kHz
+    pub fn frequency_khz(&self) -> u32 {
+        match *self {
+            LedPulse::Pulse30 => 30,
+            LedPulse::Pulse40 => 40,
+            LedPulse::Pulse50 => 50,
+            LedPulse::Pulse60 => 60,
+            LedPulse::Pulse70 => 70,
+            LedPulse::Pulse80 => 80,
+            LedPulse::Pulse90 => 90,
+            LedPulse::Pulse100 => 100,
+        }
+    }
+
+    /// Decode from the PS_LED register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        match (value >> 5) & 7 {
+            0 => LedPulse::Pulse30,
+            1 => LedPulse::Pulse40,
+            2 => LedPulse::Pulse50,
+            3 => LedPulse::Pulse60,
+            4 => LedPulse::Pulse70,
+            5 => LedPulse::Pulse80,
+            6 => LedPulse::Pulse90,
+            _ => LedPulse::Pulse100,
+        }
+    }
 }
 
 /// LED Duty Cycle
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LedDutyCycle {
     /// 25% duty
     _25,
@@ -106,15 +152,10 @@ pub enum LedDutyCycle {
     /// 75% duty
     _75,
     /// 100% (default)
+    #[default]
     _100,
 }
 
-impl Default for LedDutyCycle {
-    fn default() -> Self {
-        LedDutyCycle::_100
-    }
-}
-
 impl LedDutyCycle {
     /// LED Duty Cycle bits value
     pub fn value(&self) -> u8 {
@@ -126,10 +167,31 @@ impl LedDutyCycle {
             LedDutyCycle::_100 => 3 << BIT_OFFSET,
         }
     }
+
+    /// Duty cycle in percent
+    pub fn percent(&self) -> u32 {
+        match *self {
+            LedDutyCycle::_25 => 25,
+            LedDutyCycle::_50 => 50,
+            LedDutyCycle::_75 => 75,
+            LedDutyCycle::_100 => 100,
+        }
+    }
+
+    /// Decode from the PS_LED register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        match (value >> 3) & 3 {
+            0 => LedDutyCycle::_25,
+            1 => LedDutyCycle::_50,
+            2 => LedDutyCycle::_75,
+            _ => LedDutyCycle::_100,
+        }
+    }
 }
 
 /// Operating mode
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LedCurrent {
     /// 5 mA
     _5mA,
@@ -140,15 +202,10 @@ pub enum LedCurrent {
     /// 50 mA
     _50mA,
     /// 100 mA (default)
+    #[default]
     _100mA,
 }
 
-impl Default for LedCurrent {
-    fn default() -> Self {
-        LedCurrent::_100mA
-    }
-}
-
 impl LedCurrent {
     /// LED Current bits value
     pub fn value(&self) -> u8 {
@@ -160,16 +217,40 @@ impl LedCurrent {
             LedCurrent::_100mA => 7,
         }
     }
+
+    /// Peak current in mA
+    pub fn milliamps(&self) -> u32 {
+        match *self {
+            LedCurrent::_5mA => 5,
+            LedCurrent::_10mA => 10,
+            LedCurrent::_20mA => 20,
+            LedCurrent::_50mA => 50,
+            LedCurrent::_100mA => 100,
+        }
+    }
+
+    /// Decode from the PS_LED register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        match value & 7 {
+            0 => LedCurrent::_5mA,
+            1 => LedCurrent::_10mA,
+            2 => LedCurrent::_20mA,
+            3 => LedCurrent::_50mA,
+            _ => LedCurrent::_100mA,
+        }
+    }
 }
 
 /// Operating mode
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PsMeasRate {
     /// 50 ms
     _50ms,
     /// 70 ms
     _70ms,
     /// 100 ms (default)
+    #[default]
     _100ms,
     /// 200 ms
     _200ms,
@@ -183,12 +264,6 @@ pub enum PsMeasRate {
     _10ms,
 }
 
-impl Default for PsMeasRate {
-    fn default() -> Self {
-        PsMeasRate::_100ms
-    }
-}
-
 impl PsMeasRate {
     /// PS Measure Rate value
     pub fn value(&self) -> u8 {
@@ -203,10 +278,39 @@ impl PsMeasRate {
             PsMeasRate::_2000ms => 6,
         }
     }
+
+    /// Measurement repeat period in milliseconds
+    pub fn milliseconds(&self) -> u32 {
+        match *self {
+            PsMeasRate::_10ms => 10,
+            PsMeasRate::_50ms => 50,
+            PsMeasRate::_70ms => 70,
+            PsMeasRate::_100ms => 100,
+            PsMeasRate::_200ms => 200,
+            PsMeasRate::_500ms => 500,
+            PsMeasRate::_1000ms => 1000,
+            PsMeasRate::_2000ms => 2000,
+        }
+    }
+
+    /// Decode from the PS_MEAS_RATE register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        match value & 0x0F {
+            0 => PsMeasRate::_50ms,
+            1 => PsMeasRate::_70ms,
+            2 => PsMeasRate::_100ms,
+            3 => PsMeasRate::_200ms,
+            4 => PsMeasRate::_500ms,
+            5 => PsMeasRate::_1000ms,
+            8 => PsMeasRate::_10ms,
+            _ => PsMeasRate::_2000ms,
+        }
+    }
 }
 
 /// Operating mode
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AlsMeasRate {
     /// 50 ms
     _50ms,
@@ -215,6 +319,7 @@ pub enum AlsMeasRate {
     /// 200 ms
     _200ms,
     /// 500 ms (default)
+    #[default]
     _500ms,
     /// 1000 ms
     _1000ms,
@@ -222,12 +327,6 @@ pub enum AlsMeasRate {
     _2000ms,
 }
 
-impl Default for AlsMeasRate {
-    fn default() -> Self {
-        AlsMeasRate::_500ms
-    }
-}
-
 impl AlsMeasRate {
     /// Return value for AlsMeasRate
     pub fn value(&self) -> u8 {
@@ -240,14 +339,40 @@ impl AlsMeasRate {
             AlsMeasRate::_2000ms => 7,
         }
     }
+
+    /// Measurement repeat period in milliseconds
+    pub const fn milliseconds(&self) -> u32 {
+        match *self {
+            AlsMeasRate::_50ms => 50,
+            AlsMeasRate::_100ms => 100,
+            AlsMeasRate::_200ms => 200,
+            AlsMeasRate::_500ms => 500,
+            AlsMeasRate::_1000ms => 1000,
+            AlsMeasRate::_2000ms => 2000,
+        }
+    }
+
+    /// Decode from the ALS_MEAS_RATE register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        match value & 7 {
+            0 => AlsMeasRate::_50ms,
+            1 => AlsMeasRate::_100ms,
+            2 => AlsMeasRate::_200ms,
+            3 => AlsMeasRate::_500ms,
+            4 => AlsMeasRate::_1000ms,
+            _ => AlsMeasRate::_2000ms,
+        }
+    }
 }
 
 /// ALS Integration Time
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AlsIntTime {
     /// 50 ms
     _50ms,
     /// 100 ms
+    #[default]
     _100ms,
     /// 150 ms
     _150ms,
@@ -263,12 +388,6 @@ pub enum AlsIntTime {
     _400ms,
 }
 
-impl Default for AlsIntTime {
-    fn default() -> Self {
-        AlsIntTime::_100ms
-    }
-}
-
 impl AlsIntTime {
     /// Return value for AlsIntegrationTime
     pub fn value(&self) -> u8 {
@@ -284,7 +403,22 @@ impl AlsIntTime {
         }
     }
 
+    /// Integration time in milliseconds
+    pub const fn milliseconds(&self) -> u32 {
+        match *self {
+            AlsIntTime::_50ms => 50,
+            AlsIntTime::_100ms => 100,
+            AlsIntTime::_150ms => 150,
+            AlsIntTime::_200ms => 200,
+            AlsIntTime::_250ms => 250,
+            AlsIntTime::_300ms => 300,
+            AlsIntTime::_350ms => 350,
+            AlsIntTime::_400ms => 400,
+        }
+    }
+
     /// ALS_INT value used for lux computation
+    #[cfg(feature = "float")]
     pub fn lux_compute_value(&self) -> f32 {
         match *self {
             AlsIntTime::_100ms => 1.0,
@@ -297,12 +431,28 @@ impl AlsIntTime {
             AlsIntTime::_350ms => 3.5,
         }
     }
+
+    /// Decode from the ALS_MEAS_RATE register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        match (value >> 3) & 7 {
+            0 => AlsIntTime::_100ms,
+            1 => AlsIntTime::_50ms,
+            2 => AlsIntTime::_200ms,
+            3 => AlsIntTime::_400ms,
+            4 => AlsIntTime::_150ms,
+            5 => AlsIntTime::_250ms,
+            6 => AlsIntTime::_300ms,
+            _ => AlsIntTime::_350ms,
+        }
+    }
 }
 
 /// ALS Interrupt Persist
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AlsPersist {
     /// every ALS value out of threshold range (default)
+    #[default]
     EveryTime,
     /// 2 consecutive values outside threshold
     _2v,
@@ -336,12 +486,6 @@ pub enum AlsPersist {
     _16v,
 }
 
-impl Default for AlsPersist {
-    fn default() -> Self {
-        AlsPersist::EveryTime
-    }
-}
-
 impl AlsPersist {
     /// Return value for ALS Persistent
     pub fn value(&self) -> u8 {
@@ -364,12 +508,37 @@ impl AlsPersist {
             AlsPersist::_16v => 15,
         }
     }
+
+    /// Decode from the INTERRUPT_PERSIST register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        const ALL: [AlsPersist; 16] = [
+            AlsPersist::EveryTime,
+            AlsPersist::_2v,
+            AlsPersist::_3v,
+            AlsPersist::_4v,
+            AlsPersist::_5v,
+            AlsPersist::_6v,
+            AlsPersist::_7v,
+            AlsPersist::_8v,
+            AlsPersist::_9v,
+            AlsPersist::_10v,
+            AlsPersist::_11v,
+            AlsPersist::_12v,
+            AlsPersist::_13v,
+            AlsPersist::_14v,
+            AlsPersist::_15v,
+            AlsPersist::_16v,
+        ];
+        ALL[usize::from(value & 0x0F)]
+    }
 }
 
 /// PS Interrupt Persist
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PsPersist {
     /// every PS value out of threshold range (default)
+    #[default]
     EveryTime,
     /// 2 consecutive values outside threshold
     _2v,
@@ -403,12 +572,6 @@ pub enum PsPersist {
     _16v,
 }
 
-impl Default for PsPersist {
-    fn default() -> Self {
-        PsPersist::EveryTime
-    }
-}
-
 impl PsPersist {
     /// Return value for PS Persist
     pub fn value(&self) -> u8 {
@@ -432,12 +595,37 @@ impl PsPersist {
             PsPersist::_16v => 15 << BIT_OFFSET,
         }
     }
+
+    /// Decode from the INTERRUPT_PERSIST register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        const ALL: [PsPersist; 16] = [
+            PsPersist::EveryTime,
+            PsPersist::_2v,
+            PsPersist::_3v,
+            PsPersist::_4v,
+            PsPersist::_5v,
+            PsPersist::_6v,
+            PsPersist::_7v,
+            PsPersist::_8v,
+            PsPersist::_9v,
+            PsPersist::_10v,
+            PsPersist::_11v,
+            PsPersist::_12v,
+            PsPersist::_13v,
+            PsPersist::_14v,
+            PsPersist::_15v,
+            PsPersist::_16v,
+        ];
+        ALL[usize::from(value >> 4)]
+    }
 }
 
 /// PS Interrupt Persist
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum InterruptMode {
     /// Interrupt mode is disabled
+    #[default]
     Inactive,
     /// Interrupt for PS
     OnlyPS,
@@ -447,12 +635,6 @@ pub enum InterruptMode {
     Both,
 }
 
-impl Default for InterruptMode {
-    fn default() -> Self {
-        InterruptMode::Inactive
-    }
-}
-
 impl InterruptMode {
     /// Return value InterruptMode
     pub fn value(&self) -> u8 {
@@ -463,4 +645,158 @@ impl InterruptMode {
             InterruptMode::Both => 3,
         }
     }
+
+    /// Decode from the INTERRUPT register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        match value & 3 {
+            0 => InterruptMode::Inactive,
+            1 => InterruptMode::OnlyPS,
+            2 => InterruptMode::OnlyALS,
+            _ => InterruptMode::Both,
+        }
+    }
+}
+
+/// Decoded ALS_CONTR register
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AlsContr {
+    /// ALS gain
+    pub gain: AlsGain,
+    /// Software reset in progress
+    pub sw_reset: bool,
+    /// ALS active (otherwise in standby)
+    pub active: bool,
+}
+
+impl AlsContr {
+    /// Decode from the ALS_CONTR register value. Reserved gain codes give `None`.
+    pub(crate) fn from_register(value: u8) -> Option<Self> {
+        Some(AlsContr {
+            gain: AlsGain::from_register(value)?,
+            sw_reset: value & 2 != 0,
+            active: value & 1 != 0,
+        })
+    }
+}
+
+/// Decoded PS_CONTR register
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PsContr {
+    /// PS saturation indicator enabled
+    pub saturation_indicator: bool,
+    /// PS active (otherwise in standby)
+    pub active: bool,
+}
+
+impl PsContr {
+    /// Decode from the PS_CONTR register value
+    pub(crate) fn from_register(value: u8) -> Self {
+        PsContr {
+            saturation_indicator: value & (1 << 5) != 0,
+            active: value & 2 != 0,
+        }
+    }
+}
+
+/// ALS and PS interrupt thresholds in raw format
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Thresholds {
+    /// ALS low threshold
+    pub als_low: u16,
+    /// ALS high threshold
+    pub als_high: u16,
+    /// PS low threshold (11 bits)
+    pub ps_low: u16,
+    /// PS high threshold (11 bits)
+    pub ps_high: u16,
+}
+
+impl Default for Thresholds {
+    /// Power-up values: the whole range, so that no interrupt is triggered
+    fn default() -> Self {
+        Thresholds {
+            als_low: 0,
+            als_high: 0xFFFF,
+            ps_low: 0,
+            ps_high: 0x07FF,
+        }
+    }
+}
+
+/// Interrupt semantics of a threshold band
+///
+/// See [`Ltr559::set_als_window_mode()`](crate::Ltr559::set_als_window_mode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WindowMode {
+    /// Interrupt while the value is outside of the band (default)
+    #[default]
+    Outside,
+    /// Interrupt while the value is inside of the band
+    Inside,
+}
+
+/// Threshold windows programmed around the triggering values by
+/// [`Ltr559::acknowledge_and_rearm()`](crate::Ltr559::acknowledge_and_rearm)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Tracking {
+    /// Half-width of the ALS window in percent of CH0, at least 1 count
+    pub als_percent: u8,
+    /// Half-width of the PS window in counts
+    pub ps_delta: u16,
+}
+
+/// Average LED current limit in µA checked by
+/// [`Ltr559::set_ps_fast_mode()`](crate::Ltr559::set_ps_fast_mode)
+///
+/// At the 10 ms rate this allows e.g. 6 pulses at 60 kHz, 100% duty cycle
+/// and 100 mA.
+pub const PS_FAST_MODE_MAX_AVERAGE_CURRENT_UA: u32 = 1000;
+
+/// PS LED drive settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LedConfig {
+    /// LED pulse modulation frequency
+    pub pulse: LedPulse,
+    /// LED duty cycle
+    pub duty_cycle: LedDutyCycle,
+    /// LED peak current
+    pub current: LedCurrent,
+    /// Number of LED pulses per PS measurement (1 to 15)
+    pub n_pulses: u8,
+}
+
+impl Default for LedConfig {
+    /// Power-up values: one 60 kHz pulse at 100 mA and 100% duty cycle
+    fn default() -> Self {
+        LedConfig {
+            pulse: LedPulse::default(),
+            duty_cycle: LedDutyCycle::default(),
+            current: LedCurrent::default(),
+            n_pulses: 1,
+        }
+    }
+}
+
+impl LedConfig {
+    /// LED on-time per PS measurement in µs
+    pub fn on_time_us(&self) -> u32 {
+        u32::from(self.n_pulses) * self.duty_cycle.percent() * 10 / self.pulse.frequency_khz()
+    }
+
+    /// Average LED current in µA when measuring at `rate`
+    pub fn average_current_ua(&self, rate: PsMeasRate) -> u32 {
+        self.current.milliamps() * self.on_time_us() / rate.milliseconds()
+    }
+
+    /// Whether the average LED current at `rate` is at most
+    /// `max_average_current_ua`
+    pub fn is_within(&self, rate: PsMeasRate, max_average_current_ua: u32) -> bool {
+        self.average_current_ua(rate) <= max_average_current_ua
+    }
 }
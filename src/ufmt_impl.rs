@@ -0,0 +1,60 @@
+//! `ufmt` formatting for targets that cannot afford `core::fmt`
+use crate::{RawMeasurement, Status};
+use ufmt::{uDebug, uDisplay, uWrite, uwrite, Formatter};
+
+impl uDebug for Status {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        f.debug_struct("Status")?
+            .field("als_data_valid", &self.als_data_valid)?
+            .field("als_gain", &self.als_gain)?
+            .field("als_interrupt_status", &self.als_interrupt_status)?
+            .field("als_data_status", &self.als_data_status)?
+            .field("ps_interrupt_status", &self.ps_interrupt_status)?
+            .field("ps_data_status", &self.ps_data_status)?
+            .field("ps_saturated", &self.ps_saturated)?
+            .finish()
+    }
+}
+
+impl uDisplay for Status {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        uwrite!(
+            f,
+            "ALS: new={} valid={} int={} gain={}, PS: new={} int={} saturated={}",
+            self.als_data_status,
+            self.als_data_valid,
+            self.als_interrupt_status,
+            self.als_gain,
+            self.ps_data_status,
+            self.ps_interrupt_status,
+            self.ps_saturated
+        )
+    }
+}
+
+impl uDebug for RawMeasurement {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        f.debug_struct("RawMeasurement")?
+            .field("als_ch0", &self.als_ch0)?
+            .field("als_ch1", &self.als_ch1)?
+            .field("ps", &self.ps)?
+            .field("ps_saturated", &self.ps_saturated)?
+            .finish()
+    }
+}
+
+impl uDisplay for RawMeasurement {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        uwrite!(
+            f,
+            "CH0={} CH1={} PS={}",
+            self.als_ch0,
+            self.als_ch1,
+            self.ps
+        )?;
+        if self.ps_saturated {
+            f.write_str(" (saturated)")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Classification of the illuminance into named zones, e.g. for display
+//! brightness presets
+//!
+//! ```
+//! use ltr_559::{LuxZone, ZoneClassifier};
+//!
+//! let mut zones = ZoneClassifier::default();
+//! assert_eq!(zones.update(300.0).unwrap().current, LuxZone::Indoor);
+//! // 10% hysteresis around the 500 lux boundary
+//! assert_eq!(zones.update(520.0), None);
+//! assert_eq!(zones.update(560.0).unwrap().current, LuxZone::Bright);
+//! assert_eq!(zones.update(480.0), None);
+//! ```
+use crate::bands::{Band, Classifier, Transition};
+
+/// Named illuminance zone, from darkest to brightest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LuxZone {
+    /// Below the first boundary
+    Dark,
+    /// Between the first and the second boundary
+    Dim,
+    /// Between the second and the third boundary
+    Indoor,
+    /// Between the third and the fourth boundary
+    Bright,
+    /// Above the fourth boundary
+    Daylight,
+}
+
+impl Band for LuxZone {
+    const ALL: &'static [Self] = &[
+        LuxZone::Dark,
+        LuxZone::Dim,
+        LuxZone::Indoor,
+        LuxZone::Bright,
+        LuxZone::Daylight,
+    ];
+}
+
+/// A zone change reported by [`ZoneClassifier`]
+pub type ZoneTransition = Transition<LuxZone>;
+
+/// Maps lux values into [`LuxZone`]s with hysteresis at each boundary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneClassifier {
+    classifier: Classifier<f32, LuxZone, 4>,
+}
+
+impl Default for ZoneClassifier {
+    /// Boundaries at 10, 50, 500 and 10000 lux with 10% hysteresis
+    fn default() -> Self {
+        ZoneClassifier::new([10.0, 50.0, 500.0, 10_000.0], 0.1)
+    }
+}
+
+impl ZoneClassifier {
+    /// Create a new classifier.
+    ///
+    /// `boundaries` are the lux values between the five zones and are
+    /// sorted in ascending order. Once a zone is known, a boundary is only
+    /// crossed upwards above `boundary * (1 + hysteresis)` and downwards
+    /// below `boundary * (1 - hysteresis)`. A negative hysteresis is
+    /// treated as 0.
+    pub fn new(boundaries: [f32; 4], hysteresis: f32) -> Self {
+        ZoneClassifier {
+            classifier: Classifier::new(boundaries, hysteresis.max(0.0)),
+        }
+    }
+
+    /// Feed a new lux value.
+    ///
+    /// Returns the transition if the zone changed. The first value is
+    /// always reported.
+    pub fn update(&mut self, lux: f32) -> Option<ZoneTransition> {
+        self.classifier.update(lux)
+    }
+
+    /// Current zone, `None` before the first value
+    pub fn zone(&self) -> Option<LuxZone> {
+        self.classifier.band()
+    }
+
+    /// Lux boundaries between the zones, in ascending order
+    pub fn boundaries(&self) -> [f32; 4] {
+        self.classifier.boundaries()
+    }
+
+    /// Forget the current zone, so that the next value is reported
+    pub fn reset(&mut self) {
+        self.classifier.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_value_is_classified_without_hysteresis() {
+        let mut zones = ZoneClassifier::default();
+        let transition = zones.update(10.0).unwrap();
+        assert_eq!(transition.previous, None);
+        assert_eq!(transition.current, LuxZone::Dim);
+        zones.reset();
+        assert_eq!(zones.update(20_000.0).unwrap().current, LuxZone::Daylight);
+    }
+
+    #[test]
+    fn large_steps_cross_several_boundaries() {
+        let mut zones = ZoneClassifier::new([500.0, 10.0, 10_000.0, 50.0], 0.1);
+        assert_eq!(zones.boundaries(), [10.0, 50.0, 500.0, 10_000.0]);
+        zones.update(0.0);
+        let transition = zones.update(800.0).unwrap();
+        assert_eq!(transition.previous, Some(LuxZone::Dark));
+        assert_eq!(transition.current, LuxZone::Bright);
+        assert_eq!(zones.update(46.0).unwrap().current, LuxZone::Indoor);
+        assert_eq!(zones.update(44.0).unwrap().current, LuxZone::Dim);
+        assert_eq!(zones.zone(), Some(LuxZone::Dim));
+    }
+}